@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 use std::ops::DerefMut;
 use imgui::*;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use winit::{
     dpi::LogicalSize,
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
@@ -11,15 +11,23 @@ use winit::{
 use winit::event_loop::EventLoopWindowTarget;
 use imgui::docking::DockNodeFlags;
 use toolbelt::{SimpleCell, Defer, normalize_with_constant};
+use toolbelt::cgmath::Point2;
 use toolbelt::once::DoOnce;
 use binder::PropertyBinding;
-use crate::GLOBALS;
+use crate::{GLOBALS, Toggle};
+use crate::generators::{AoCurvature, MapTarget, MapsFromAlbedo, NormalFromHeight, PhotometricStereo, SilhouetteBevel};
 use crate::viewport::Viewport;
-use crate::palette::PaletteEditor;
-use crate::project::ProjectData;
+use crate::lights::{DitherPattern, Light, LightKind, ToonSettings, MAX_TOON_BANDS};
+use crate::material::{MaterialWorkflow, SpecularModel};
+use crate::palette::{ColorMetric, PaletteEditor, QuantizeDither};
+use crate::panel::{GeneratorAction, GeneratorPanel, Panel, PaletteExtractPanel, PaletteSwapPanel, RampEditorPanel, SpriteBrowserPanel, ERROR_COLOR};
+use crate::pipeline::sprite::MAX_LIGHTS;
+use crate::project::{ProjectData, SpriteEntry};
 use crate::recent::draw_recent_window;
 use crate::registry::TextureRegistry;
 use crate::scene::Scene;
+use crate::sprite::SceneData;
+use serde_derive::{Serialize, Deserialize};
 
 
 // yes i know this is terrible
@@ -34,6 +42,10 @@ pub fn IMGUI_CTX() -> &'static mut imgui::Context {
 }
 
 
+/// How long a successful save is shown in the menu bar. Failures stay until the next save.
+const SAVE_STATUS_DURATION: Duration = Duration::from_secs(4);
+
+
 const SURFACE_CONF: wgpu::SurfaceConfiguration  = wgpu::SurfaceConfiguration {
     usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
     format: wgpu::TextureFormat::Bgra8UnormSrgb,
@@ -44,19 +56,33 @@ const SURFACE_CONF: wgpu::SurfaceConfiguration  = wgpu::SurfaceConfiguration {
 
 
 #[repr(u32)]
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum MapType { Albedo = 0, Normal = 1, Roughness = 2, Height = 3, Rendered = 4 }
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MapType {
+    Albedo = 0,
+    Normal = 1,
+    /// Specular intensity or roughness, depending on the material's workflow
+    #[serde(alias = "Roughness")]
+    Specular = 2,
+    Height = 3,
+    Rendered = 4,
+    AO = 5,
+    Shadows = 6,
+    Emissive = 7,
+}
 impl MapType {
-    pub const TYPES: [MapType; 5] = [MapType::Albedo, MapType::Normal, MapType::Roughness, MapType::Height, MapType::Rendered];
+    pub const TYPES: [MapType; 8] = [MapType::Albedo, MapType::Normal, MapType::Specular, MapType::Height, MapType::AO, MapType::Emissive, MapType::Shadows, MapType::Rendered];
 }
 impl std::fmt::Display for MapType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
             MapType::Albedo => "Albedo",
             MapType::Normal => "Normal",
-            MapType::Roughness => "Roughness",
+            MapType::Specular => "Specular",
             MapType::Height => "Height",
             MapType::Rendered => "Rendered",
+            MapType::AO => "AO",
+            MapType::Shadows => "Shadows",
+            MapType::Emissive => "Emissive",
         })
     }
 }
@@ -64,6 +90,8 @@ impl std::fmt::Display for MapType {
 
 pub struct App {
     project: Option<ProjectData>,
+    /// Why the last attempt to open a project failed, shown in the startup window
+    project_error: Option<String>,
     demo_open: bool,
     window: Window,
     surface: wgpu::Surface,
@@ -75,7 +103,18 @@ pub struct App {
     last_frame: Instant,
     last_cursor: Option<Option<MouseCursor>>,
     palette: PaletteEditor,
+    sprite_browser: SpriteBrowserPanel,
+    ramp_editor: RampEditorPanel,
+    swap_editor: PaletteSwapPanel,
+    palette_extractor: PaletteExtractPanel,
+    normal_generator: GeneratorPanel<NormalFromHeight>,
+    bevel_generator: GeneratorPanel<SilhouetteBevel>,
+    ao_generator: GeneratorPanel<AoCurvature>,
+    albedo_generator: GeneratorPanel<MapsFromAlbedo>,
+    stereo_generator: GeneratorPanel<PhotometricStereo>,
     selected_viewport: Option<usize>,
+    /// Result of the last scene save and when it happened, shown in the menu bar
+    save_status: Option<(Result<String, String>, Instant)>,
 }
 
 
@@ -155,6 +194,7 @@ impl App {
 
         App {
             project: None,
+            project_error: None,
             demo_open: false,
             viewports: [None, None, None, None],
             scene: None,
@@ -164,13 +204,27 @@ impl App {
             last_frame: Instant::now(),
             last_cursor: None,
             palette: PaletteEditor::new(),
+            sprite_browser: SpriteBrowserPanel::new(),
+            ramp_editor: RampEditorPanel::new(),
+            swap_editor: PaletteSwapPanel::new(),
+            palette_extractor: PaletteExtractPanel::new(),
+            normal_generator: GeneratorPanel::new(NormalFromHeight::new()),
+            bevel_generator: GeneratorPanel::new(SilhouetteBevel::new()),
+            ao_generator: GeneratorPanel::new(AoCurvature::new()),
+            albedo_generator: GeneratorPanel::new(MapsFromAlbedo::new()),
+            stereo_generator: GeneratorPanel::new(PhotometricStereo::new()),
             selected_viewport: None,
+            save_status: None,
         }
     }
 
 
     fn open_project(&mut self, project: ProjectData) {
         self.project = Some(project.clone());
+        self.project_error = None;
+        self.window.set_title(&format!("pixelsmith - {}", project.manifest.name));
+
+        self.palette.open_project(&project);
 
         let ini_path = project.ini_path();
         if ini_path.exists() {
@@ -179,9 +233,188 @@ impl App {
             }
         }
         IMGUI_CTX().set_ini_filename(Some(ini_path));
+
+        self.refresh_sprites();
+        for idx in 0..self.sprite_browser.entries.len() {
+            if self.load_sprite(idx) {
+                break;
+            }
+        }
+    }
+
+
+    /// Rescans the project for sprites, keeping the active sprite selected if it's still there.
+    fn refresh_sprites(&mut self) {
+        let project = match self.project.as_ref() {
+            Some(project) => project,
+            None => return,
+        };
+        match project.find_sprites() {
+            Ok(entries) => {
+                self.sprite_browser.entries = entries;
+                self.sprite_browser.error = None;
+            }
+            Err(e) => {
+                self.sprite_browser.entries = Vec::new();
+                self.sprite_browser.error = Some(e.to_string());
+            }
+        }
+        self.sprite_browser.active = None;
+        if let Some(scene) = self.scene.as_ref() {
+            let scene = scene.get();
+            if let Some(idx) = self.sprite_browser.entries.iter().position(|e| e.path == scene.path) {
+                self.sprite_browser.active = Some(idx);
+                self.sprite_browser.entries[idx].map_warnings = scene.load_problems.iter().map(|p| p.to_string()).collect();
+            }
+        }
+    }
+
+
+    /// Makes the sprite at `idx` in the sprite browser the active scene. The outgoing scene is
+    /// saved, its textures are released, and the viewports are opened, closed and rebound to match
+    /// the new scene's saved layout.
+    /// Returns false if the sprite couldn't be loaded, in which case the current scene is kept.
+    fn load_sprite(&mut self, idx: usize) -> bool {
+        let (path, data) = match self.sprite_browser.entries.get(idx) {
+            Some(SpriteEntry { path, data: Ok(data), .. }) => (path.clone(), data.clone()),
+            _ => return false,
+        };
+
+        let map_names = &self.project.as_ref().unwrap().manifest.maps;
+        let scene = match Scene::from_sprite_path(path, &data, map_names, &mut self.texture_registry) {
+            Ok(scene) => scene,
+            Err(e) => {
+                let entry = &mut self.sprite_browser.entries[idx];
+                entry.map_error = Some(e.to_string());
+                entry.map_warnings.clear();
+                return false;
+            }
+        };
+        {
+            let entry = &mut self.sprite_browser.entries[idx];
+            entry.map_error = None;
+            entry.map_warnings = scene.get().load_problems.iter().map(|p| p.to_string()).collect();
+        }
+
+        self.save_scene();
+        let old_scene = self.scene.replace(scene);
+
+        if let Some(old_scene) = old_scene {
+            old_scene.get().release(&mut self.texture_registry);
+        }
+        // viewports are opened and closed to match the new sprite's layout, so saving it again
+        // doesn't overwrite its view state with the old sprite's
+        for (i, open) in data.viewports_open.iter().enumerate() {
+            if !*open {
+                self.close_viewport(i);
+                continue;
+            }
+            match self.viewports[i].as_mut() {
+                Some(vp) => vp.set_scene(self.scene.as_ref().unwrap()),
+                None => self.create_viewport(i),
+            }
+            if let (Some(vp), Some(vp_data)) = (self.viewports[i].as_mut(), data.viewports[i].as_ref()) {
+                vp.apply_data(vp_data);
+            }
+        }
+
+        self.ramp_editor.ramp = Some(self.scene.as_ref().unwrap().get().material.ramp.clone());
+        self.swap_editor.swaps = Some(self.scene.as_ref().unwrap().get().swaps.clone());
+        self.palette_extractor.set_image(&self.scene.as_ref().unwrap().get().textures.images.albedo);
+        // guessed specular maps default to what the new sprite's material expects
+        let workflow = self.scene.as_ref().unwrap().get().material.workflow;
+        self.albedo_generator.generator.roughness = workflow == MaterialWorkflow::Roughness;
+        self.set_generator_sprite();
+        self.sprite_browser.active = Some(idx);
+        true
     }
 
 
+    /// Points every map generator at the current sprite.
+    fn set_generator_sprite(&mut self) {
+        let (dir, images) = match &self.scene {
+            Some(scene) => {
+                let scene = scene.get();
+                (scene.path.clone(), scene.textures.images.clone())
+            }
+            None => return,
+        };
+        self.normal_generator.set_sprite(&dir, &images);
+        self.bevel_generator.set_sprite(&dir, &images);
+        self.ao_generator.set_sprite(&dir, &images);
+        self.albedo_generator.set_sprite(&dir, &images);
+        self.stereo_generator.set_sprite(&dir, &images);
+    }
+
+    /// Carries out a map generator's action on the current scene. Returns the outcome of saving
+    /// the maps for the generator to show, if any were saved.
+    fn apply_generator_action(&mut self, action: GeneratorAction) -> Option<Result<String, String>> {
+        let scene = self.scene.as_ref()?;
+        match action {
+            GeneratorAction::Preview(maps) => {
+                let scene = scene.get();
+                for map in maps.iter() {
+                    if let Some(kind) = map.preview_kind() {
+                        scene.textures.upload(kind, &map.image, &self.texture_registry);
+                    }
+                }
+                None
+            }
+            GeneratorAction::Restore(kinds) => {
+                let scene = scene.get();
+                for kind in kinds {
+                    scene.textures.upload(kind, scene.textures.images.get(kind), &self.texture_registry);
+                }
+                None
+            }
+            GeneratorAction::Accept(maps) => {
+                let map_names = &self.project.as_ref().unwrap().manifest.maps;
+                let mut scene = scene.get_mut();
+                let mut saved = Vec::new();
+                let mut result = Ok(());
+                for map in maps {
+                    let file_name = match map.target {
+                        MapTarget::Map(kind) => kind.file_name(map_names),
+                        MapTarget::File { name, .. } => name,
+                    };
+                    let path = scene.path.join(file_name);
+                    if result.is_ok() {
+                        if let Err(e) = map.image.save(&path) {
+                            result = Err(format!("Failed to save {}: {}", path.display(), e));
+                        }
+                    }
+                    if result.is_ok() {
+                        saved.push(file_name.to_string());
+                    }
+                    if let MapTarget::Map(kind) = map.target {
+                        if result.is_ok() {
+                            scene.textures.upload(kind, &map.image, &self.texture_registry);
+                            scene.textures.images.set(kind, map.image);
+                            // the map exists now, so whatever was wrong with it is fixed
+                            scene.load_problems.retain(|problem| problem.map != kind);
+                        } else {
+                            // put the sprite's own map back rather than leave an unsaved preview showing
+                            scene.textures.upload(kind, scene.textures.images.get(kind), &self.texture_registry);
+                        }
+                    }
+                }
+
+                if let Some(entry) = self.sprite_browser.active.and_then(|idx| self.sprite_browser.entries.get_mut(idx)) {
+                    entry.map_warnings = scene.load_problems.iter().map(|p| p.to_string()).collect();
+                }
+                let images = scene.textures.images.clone();
+                drop(scene);
+                self.normal_generator.set_images(&images);
+                self.bevel_generator.set_images(&images);
+                self.ao_generator.set_images(&images);
+                self.albedo_generator.set_images(&images);
+                self.stereo_generator.set_images(&images);
+
+                Some(result.map(|_| format!("Saved {}", saved.join(", "))))
+            }
+        }
+    }
+
     fn select_viewport(&mut self, num: usize) {
         self.selected_viewport = Some(num);
     }
@@ -204,13 +437,44 @@ impl App {
         self.select_viewport(num);
     }
 
+    /// Collects the current scene and viewport state into a `SceneData` for saving.
+    fn scene_data(&self) -> Option<SceneData> {
+        let scene = self.scene.as_ref()?;
+        let lighting = scene.get().lighting.clone();
+        let material = scene.get().material.clone();
+        let swaps = scene.get().swaps.clone();
+        let mut viewports_open = [false; 4];
+        let mut viewports = [None, None, None, None];
+        for (i, vp) in self.viewports.iter().enumerate() {
+            if let Some(vp) = vp {
+                viewports_open[i] = true;
+                viewports[i] = Some(vp.data());
+            }
+        }
+        Some(SceneData { viewports_open, viewports, lighting, material, swaps })
+    }
+
+    /// Saves the current scene to its `scene.yaml`. Does nothing if no scene is loaded.
+    fn save_scene(&mut self) {
+        if let Some(data) = self.scene_data() {
+            let path = self.scene.as_ref().unwrap().get().scene_file_path();
+            let status = match data.save(&path) {
+                Ok(()) => Ok(format!("Saved {}", path.display())),
+                Err(e) => Err(format!("Failed to save {}: {}", path.display(), e)),
+            };
+            self.save_status = Some((status, Instant::now()));
+            // the browser's copy is what the sprite is reloaded from when it's switched back to
+            let scene_path = &self.scene.as_ref().unwrap().get().path;
+            if let Some(entry) = self.sprite_browser.entries.iter_mut().find(|e| &e.path == scene_path) {
+                entry.data = Ok(data);
+            }
+        }
+    }
+
     fn get_viewport(&self, num: usize) -> Option<&Viewport> {
         self.viewports[num].as_ref()
     }
 
-    fn get_viewport_mut(&mut self, num: usize) -> Option<&mut Viewport> {
-        self.viewports[num].as_mut()
-    }
 
     fn main_loop(&mut self, event: Event<()>, _: &EventLoopWindowTarget<()>, control_flow: &mut ControlFlow) {
         let device = &GLOBALS.get().device;
@@ -240,8 +504,17 @@ impl App {
                 event: WindowEvent::CloseRequested,
                 ..
             } => {
+                self.save_scene();
                 *control_flow = ControlFlow::Exit;
             }
+            Event::WindowEvent { event: WindowEvent::KeyboardInput {
+                input: KeyboardInput {
+                    virtual_keycode: Some(VirtualKeyCode::S),
+                    state: ElementState::Pressed,
+                    .. },
+                .. }, .. } if imgui_ctx.io().key_ctrl => {
+                self.save_scene();
+            }
             Event::MainEventsCleared => self.window.request_redraw(),
             Event::RedrawEventsCleared => {
                 let delta_s = self.last_frame.elapsed();
@@ -265,18 +538,11 @@ impl App {
 
                     if self.project.is_none() {
                         let size = self.window.inner_size();
-                        if let Some((name, path)) = draw_recent_window(ui, [size.width as f32, size.height as f32]) {
+                        if let Some((name, path)) = draw_recent_window(ui, [size.width as f32, size.height as f32], self.project_error.as_deref()) {
                             println!("selected {} {}", name, path);
-                            self.open_project(ProjectData { path: path.into() });
-                        }
-                    } else if self.scene.is_none() {
-                        let (path, data) = self.project.as_ref().unwrap().find_sprites().into_iter().next().unwrap();
-                        let scene = Scene::from_sprite_path(path, &mut self.texture_registry);
-                        self.scene = Some(scene);
-                        for (i, open) in data.viewports_open.iter().enumerate() {
-                            self.close_viewport(i);
-                            if *open {
-                                self.create_viewport(i);
+                            match ProjectData::open(path.into()) {
+                                Ok(project) => self.open_project(project),
+                                Err(e) => self.project_error = Some(e.to_string()),
                             }
                         }
                     } else {
@@ -284,6 +550,12 @@ impl App {
 
                         ui.main_menu_bar(|| {
                             if let Some(inner) = ui.begin_menu("File") {
+                                if ui.menu_item_config("Save Scene")
+                                    .shortcut("Ctrl+S")
+                                    .build()
+                                {
+                                    self.save_scene();
+                                }
                                 if ui.menu_item_config("Show Demo Window")
                                     .selected(self.demo_open)
                                     .build()
@@ -291,6 +563,7 @@ impl App {
                                     self.demo_open = !self.demo_open;
                                 }
                                 if ui.menu_item("Quit") {
+                                    self.save_scene();
                                     *control_flow = ControlFlow::Exit;
                                     return;
                                 }
@@ -300,6 +573,7 @@ impl App {
                                 for i in 0..4 {
                                     if ui.menu_item_config(format!("Viewport {}", i + 1))
                                         .selected(self.viewports[i].is_some())
+                                        .enabled(self.scene.is_some())
                                         .build()
                                     {
                                         if self.viewports[i].is_some() {
@@ -309,8 +583,77 @@ impl App {
                                         }
                                     }
                                 }
+                                if ui.menu_item_config("Sprites")
+                                    .selected(self.sprite_browser.is_open())
+                                    .build()
+                                {
+                                    self.sprite_browser.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("Lighting Ramp")
+                                    .selected(self.ramp_editor.is_open())
+                                    .build()
+                                {
+                                    self.ramp_editor.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("Palette Swaps")
+                                    .selected(self.swap_editor.is_open())
+                                    .build()
+                                {
+                                    self.swap_editor.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("Palette from Sprite")
+                                    .selected(self.palette_extractor.is_open())
+                                    .build()
+                                {
+                                    self.palette_extractor.open_ref().toggle();
+                                }
                                 inner.end();
                             }
+                            if let Some(inner) = ui.begin_menu("Generate") {
+                                if ui.menu_item_config("Normal from Height")
+                                    .selected(self.normal_generator.is_open())
+                                    .enabled(self.scene.is_some())
+                                    .build()
+                                {
+                                    self.normal_generator.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("Silhouette Bevel")
+                                    .selected(self.bevel_generator.is_open())
+                                    .enabled(self.scene.is_some())
+                                    .build()
+                                {
+                                    self.bevel_generator.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("AO and Curvature")
+                                    .selected(self.ao_generator.is_open())
+                                    .enabled(self.scene.is_some())
+                                    .build()
+                                {
+                                    self.ao_generator.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("Maps from Albedo")
+                                    .selected(self.albedo_generator.is_open())
+                                    .enabled(self.scene.is_some())
+                                    .build()
+                                {
+                                    self.albedo_generator.open_ref().toggle();
+                                }
+                                if ui.menu_item_config("Normals from Lighting Profiles")
+                                    .selected(self.stereo_generator.is_open())
+                                    .enabled(self.scene.is_some())
+                                    .build()
+                                {
+                                    self.stereo_generator.open_ref().toggle();
+                                }
+                                inner.end();
+                            }
+                            if let Some((status, time)) = &self.save_status {
+                                match status {
+                                    Err(message) => ui.text_colored(ERROR_COLOR, message),
+                                    Ok(message) if time.elapsed() < SAVE_STATUS_DURATION => ui.text_disabled(message),
+                                    Ok(_) => {}
+                                }
+                            }
                         });
 
                         for (i, vp) in self.viewports.iter_mut().enumerate() {
@@ -362,33 +705,35 @@ impl App {
                                         Some(num) => {
                                             ui.text("Light Intensity");
 
-                                            let vp = self.get_viewport_mut(num).unwrap();
-                                            ui.checkbox("Normalize", &mut vp.normalize_intensities);
-
-                                            let mut once = DoOnce::new(); // necessary to prevent simultaneous ripple-updates
-                                            if ui.slider("Ambient##intensity", 0.0, 1.0, &mut vp.global_ambient)
-                                                && vp.normalize_intensities
-                                            {
-                                                once.do_once(|| {
-                                                    (vp.global_ambient, vp.global_diffuse, vp.global_specular)
-                                                        = normalize_with_constant(vp.global_ambient, vp.global_diffuse, vp.global_specular);
-                                                });
-                                            }
-                                            if ui.slider("Diffuse##intensity", 0.0, 1.0, &mut vp.global_diffuse)
-                                                && vp.normalize_intensities
                                             {
-                                                once.do_once(|| {
-                                                    (vp.global_diffuse, vp.global_ambient, vp.global_specular)
-                                                        = normalize_with_constant(vp.global_diffuse, vp.global_ambient, vp.global_specular);
-                                                });
-                                            }
-                                            if ui.slider("Specular##intensity", 0.0, 1.0, &mut vp.global_specular)
-                                                && vp.normalize_intensities
-                                            {
-                                                once.do_once(|| {
-                                                    (vp.global_specular, vp.global_diffuse, vp.global_ambient)
-                                                        = normalize_with_constant(vp.global_specular, vp.global_diffuse, vp.global_ambient);
-                                                });
+                                                let lighting = &mut self.scene.as_ref().unwrap().get_mut().lighting;
+                                                ui.checkbox("Normalize", &mut lighting.normalize_intensities);
+
+                                                let mut once = DoOnce::new(); // necessary to prevent simultaneous ripple-updates
+                                                if ui.slider("Ambient##intensity", 0.0, 1.0, &mut lighting.global_ambient)
+                                                    && lighting.normalize_intensities
+                                                {
+                                                    once.do_once(|| {
+                                                        (lighting.global_ambient, lighting.global_diffuse, lighting.global_specular)
+                                                            = normalize_with_constant(lighting.global_ambient, lighting.global_diffuse, lighting.global_specular);
+                                                    });
+                                                }
+                                                if ui.slider("Diffuse##intensity", 0.0, 1.0, &mut lighting.global_diffuse)
+                                                    && lighting.normalize_intensities
+                                                {
+                                                    once.do_once(|| {
+                                                        (lighting.global_diffuse, lighting.global_ambient, lighting.global_specular)
+                                                            = normalize_with_constant(lighting.global_diffuse, lighting.global_ambient, lighting.global_specular);
+                                                    });
+                                                }
+                                                if ui.slider("Specular##intensity", 0.0, 1.0, &mut lighting.global_specular)
+                                                    && lighting.normalize_intensities
+                                                {
+                                                    once.do_once(|| {
+                                                        (lighting.global_specular, lighting.global_diffuse, lighting.global_ambient)
+                                                            = normalize_with_constant(lighting.global_specular, lighting.global_diffuse, lighting.global_ambient);
+                                                    });
+                                                }
                                             }
                                             ui.separator();
 
@@ -410,25 +755,144 @@ impl App {
                                                 }
                                             }
                                             {
-                                                let lighting = &mut self.scene.as_ref().unwrap().get_mut().lighting;
-                                                ui.slider_config("Height##light-gizmo", 10.0, 250.0)
-                                                    .flags(SliderFlags::LOGARITHMIC)
-                                                    .build(&mut lighting.lights[0].height);
-                                                ui.slider_config("Intensity##light-gizmo", 0.25, 5.0)
-                                                    .flags(SliderFlags::LOGARITHMIC)
-                                                    .display_format("%1.3f")
-                                                    .build(&mut lighting.lights[0].color.components_4_mut()[3]);
+                                                let scene = &mut *self.scene.as_ref().unwrap().get_mut();
                                                 ui.separator();
 
-                                                ui.checkbox("Use Light Falloff", &mut lighting.lights[0].enable_falloff);
-                                                if lighting.lights[0].enable_falloff {
-                                                    ui.slider_config("Exponent##light-falloff", 0.25, 4.0)
+                                                ui.text(format!("Lights ({}/{})", scene.lighting.lights.len(), MAX_LIGHTS));
+                                                for (i, light) in scene.lighting.lights.iter().enumerate() {
+                                                    let label = if light.name.is_empty() { format!("Light {}", i+1) } else { light.name.clone() };
+                                                    // scene files can hold more lights than the shader draws
+                                                    let drawn = i < MAX_LIGHTS;
+                                                    let color = (!drawn).then(|| ui.push_style_color(StyleColor::Text, ERROR_COLOR));
+                                                    let label = if drawn { label } else { format!("{} (not drawn)", label) };
+                                                    if ui.selectable_config(format!("{}##light-{}", label, i))
+                                                        .selected(scene.selected_light == Some(i))
+                                                        .build()
+                                                    {
+                                                        scene.selected_light = Some(i);
+                                                    }
+                                                    drop(color);
+                                                    if !drawn && ui.is_item_hovered() {
+                                                        ui.tooltip_text(format!("Only the first {} lights are drawn, remove some to draw this one", MAX_LIGHTS));
+                                                    }
+                                                }
+                                                {
+                                                    let _disabled = ui.begin_disabled(scene.lighting.lights.len() >= MAX_LIGHTS);
+                                                    if ui.button("Add##light") {
+                                                        let name = format!("Light {}", scene.lighting.lights.len() + 1);
+                                                        let position = Point2::new(scene.textures.size.0 as f32 / 2.0, scene.textures.size.1 as f32 / 2.0);
+                                                        scene.lighting.lights.push(Light::new(name, position));
+                                                        scene.selected_light = Some(scene.lighting.lights.len() - 1);
+                                                    }
+                                                }
+                                                ui.same_line();
+                                                {
+                                                    let _disabled = ui.begin_disabled(scene.selected_light.is_none());
+                                                    if ui.button("Remove##light") {
+                                                        if let Some(idx) = scene.selected_light {
+                                                            scene.lighting.lights.remove(idx);
+                                                            scene.selected_light = if scene.lighting.lights.is_empty() { None }
+                                                                                   else { Some(idx.min(scene.lighting.lights.len() - 1)) };
+                                                        }
+                                                    }
+                                                }
+                                                ui.separator();
+
+                                                if let Some(light) = scene.selected_light.and_then(|idx| scene.lighting.lights.get_mut(idx)) {
+                                                    ui.input_text("Name##light", &mut light.name).build();
+                                                    let mut kind_idx = light.kind.index();
+                                                    if ui.combo_simple_string("Type##light", &mut kind_idx, &LightKind::NAMES)
+                                                        && kind_idx != light.kind.index()
+                                                    {
+                                                        light.kind = LightKind::from_index(kind_idx);
+                                                    }
+                                                    let sky_label = if let LightKind::Hemisphere { .. } = light.kind { "Sky Color##light" } else { "Color##light" };
+                                                    ui.color_edit3(sky_label, light.color.components_3_mut());
+                                                    ui.slider_config("Intensity##light-gizmo", 0.25, 5.0)
                                                         .flags(SliderFlags::LOGARITHMIC)
-                                                        .build(&mut lighting.lights[0].falloff_exp);
+                                                        .display_format("%1.3f")
+                                                        .build(&mut light.color.components_4_mut()[3]);
+                                                    ui.slider("Diffuse##light", 0.0, 2.0, &mut light.diffuse);
+                                                    if !matches!(light.kind, LightKind::Hemisphere { .. }) {
+                                                        ui.slider("Specular##light", 0.0, 2.0, &mut light.specular);
+                                                    }
+
+                                                    match &mut light.kind {
+                                                        LightKind::Directional { angle, elevation } => {
+                                                            ui.slider("Angle##light-dir", 0.0, 360.0, angle);
+                                                            ui.slider("Elevation##light-dir", 0.0, 90.0, elevation);
+                                                        }
+                                                        LightKind::Spot { direction, cone_angle, softness } => {
+                                                            ui.slider("Direction##light-spot", 0.0, 360.0, direction);
+                                                            ui.slider("Cone Angle##light-spot", 1.0, 180.0, cone_angle);
+                                                            ui.slider("Softness##light-spot", 0.0, 1.0, softness);
+                                                        }
+                                                        LightKind::Hemisphere { ground } => {
+                                                            ui.color_edit3("Ground Color##light", ground.components_3_mut());
+                                                        }
+                                                        LightKind::Point => {}
+                                                    }
+
+                                                    // directional and hemisphere lights don't depend on position
+                                                    if let LightKind::Point | LightKind::Spot { .. } = light.kind {
+                                                        ui.slider_config("Height##light-gizmo", 10.0, 250.0)
+                                                            .flags(SliderFlags::LOGARITHMIC)
+                                                            .build(&mut light.height);
+                                                        ui.checkbox("Use Light Falloff", &mut light.enable_falloff);
+                                                        if light.enable_falloff {
+                                                            ui.slider_config("Range##light-falloff", 10.0, 1000.0)
+                                                                .flags(SliderFlags::LOGARITHMIC)
+                                                                .build(&mut light.range);
+                                                            ui.slider_config("Exponent##light-falloff", 0.25, 4.0)
+                                                                .flags(SliderFlags::LOGARITHMIC)
+                                                                .build(&mut light.falloff_exp);
+                                                        }
+                                                    }
+                                                }
+                                                else {
+                                                    ui.text_disabled("No light selected");
                                                 }
                                                 ui.separator();
 
+                                                let lighting = &mut scene.lighting;
                                                 ui.checkbox("Light Parallax", &mut lighting.enable_light_parallax);
+                                                ui.checkbox("AO Affects Diffuse", &mut lighting.ao_affects_diffuse);
+                                                ui.slider_config("Emissive##intensity", 0.0, 8.0)
+                                                    .flags(SliderFlags::LOGARITHMIC)
+                                                    .build(&mut lighting.emissive_intensity);
+                                                ui.separator();
+
+                                                let toon = &mut lighting.toon;
+                                                ui.checkbox("Toon Shading", &mut toon.enabled);
+                                                if toon.enabled {
+                                                    if ui.slider("Bands##toon", 2, MAX_TOON_BANDS, &mut toon.bands) {
+                                                        toon.thresholds = ToonSettings::even_thresholds(toon.bands);
+                                                    }
+                                                    for i in 0..toon.thresholds.len() {
+                                                        // keep thresholds in order so every band stays reachable
+                                                        let min = if i == 0 { 0.0 } else { toon.thresholds[i - 1] };
+                                                        let max = toon.thresholds.get(i + 1).copied().unwrap_or(1.0);
+                                                        ui.slider(format!("Threshold {}##toon", i + 1), min, max, &mut toon.thresholds[i]);
+                                                    }
+                                                    let mut dither_idx = DitherPattern::ALL.iter().position(|d| *d == toon.dither).unwrap();
+                                                    if ui.combo("Dither##toon", &mut dither_idx, &DitherPattern::ALL, |d| d.to_string().into()) {
+                                                        toon.dither = DitherPattern::ALL[dither_idx];
+                                                    }
+                                                    if toon.dither != DitherPattern::None {
+                                                        ui.slider("Dither Spread##toon", 0.0, 0.25, &mut toon.dither_spread);
+                                                    }
+                                                }
+                                                ui.separator();
+
+                                                let shadows = &mut lighting.shadows;
+                                                ui.checkbox("Shadows", &mut shadows.enabled);
+                                                if shadows.enabled {
+                                                    ui.slider("Steps##shadows", 4, 128, &mut shadows.steps);
+                                                    ui.slider_config("Height Scale##shadows", 1.0, 100.0)
+                                                        .flags(SliderFlags::LOGARITHMIC)
+                                                        .build(&mut shadows.height_scale);
+                                                    ui.slider("Softness##shadows", 0.0, 1.0, &mut shadows.softness);
+                                                }
                                             }
                                         }
                                         None => {
@@ -441,6 +905,77 @@ impl App {
                                     ui.spacing();
                                 }
 
+                                if ui.collapsing_header("Material", TreeNodeFlags::DEFAULT_OPEN) {
+                                    ui.spacing();
+
+                                    if let Some(scene) = self.scene.as_ref() {
+                                        let material = &mut scene.get_mut().material;
+                                        let mut workflow_idx = material.workflow as usize;
+                                        if ui.combo("Specular Map##material", &mut workflow_idx, &MaterialWorkflow::ALL,
+                                                    |w| w.to_string().into())
+                                        {
+                                            material.workflow = MaterialWorkflow::ALL[workflow_idx];
+                                        }
+                                        let mut model_idx = material.specular_model as usize;
+                                        if ui.combo("Specular Model##material", &mut model_idx, &SpecularModel::ALL,
+                                                    |m| m.to_string().into())
+                                        {
+                                            material.specular_model = SpecularModel::ALL[model_idx];
+                                        }
+                                        ui.slider_config("Specular Power##material", 1.0, 256.0)
+                                            .flags(SliderFlags::LOGARITHMIC)
+                                            .build(&mut material.spec_power);
+
+                                        ui.checkbox("Ramp Lighting##material", &mut material.ramp.enabled);
+                                        {
+                                            let _disabled = ui.begin_disabled(!material.ramp.enabled);
+                                            ui.slider("Ramp Strength##material", 0.0, 1.0, &mut material.ramp.strength);
+                                        }
+                                        if ui.button("Edit Ramp...##material") {
+                                            self.ramp_editor.set_open(true);
+                                        }
+                                    }
+                                    else {
+                                        ui.text("No sprite loaded");
+                                    }
+
+                                    ui.spacing();
+                                    ui.spacing();
+                                    ui.spacing();
+                                }
+
+                                if ui.collapsing_header("Palette", TreeNodeFlags::empty()) {
+                                    ui.spacing();
+
+                                    if let Some(num) = self.selected_viewport {
+                                        let quantize = &mut self.viewports[num].as_mut().unwrap().quantize;
+                                        ui.checkbox("Quantize to Palette", &mut quantize.enabled);
+                                        if ui.is_item_hovered() {
+                                            ui.tooltip_text("Snaps the lit sprite to the palette, rendering it at sprite resolution");
+                                        }
+                                        if quantize.enabled {
+                                            let mut metric_idx = quantize.metric as usize;
+                                            if ui.combo("Distance##quantize", &mut metric_idx, &ColorMetric::ALL, |m| m.to_string().into()) {
+                                                quantize.metric = ColorMetric::ALL[metric_idx];
+                                            }
+                                            let mut dither_idx = QuantizeDither::ALL.iter().position(|d| *d == quantize.dither).unwrap();
+                                            if ui.combo("Dither##quantize", &mut dither_idx, &QuantizeDither::ALL, |d| d.to_string().into()) {
+                                                quantize.dither = QuantizeDither::ALL[dither_idx];
+                                            }
+                                            if quantize.dither.bayer_size() != 0 {
+                                                ui.slider("Dither Spread##quantize", 0.0, 0.5, &mut quantize.dither_spread);
+                                            }
+                                        }
+                                    }
+                                    else {
+                                        ui.text("No viewport selected");
+                                    }
+
+                                    ui.spacing();
+                                    ui.spacing();
+                                    ui.spacing();
+                                }
+
                                 if ui.collapsing_header("General", TreeNodeFlags::empty()) {
                                     ui.spacing();
 
@@ -462,6 +997,65 @@ impl App {
 
                         self.palette.draw(&ui);
 
+                        self.sprite_browser.draw(ui);
+                        if self.sprite_browser.take_refresh_request() {
+                            self.refresh_sprites();
+                        }
+                        if let Some(idx) = self.sprite_browser.take_selection() {
+                            self.load_sprite(idx);
+                        }
+
+                        self.ramp_editor.draw(ui);
+                        if let (Some(stops), Some(scene)) = (self.ramp_editor.take_changes(), self.scene.as_ref()) {
+                            let mut scene = scene.get_mut();
+                            scene.material.ramp.stops = stops;
+                            scene.upload_ramp(&self.texture_registry);
+                        }
+
+                        self.swap_editor.palette_color = self.palette.colors.get(self.palette.selected_idx).copied();
+                        self.swap_editor.draw(ui);
+                        if let (Some(swaps), Some(scene)) = (self.swap_editor.take_changes(), self.scene.as_ref()) {
+                            let mut scene = scene.get_mut();
+                            scene.swaps = swaps;
+                            scene.upload_swaps(&self.texture_registry);
+                        }
+
+                        self.palette_extractor.draw(ui);
+                        if let Some(colors) = self.palette_extractor.take_palette() {
+                            self.palette.replace_colors(colors);
+                        }
+
+                        self.normal_generator.draw(ui);
+                        if let Some(action) = self.normal_generator.take_action() {
+                            if let Some(status) = self.apply_generator_action(action) {
+                                self.normal_generator.set_status(status);
+                            }
+                        }
+                        self.bevel_generator.draw(ui);
+                        if let Some(action) = self.bevel_generator.take_action() {
+                            if let Some(status) = self.apply_generator_action(action) {
+                                self.bevel_generator.set_status(status);
+                            }
+                        }
+                        self.ao_generator.draw(ui);
+                        if let Some(action) = self.ao_generator.take_action() {
+                            if let Some(status) = self.apply_generator_action(action) {
+                                self.ao_generator.set_status(status);
+                            }
+                        }
+                        self.albedo_generator.draw(ui);
+                        if let Some(action) = self.albedo_generator.take_action() {
+                            if let Some(status) = self.apply_generator_action(action) {
+                                self.albedo_generator.set_status(status);
+                            }
+                        }
+                        self.stereo_generator.draw(ui);
+                        if let Some(action) = self.stereo_generator.take_action() {
+                            if let Some(status) = self.apply_generator_action(action) {
+                                self.stereo_generator.set_status(status);
+                            }
+                        }
+
                         if self.demo_open {
                             ui.show_demo_window(&mut self.demo_open);
                         }
@@ -478,7 +1072,7 @@ impl App {
 
                 for vp in self.viewports.iter() {
                     if let Some(vp) = vp {
-                        vp.render(&mut encoder, &self.texture_registry);
+                        vp.render(&mut encoder, &self.texture_registry, &self.palette.colors);
                     }
                 }
 
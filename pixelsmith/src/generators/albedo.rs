@@ -0,0 +1,192 @@
+use imgui::SliderFlags;
+use image::RgbaImage;
+use crate::registry::MapImages;
+use crate::sprite::MapKind;
+use super::{luminance, EdgeMode, Field, GeneratedMap, MapGenerator};
+
+/// How far either side of a specular threshold the cutoff fades, so highlights don't end in jaggies
+const THRESHOLD_SOFTNESS: f32 = 0.05;
+
+
+/// Guesses a height map and a specular map from the albedo's brightness, for quick mock-ups.
+pub struct MapsFromAlbedo {
+    pub make_height: bool,
+    /// Luminance that maps to black and to white in the height map
+    pub levels: [f32; 2],
+    /// Midtone curve, above 1 lifts the midtones
+    pub gamma: f32,
+    /// Standard deviation in pixels of the blur applied to the height map
+    pub blur: f32,
+    /// Dark pixels are high rather than low
+    pub invert: bool,
+    pub edge: EdgeMode,
+
+    pub make_specular: bool,
+    /// Pixels at least this bright are shiny
+    pub specular_luminance: f32,
+    /// Pixels at most this saturated are shiny
+    pub specular_saturation: f32,
+    pub specular_intensity: f32,
+    /// Write roughness, where shiny is black, rather than specular intensity. Set from the
+    /// sprite's material workflow when it's loaded.
+    pub roughness: bool,
+}
+
+impl MapsFromAlbedo {
+    pub fn new() -> Self {
+        MapsFromAlbedo {
+            make_height: true,
+            levels: [0.0, 1.0],
+            gamma: 1.0,
+            blur: 1.0,
+            invert: false,
+            edge: EdgeMode::TransparentZero,
+            make_specular: true,
+            specular_luminance: 0.7,
+            specular_saturation: 0.3,
+            specular_intensity: 1.0,
+            roughness: true,
+        }
+    }
+
+    pub fn height(&self, albedo: &RgbaImage) -> Field {
+        let [black, white] = self.levels;
+        let range = (white - black).max(f32::EPSILON);
+        let mut height = Field {
+            width: albedo.width(),
+            height: albedo.height(),
+            values: albedo.pixels().map(|p| {
+                let level = ((luminance(*p) - black) / range).clamp(0.0, 1.0).powf(1.0 / self.gamma);
+                if self.invert { 1.0 - level } else { level }
+            }).collect(),
+        };
+        if self.edge == EdgeMode::TransparentZero {
+            height.mask_transparent(albedo);
+        }
+        let mut height = height.blur(self.blur, self.edge);
+        // blurring spreads height out past the silhouette, where it would only be wasted
+        if self.edge == EdgeMode::TransparentZero {
+            height.mask_transparent(albedo);
+        }
+        height
+    }
+
+    pub fn specular(&self, albedo: &RgbaImage) -> Field {
+        let values = albedo.pixels().map(|p| {
+            if p.0[3] == 0 {
+                return if self.roughness { 1.0 } else { 0.0 };
+            }
+            let max = p.0[0].max(p.0[1]).max(p.0[2]) as f32;
+            let min = p.0[0].min(p.0[1]).min(p.0[2]) as f32;
+            let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+            let bright = smoothstep(self.specular_luminance - THRESHOLD_SOFTNESS, self.specular_luminance + THRESHOLD_SOFTNESS, luminance(*p));
+            let dull = smoothstep(self.specular_saturation - THRESHOLD_SOFTNESS, self.specular_saturation + THRESHOLD_SOFTNESS, saturation);
+            let shine = self.specular_intensity * bright * (1.0 - dull);
+            if self.roughness { 1.0 - shine } else { shine }
+        }).collect();
+        Field { width: albedo.width(), height: albedo.height(), values }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl MapGenerator for MapsFromAlbedo {
+    const NAME: &'static str = "Maps from Albedo";
+    const INPUTS: &'static [MapKind] = &[MapKind::Albedo];
+
+    fn draw_settings(&mut self, ui: &imgui::Ui) -> bool {
+        let mut changed = ui.checkbox("Height##albedo-maps", &mut self.make_height);
+        {
+            let _disabled = ui.begin_disabled(!self.make_height);
+            if ui.slider_config("Levels##albedo-maps", 0.0, 1.0).display_format("%.2f").build_array(&mut self.levels) {
+                // keep the black point below the white point
+                if self.levels[0] > self.levels[1] {
+                    self.levels.swap(0, 1);
+                }
+                changed = true;
+            }
+            changed |= ui.slider_config("Gamma##albedo-maps", 0.1, 10.0)
+                .flags(SliderFlags::LOGARITHMIC)
+                .build(&mut self.gamma);
+            changed |= ui.slider("Blur##albedo-maps", 0.0, 8.0, &mut self.blur);
+            changed |= ui.checkbox("Invert##albedo-maps", &mut self.invert);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Makes dark pixels high, for sprites drawn with dark raised lines");
+            }
+            let mut edge_idx = EdgeMode::ALL.iter().position(|e| *e == self.edge).unwrap();
+            if ui.combo("Edges##albedo-maps", &mut edge_idx, &EdgeMode::ALL, |e| e.to_string().into()) {
+                self.edge = EdgeMode::ALL[edge_idx];
+                changed = true;
+            }
+        }
+
+        ui.separator();
+        changed |= ui.checkbox("Specular##albedo-maps", &mut self.make_specular);
+        {
+            let _disabled = ui.begin_disabled(!self.make_specular);
+            changed |= ui.slider("Min Luminance##albedo-maps", 0.0, 1.0, &mut self.specular_luminance);
+            changed |= ui.slider("Max Saturation##albedo-maps", 0.0, 1.0, &mut self.specular_saturation);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Shiny things tend to be bright and washed out by their highlights");
+            }
+            changed |= ui.slider("Intensity##albedo-maps", 0.0, 1.0, &mut self.specular_intensity);
+            changed |= ui.checkbox("Output as Roughness##albedo-maps", &mut self.roughness);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Writes shiny pixels as black, for materials using the roughness workflow");
+            }
+        }
+        changed
+    }
+
+    fn generate(&self, images: &MapImages) -> Result<Vec<GeneratedMap>, String> {
+        let mut maps = Vec::new();
+        if self.make_height {
+            maps.push(GeneratedMap::new(MapKind::Height, self.height(&images.albedo).to_image()));
+        }
+        if self.make_specular {
+            maps.push(GeneratedMap::new(MapKind::Specular, self.specular(&images.albedo).to_image()));
+        }
+        if maps.is_empty() {
+            return Err("Choose at least one map to generate".to_string());
+        }
+        Ok(maps)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use super::*;
+
+    fn swatches() -> RgbaImage {
+        // white, saturated red, mid gray and transparent
+        RgbaImage::from_fn(4, 1, |x, _| Rgba([[255, 255, 255, 255], [255, 0, 0, 255], [128, 128, 128, 255], [255, 255, 255, 0]][x as usize]))
+    }
+
+    #[test]
+    fn specular_picks_bright_unsaturated() {
+        let generator = MapsFromAlbedo { roughness: false, ..MapsFromAlbedo::new() };
+        assert_eq!(generator.specular(&swatches()).values, vec![1.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn roughness_is_inverted_specular() {
+        let generator = MapsFromAlbedo { roughness: true, ..MapsFromAlbedo::new() };
+        assert_eq!(generator.specular(&swatches()).values, vec![0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn height_follows_luminance() {
+        let generator = MapsFromAlbedo { blur: 0.0, ..MapsFromAlbedo::new() };
+        let height = generator.height(&swatches());
+        assert_eq!(height.values[0], 1.0);
+        assert!((height.values[2] - 128.0 / 255.0).abs() < 1e-5);
+        assert_eq!(height.values[3], 0.0);
+        let inverted = MapsFromAlbedo { invert: true, ..generator }.height(&swatches());
+        assert_eq!(inverted.values[0], 0.0);
+    }
+}
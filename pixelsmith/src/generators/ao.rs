@@ -0,0 +1,200 @@
+use imgui::SliderFlags;
+use image::{Rgba, RgbaImage};
+use crate::registry::MapImages;
+use crate::sprite::MapKind;
+use super::{EdgeMode, Field, GeneratedMap, MapGenerator, MapTarget, NormalFormat};
+
+/// Curvature isn't one of the sprite's maps, so it has a fixed name rather than one from the manifest
+const CURVATURE_FILE_NAME: &str = "curvature.png";
+
+
+/// Bakes ambient occlusion from the height map, and optionally a curvature map from the normal map.
+pub struct AoCurvature {
+    /// Directions searched around each pixel for higher ground
+    pub rays: u32,
+    /// Pixels searched along each ray
+    pub radius: f32,
+    /// Height in pixels of a full white height map value
+    pub depth: f32,
+    pub strength: f32,
+    pub edge: EdgeMode,
+    /// Also save `curvature.png`
+    pub curvature: bool,
+    pub curvature_strength: f32,
+    /// Preview the curvature map in place of the AO map
+    pub show_curvature: bool,
+}
+
+impl AoCurvature {
+    pub fn new() -> Self {
+        AoCurvature {
+            rays: 8,
+            radius: 8.0,
+            depth: 8.0,
+            strength: 1.0,
+            edge: EdgeMode::Clamp,
+            curvature: false,
+            curvature_strength: 1.0,
+            show_curvature: false,
+        }
+    }
+
+    /// Horizon-based occlusion: each ray finds the steepest rise it can see, nearer rises
+    /// counting for more, and the pixel is darkened by the average over all the rays.
+    pub fn ambient_occlusion(&self, height: &Field) -> Field {
+        let steps = self.radius.ceil() as u32;
+        let directions: Vec<(f32, f32)> = (0..self.rays)
+            .map(|i| {
+                let angle = (i as f32 + 0.5) / self.rays as f32 * std::f32::consts::TAU;
+                (angle.cos(), angle.sin())
+            })
+            .collect();
+
+        let mut values = Vec::with_capacity(height.values.len());
+        for y in 0..height.height {
+            for x in 0..height.width {
+                let origin = height.get(x, y) * self.depth;
+                let mut occlusion = 0.0;
+                for (dx, dy) in directions.iter() {
+                    let mut horizon: f32 = 0.0;
+                    for step in 1..=steps {
+                        let distance = (step as f32).min(self.radius);
+                        let sx = x as i32 + (dx * distance).round() as i32;
+                        let sy = y as i32 + (dy * distance).round() as i32;
+                        let rise = height.sample(sx, sy, self.edge) * self.depth - origin;
+                        if rise > 0.0 {
+                            let sine = rise / (rise * rise + distance * distance).sqrt();
+                            let falloff = 1.0 - (distance / (self.radius + 1.0)).powi(2);
+                            horizon = horizon.max(sine * falloff);
+                        }
+                    }
+                    occlusion += horizon;
+                }
+                values.push((1.0 - self.strength * occlusion / self.rays as f32).clamp(0.0, 1.0));
+            }
+        }
+        Field { width: height.width, height: height.height, values }
+    }
+
+    /// How much the normal map bends at each pixel, from 0 for concave to 1 for convex.
+    pub fn curvature(&self, normal: &RgbaImage, albedo: &RgbaImage) -> Field {
+        let normals: Vec<[f32; 3]> = normal.pixels().map(|p| NormalFormat::YUp.decode(*p)).collect();
+        let component = |i: usize| {
+            let mut field = Field {
+                width: normal.width(),
+                height: normal.height(),
+                values: normals.iter().map(|n| n[i]).collect(),
+            };
+            // transparent pixels read as facing the viewer, so the silhouette's edge counts as a bend
+            if self.edge == EdgeMode::TransparentZero {
+                field.mask_transparent(albedo);
+            }
+            field
+        };
+        let (nx, ny) = (component(0), component(1));
+
+        let mut values = Vec::with_capacity(normals.len());
+        for y in 0..normal.height() as i32 {
+            for x in 0..normal.width() as i32 {
+                // normals spread apart over convex surfaces and come together over concave ones
+                let divergence = (nx.sample(x + 1, y, self.edge) - nx.sample(x - 1, y, self.edge)
+                    + ny.sample(x, y + 1, self.edge) - ny.sample(x, y - 1, self.edge)) * 0.5;
+                values.push((0.5 + 0.5 * self.curvature_strength * divergence).clamp(0.0, 1.0));
+            }
+        }
+        Field { width: normal.width(), height: normal.height(), values }
+    }
+}
+
+/// Fills every pixel that's transparent in `albedo` with `pixel`.
+fn fill_transparent(image: &mut RgbaImage, albedo: &RgbaImage, pixel: [u8; 4]) {
+    for (out, albedo) in image.pixels_mut().zip(albedo.pixels()) {
+        if albedo.0[3] == 0 {
+            *out = Rgba(pixel);
+        }
+    }
+}
+
+impl MapGenerator for AoCurvature {
+    const NAME: &'static str = "AO and Curvature";
+    const INPUTS: &'static [MapKind] = &[MapKind::Height, MapKind::Normal, MapKind::Albedo];
+
+    fn draw_settings(&mut self, ui: &imgui::Ui) -> bool {
+        let mut changed = ui.slider("Rays##ao", 1, 32, &mut self.rays);
+        changed |= ui.slider_config("Radius##ao", 1.0, 64.0)
+            .flags(SliderFlags::LOGARITHMIC)
+            .build(&mut self.radius);
+        changed |= ui.slider_config("Depth##ao", 0.5, 64.0)
+            .flags(SliderFlags::LOGARITHMIC)
+            .build(&mut self.depth);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Height in pixels of white in the height map");
+        }
+        changed |= ui.slider("Strength##ao", 0.0, 4.0, &mut self.strength);
+        let mut edge_idx = EdgeMode::ALL.iter().position(|e| *e == self.edge).unwrap();
+        if ui.combo("Edges##ao", &mut edge_idx, &EdgeMode::ALL, |e| e.to_string().into()) {
+            self.edge = EdgeMode::ALL[edge_idx];
+            changed = true;
+        }
+
+        ui.separator();
+        changed |= ui.checkbox("Curvature##ao", &mut self.curvature);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(format!("Also save {} from the normal map, bright where it's convex and dark where it's concave", CURVATURE_FILE_NAME));
+        }
+        {
+            let _disabled = ui.begin_disabled(!self.curvature);
+            changed |= ui.slider_config("Curvature Strength##ao", 0.1, 16.0)
+                .flags(SliderFlags::LOGARITHMIC)
+                .build(&mut self.curvature_strength);
+            changed |= ui.checkbox("Show in AO View##ao", &mut self.show_curvature);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Previews the curvature map in place of the AO map, set a viewport to View: AO to see it");
+            }
+        }
+        changed
+    }
+
+    fn generate(&self, images: &MapImages) -> Result<Vec<GeneratedMap>, String> {
+        let mut height = Field::from_red(&images.height);
+        if self.edge == EdgeMode::TransparentZero {
+            height.mask_transparent(&images.albedo);
+        }
+        let mut ao = self.ambient_occlusion(&height).to_image();
+        fill_transparent(&mut ao, &images.albedo, MapKind::Ao.neutral_pixel().unwrap());
+        let mut maps = vec![GeneratedMap::new(MapKind::Ao, ao)];
+
+        if self.curvature {
+            let mut curvature = self.curvature(&images.normal, &images.albedo).to_image();
+            fill_transparent(&mut curvature, &images.albedo, [128, 128, 128, 255]);
+            let preview_in = if self.show_curvature { Some(MapKind::Ao) } else { None };
+            // after the AO map, so its preview is the one left showing
+            maps.push(GeneratedMap { target: MapTarget::File { name: CURVATURE_FILE_NAME, preview_in }, image: curvature });
+        }
+        Ok(maps)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_height_is_unoccluded() {
+        let height = Field { width: 6, height: 6, values: vec![0.5; 36] };
+        for edge in EdgeMode::ALL {
+            let ao = AoCurvature { edge, ..AoCurvature::new() }.ambient_occlusion(&height);
+            assert!(ao.values.iter().all(|v| *v == 1.0), "{:?}", edge);
+        }
+    }
+
+    #[test]
+    fn pit_is_occluded() {
+        let mut height = Field { width: 5, height: 5, values: vec![1.0; 25] };
+        height.values[12] = 0.0;
+        let ao = AoCurvature::new().ambient_occlusion(&height);
+        assert!(ao.get(2, 2) < 0.5);
+        assert_eq!(ao.get(0, 0), 1.0);
+    }
+}
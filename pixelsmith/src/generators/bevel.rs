@@ -0,0 +1,345 @@
+use imgui::SliderFlags;
+use image::RgbaImage;
+use toolbelt::curve::FloatCurve;
+use crate::registry::MapImages;
+use crate::sprite::MapKind;
+use super::{luminance, EdgeMode, Field, GeneratedMap, MapGenerator, NormalFormat, NormalFromHeight};
+use super::normal::GradientKernel;
+
+/// Samples drawn of the profile curve for its preview
+const PROFILE_PREVIEW_SAMPLES: usize = 32;
+/// Pixels over which an inner edge's groove fades out
+const EMBOSS_FALLOFF: f32 = 1.5;
+
+
+/// Shape of the bevel from the silhouette's edge (0) to its full height (1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BevelProfile {
+    Linear,
+    /// A quarter circle, steep at the edge and flat on top
+    Round,
+    /// The custom curve in `SilhouetteBevel::curve_points`
+    Curve,
+}
+
+impl BevelProfile {
+    pub const ALL: [BevelProfile; 3] = [BevelProfile::Linear, BevelProfile::Round, BevelProfile::Curve];
+}
+
+impl std::fmt::Display for BevelProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            BevelProfile::Linear => "Linear",
+            BevelProfile::Round => "Round",
+            BevelProfile::Curve => "Curve",
+        })
+    }
+}
+
+
+/// Puffs the albedo's silhouette up into a "pillow" height map, and bakes normals from it.
+pub struct SilhouetteBevel {
+    /// Pixels from the silhouette's edge to where the bevel reaches full height
+    pub width: f32,
+    pub profile: BevelProfile,
+    /// Time and value of each point of the `Curve` profile, sorted by time
+    pub curve_points: Vec<[f32; 2]>,
+    /// How deep grooves are cut along sharp changes in the albedo's brightness, from 0 to 1
+    pub emboss: f32,
+    /// Brightness difference between neighboring pixels that counts as an inner edge
+    pub emboss_threshold: f32,
+    pub normal_strength: f32,
+    pub format: NormalFormat,
+}
+
+impl SilhouetteBevel {
+    pub fn new() -> Self {
+        SilhouetteBevel {
+            width: 4.0,
+            profile: BevelProfile::Round,
+            curve_points: vec![[0.0, 0.0], [0.4, 0.8], [1.0, 1.0]],
+            emboss: 0.0,
+            emboss_threshold: 0.25,
+            normal_strength: 4.0,
+            format: NormalFormat::YUp,
+        }
+    }
+
+    fn curve(&self) -> FloatCurve {
+        let mut curve = FloatCurve::new();
+        let mut last_time = None;
+        // points are added in order, so each one's neighbors get their tangents set too. the
+        // curve can't hold two points at the same time, so later ones are dropped.
+        for [time, value] in self.curve_points.iter() {
+            if last_time.is_none_or(|last: f32| *time - last > f32::EPSILON) {
+                curve.add_point_auto(*time, *value);
+                last_time = Some(*time);
+            }
+        }
+        curve
+    }
+
+    /// Height at `t`, from 0 at the silhouette's edge to 1 at the bevel's full width.
+    fn profile_height(&self, t: f32, curve: &FloatCurve) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self.profile {
+            BevelProfile::Linear => t,
+            BevelProfile::Round => (1.0 - (1.0 - t) * (1.0 - t)).sqrt(),
+            BevelProfile::Curve => curve.get_value(t).clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn height(&self, albedo: &RgbaImage) -> Field {
+        let (width, height) = albedo.dimensions();
+        let opaque: Vec<bool> = albedo.pixels().map(|p| p.0[3] != 0).collect();
+        let transparent: Vec<bool> = opaque.iter().map(|o| !o).collect();
+        let edge_distance = distance_field(&transparent, width, height, true);
+
+        let groove_distance = if self.emboss > 0.0 {
+            let luminance: Vec<f32> = albedo.pixels().map(|p| luminance(*p)).collect();
+            // the darker side of each sharp change in brightness is the groove
+            let inner_edges: Vec<bool> = (0..width * height).map(|i| {
+                let (x, y) = (i % width, i / width);
+                let i = i as usize;
+                opaque[i] && [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(dx, dy)| {
+                    let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+                    if nx < 0 || ny < 0 || nx >= width as i32 || ny >= height as i32 {
+                        return false;
+                    }
+                    let n = (ny as u32 * width + nx as u32) as usize;
+                    opaque[n] && luminance[n] - luminance[i] > self.emboss_threshold
+                })
+            }).collect();
+            Some(distance_field(&inner_edges, width, height, false))
+        } else {
+            None
+        };
+
+        let curve = self.curve();
+        let values = (0..opaque.len()).map(|i| {
+            if !opaque[i] {
+                return 0.0;
+            }
+            // distances are between pixel centers, so the outermost pixels sit half a pixel in
+            let mut h = self.profile_height((edge_distance.values[i] - 0.5) / self.width, &curve);
+            if let Some(grooves) = &groove_distance {
+                h -= self.emboss * (1.0 - grooves.values[i] / EMBOSS_FALLOFF).max(0.0);
+            }
+            h.clamp(0.0, 1.0)
+        }).collect();
+        Field { width, height, values }
+    }
+}
+
+impl MapGenerator for SilhouetteBevel {
+    const NAME: &'static str = "Silhouette Bevel";
+    const INPUTS: &'static [MapKind] = &[MapKind::Albedo];
+
+    fn draw_settings(&mut self, ui: &imgui::Ui) -> bool {
+        let mut changed = ui.slider_config("Bevel Width##bevel", 1.0, 64.0)
+            .flags(SliderFlags::LOGARITHMIC)
+            .build(&mut self.width);
+        let mut profile_idx = BevelProfile::ALL.iter().position(|p| *p == self.profile).unwrap();
+        if ui.combo("Profile##bevel", &mut profile_idx, &BevelProfile::ALL, |p| p.to_string().into()) {
+            self.profile = BevelProfile::ALL[profile_idx];
+            changed = true;
+        }
+
+        if self.profile == BevelProfile::Curve {
+            // the curve needs two points to have a shape
+            let removable = self.curve_points.len() > 2;
+            let mut remove = None;
+            for (i, point) in self.curve_points.iter_mut().enumerate() {
+                let _id = ui.push_id(i.to_string());
+                if ui.slider_config("##curve-point", 0.0, 1.0).display_format("%.2f").build_array(point) {
+                    changed = true;
+                }
+                if removable {
+                    ui.same_line();
+                    if ui.button("x") {
+                        remove = Some(i);
+                    }
+                }
+            }
+            if let Some(i) = remove {
+                self.curve_points.remove(i);
+                changed = true;
+            }
+            if ui.button("Add Point##bevel") {
+                self.curve_points.push([0.5, 0.5]);
+                changed = true;
+            }
+            if changed {
+                self.curve_points.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap_or(std::cmp::Ordering::Equal));
+            }
+        }
+        let curve = self.curve();
+        let samples: Vec<f32> = (0..PROFILE_PREVIEW_SAMPLES)
+            .map(|i| self.profile_height(i as f32 / (PROFILE_PREVIEW_SAMPLES - 1) as f32, &curve))
+            .collect();
+        ui.plot_lines("##bevel-profile", &samples)
+            .scale_min(0.0)
+            .scale_max(1.0)
+            .graph_size([0.0, 48.0])
+            .build();
+
+        ui.separator();
+        changed |= ui.slider("Emboss##bevel", 0.0, 1.0, &mut self.emboss);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Cuts grooves along lines drawn inside the silhouette");
+        }
+        {
+            let _disabled = ui.begin_disabled(self.emboss <= 0.0);
+            changed |= ui.slider("Emboss Threshold##bevel", 0.01, 1.0, &mut self.emboss_threshold);
+        }
+        changed |= ui.slider_config("Normal Strength##bevel", 0.1, 64.0)
+            .flags(SliderFlags::LOGARITHMIC)
+            .build(&mut self.normal_strength);
+        let mut format_idx = NormalFormat::ALL.iter().position(|f| *f == self.format).unwrap();
+        if ui.combo("Output##bevel", &mut format_idx, &NormalFormat::ALL, |f| f.to_string().into()) {
+            self.format = NormalFormat::ALL[format_idx];
+            changed = true;
+        }
+        changed
+    }
+
+    fn generate(&self, images: &MapImages) -> Result<Vec<GeneratedMap>, String> {
+        if images.albedo.pixels().all(|p| p.0[3] != 0) {
+            return Err("The albedo has no transparent pixels, so there's no silhouette to bevel".to_string());
+        }
+        let height = self.height(&images.albedo);
+        let normals = NormalFromHeight {
+            kernel: GradientKernel::Sobel,
+            strength: self.normal_strength,
+            edge: EdgeMode::TransparentZero,
+            format: self.format,
+        }.normals(&height);
+        let normal = RgbaImage::from_fn(height.width, height.height, |x, y| {
+            if images.albedo.get_pixel(x, y).0[3] == 0 {
+                self.format.encode([0.0, 0.0, 1.0])
+            } else {
+                self.format.encode(normals[(y * height.width + x) as usize])
+            }
+        });
+        Ok(vec![
+            GeneratedMap::new(MapKind::Height, height.to_image()),
+            GeneratedMap::new(MapKind::Normal, normal),
+        ])
+    }
+}
+
+/// Euclidean distance from every pixel to the nearest pixel in `features`, using Felzenszwalb
+/// and Huttenlocher's separable transform. With `border_is_feature`, everything outside the
+/// image counts as a feature too.
+fn distance_field(features: &[bool], width: u32, height: u32, border_is_feature: bool) -> Field {
+    // far enough that sums stay finite, squared distances inside any image are far smaller
+    const FAR: f64 = 1e20;
+    let pad = if border_is_feature { 1 } else { 0 };
+    let (w, h) = ((width + 2 * pad) as usize, (height + 2 * pad) as usize);
+    let mut grid = vec![if border_is_feature { 0.0 } else { FAR }; w * h];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let feature = features[y * width as usize + x];
+            grid[(y + pad as usize) * w + x + pad as usize] = if feature { 0.0 } else { FAR };
+        }
+    }
+
+    let mut column = vec![0.0; h];
+    for x in 0..w {
+        for y in 0..h {
+            column[y] = grid[y * w + x];
+        }
+        let transformed = distance_1d(&column);
+        for y in 0..h {
+            grid[y * w + x] = transformed[y];
+        }
+    }
+    for y in 0..h {
+        let transformed = distance_1d(&grid[y * w..(y + 1) * w]);
+        grid[y * w..(y + 1) * w].copy_from_slice(&transformed);
+    }
+
+    let mut values = Vec::with_capacity((width * height) as usize);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            values.push(grid[(y + pad as usize) * w + x + pad as usize].sqrt() as f32);
+        }
+    }
+    Field { width, height, values }
+}
+
+/// Squared distance transform of one row: the lower envelope of parabolas rooted at each sample.
+fn distance_1d(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    let mut distances = vec![0.0; n];
+    // roots of the parabolas in the envelope, and where each one takes over from the last
+    let mut roots = vec![0usize; n];
+    let mut bounds = vec![0.0f64; n + 1];
+    let intersect = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2.0 * q as f64 - 2.0 * p as f64)
+    };
+
+    let mut k = 0;
+    bounds[0] = f64::NEG_INFINITY;
+    bounds[1] = f64::INFINITY;
+    for q in 1..n {
+        let mut s = intersect(q, roots[k]);
+        while s <= bounds[k] {
+            k -= 1;
+            s = intersect(q, roots[k]);
+        }
+        k += 1;
+        roots[k] = q;
+        bounds[k] = s;
+        bounds[k + 1] = f64::INFINITY;
+    }
+
+    k = 0;
+    for (q, distance) in distances.iter_mut().enumerate() {
+        while bounds[k + 1] < q as f64 {
+            k += 1;
+        }
+        let offset = q as f64 - roots[k] as f64;
+        *distance = offset * offset + f[roots[k]];
+    }
+    distances
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_inside_square() {
+        // a 5x5 opaque square with a one pixel transparent border
+        let transparent: Vec<bool> = (0..49).map(|i| i % 7 == 0 || i % 7 == 6 || i / 7 == 0 || i / 7 == 6).collect();
+        let distances = distance_field(&transparent, 7, 7, false);
+        for y in 0..7 {
+            for x in 0..7 {
+                let expected = x.min(6 - x).min(y).min(6 - y) as f32;
+                assert_eq!(distances.get(x, y), expected, "{}, {}", x, y);
+            }
+        }
+        // the same square filling its image, with the border outside it
+        let distances = distance_field(&[false; 25], 5, 5, true);
+        for y in 0..5 {
+            for x in 0..5 {
+                let expected = (x + 1).min(5 - x).min(y + 1).min(5 - y) as f32;
+                assert_eq!(distances.get(x, y), expected, "{}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_is_euclidean() {
+        let mut features = [false; 25];
+        features[0] = true;
+        let distances = distance_field(&features, 5, 5, false);
+        for y in 0..5 {
+            for x in 0..5 {
+                assert!((distances.get(x, y) - ((x * x + y * y) as f32).sqrt()).abs() < 1e-5, "{}, {}", x, y);
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::path::Path;
+use image::{Rgba, RgbaImage};
+use crate::registry::MapImages;
+use crate::sprite::MapKind;
+
+pub mod albedo;
+pub mod ao;
+pub mod bevel;
+pub mod normal;
+pub mod stereo;
+pub use albedo::MapsFromAlbedo;
+pub use ao::AoCurvature;
+pub use bevel::SilhouetteBevel;
+pub use normal::NormalFromHeight;
+pub use stereo::PhotometricStereo;
+
+
+/// Where a generated map is shown and saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapTarget {
+    /// One of the sprite's maps, which is previewed in the viewports
+    Map(MapKind),
+    /// Another file saved next to the sprite's maps, which pixelsmith doesn't read. It can be
+    /// previewed in place of one of the sprite's maps, but never replaces it.
+    File { name: &'static str, preview_in: Option<MapKind> },
+}
+
+/// A map made by a `MapGenerator`, shown in place of the sprite's own until it's accepted
+#[derive(Debug, Clone)]
+pub struct GeneratedMap {
+    pub target: MapTarget,
+    pub image: RgbaImage,
+}
+
+impl GeneratedMap {
+    pub fn new(kind: MapKind, image: RgbaImage) -> Self {
+        GeneratedMap { target: MapTarget::Map(kind), image }
+    }
+
+    /// The sprite map this is shown in place of while previewing, if any
+    pub fn preview_kind(&self) -> Option<MapKind> {
+        match self.target {
+            MapTarget::Map(kind) => Some(kind),
+            MapTarget::File { preview_in, .. } => preview_in,
+        }
+    }
+}
+
+/// Builds some of a sprite's maps out of its other maps.
+pub trait MapGenerator {
+    /// Title of the generator's window
+    const NAME: &'static str;
+    /// Maps `generate` reads, so it only runs again when one of them is replaced
+    const INPUTS: &'static [MapKind];
+
+    /// Draws the generator's settings. Returns true if any of them changed.
+    fn draw_settings(&mut self, ui: &imgui::Ui) -> bool;
+
+    /// Generates maps from the sprite's current ones.
+    fn generate(&self, images: &MapImages) -> Result<Vec<GeneratedMap>, String>;
+
+    /// Called when a sprite is loaded, with the folder its maps are in, for generators that read
+    /// other files from there.
+    fn set_sprite_dir(&mut self, _dir: &Path) {}
+}
+
+
+/// How pixels outside the image are read when filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeMode {
+    /// Repeat the nearest edge pixel
+    Clamp,
+    /// Read from the opposite edge, for tiles
+    Wrap,
+    /// Everything outside the image and every transparent pixel of the albedo reads as zero
+    TransparentZero,
+}
+
+impl EdgeMode {
+    pub const ALL: [EdgeMode; 3] = [EdgeMode::Clamp, EdgeMode::Wrap, EdgeMode::TransparentZero];
+}
+
+impl std::fmt::Display for EdgeMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            EdgeMode::Clamp => "Clamp",
+            EdgeMode::Wrap => "Wrap",
+            EdgeMode::TransparentZero => "Transparent as Zero",
+        })
+    }
+}
+
+/// Which way a normal map's green channel points. Pixelsmith reads normal maps as Y+.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalFormat { YUp, YDown }
+
+impl NormalFormat {
+    pub const ALL: [NormalFormat; 2] = [NormalFormat::YUp, NormalFormat::YDown];
+
+    /// Decodes a pixel of a normal map in this format to a unit normal, in image space with y
+    /// pointing down.
+    pub fn decode(self, pixel: Rgba<u8>) -> [f32; 3] {
+        let [x, y, z] = [0, 1, 2].map(|i| pixel.0[i] as f32 / 255.0 * 2.0 - 1.0);
+        let y = match self {
+            NormalFormat::YUp => -y,
+            NormalFormat::YDown => y,
+        };
+        normalize([x, y, z])
+    }
+
+    /// Encodes a unit normal, in image space with y pointing down, as a pixel.
+    pub fn encode(self, [x, y, z]: [f32; 3]) -> Rgba<u8> {
+        let y = match self {
+            NormalFormat::YUp => -y,
+            NormalFormat::YDown => y,
+        };
+        let channel = |c: f32| ((c * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgba([channel(x), channel(y), channel(z), 255])
+    }
+}
+
+impl std::fmt::Display for NormalFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            NormalFormat::YUp => "Y+ (OpenGL)",
+            NormalFormat::YDown => "Y- (DirectX)",
+        })
+    }
+}
+
+/// Normalizes `v`, or returns a normal facing the viewer if it has no length.
+pub fn normalize([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    if length > f32::EPSILON { [x / length, y / length, z / length] } else { [0.0, 0.0, 1.0] }
+}
+
+/// Relative luminance of a pixel's color, from 0 to 1. Works on the stored sRGB values, which
+/// is close enough for guessing at maps.
+pub fn luminance(pixel: Rgba<u8>) -> f32 {
+    (0.2126 * pixel.0[0] as f32 + 0.7152 * pixel.0[1] as f32 + 0.0722 * pixel.0[2] as f32) / 255.0
+}
+
+
+/// A single-channel image of floats
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<f32>,
+}
+
+impl Field {
+    /// The red channel of `image`, from 0 to 1.
+    pub fn from_red(image: &RgbaImage) -> Self {
+        Field {
+            width: image.width(),
+            height: image.height(),
+            values: image.pixels().map(|p| p.0[0] as f32 / 255.0).collect(),
+        }
+    }
+
+    /// Zeroes every value whose pixel in `albedo` is fully transparent.
+    pub fn mask_transparent(&mut self, albedo: &RgbaImage) {
+        for (value, pixel) in self.values.iter_mut().zip(albedo.pixels()) {
+            if pixel.0[3] == 0 {
+                *value = 0.0;
+            }
+        }
+    }
+
+    /// Grayscale image of the values, clamped to 0 to 1.
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let v = (self.get(x, y).clamp(0.0, 1.0) * 255.0).round() as u8;
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    /// Gaussian blur with a standard deviation of `radius` pixels, reading past the edges with `edge`.
+    pub fn blur(&self, radius: f32, edge: EdgeMode) -> Field {
+        if radius <= 0.0 {
+            return self.clone();
+        }
+        let reach = (radius * 3.0).ceil() as i32;
+        let weights: Vec<f32> = (-reach..=reach).map(|i| (-(i * i) as f32 / (2.0 * radius * radius)).exp()).collect();
+        let total: f32 = weights.iter().sum();
+        let pass = |field: &Field, (step_x, step_y): (i32, i32)| {
+            let mut values = Vec::with_capacity(field.values.len());
+            for y in 0..field.height as i32 {
+                for x in 0..field.width as i32 {
+                    let sum: f32 = weights.iter().zip(-reach..=reach)
+                        .map(|(w, i)| w * field.sample(x + i * step_x, y + i * step_y, edge))
+                        .sum();
+                    values.push(sum / total);
+                }
+            }
+            Field { width: field.width, height: field.height, values }
+        };
+        pass(&pass(self, (1, 0)), (0, 1))
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.values[(y * self.width + x) as usize]
+    }
+
+    /// Reads the value at `x`, `y`, which may be outside the field, resolving it with `edge`.
+    pub fn sample(&self, x: i32, y: i32, edge: EdgeMode) -> f32 {
+        let (width, height) = (self.width as i32, self.height as i32);
+        match edge {
+            EdgeMode::Clamp => self.get(x.clamp(0, width - 1) as u32, y.clamp(0, height - 1) as u32),
+            EdgeMode::Wrap => self.get(x.rem_euclid(width) as u32, y.rem_euclid(height) as u32),
+            EdgeMode::TransparentZero => {
+                if x < 0 || y < 0 || x >= width || y >= height { 0.0 } else { self.get(x as u32, y as u32) }
+            }
+        }
+    }
+}
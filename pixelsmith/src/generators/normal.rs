@@ -0,0 +1,166 @@
+use imgui::SliderFlags;
+use image::RgbaImage;
+use crate::registry::MapImages;
+use crate::sprite::MapKind;
+use super::{normalize, EdgeMode, Field, GeneratedMap, MapGenerator, NormalFormat};
+
+
+/// 3x3 derivative filter used to find the height map's slope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientKernel {
+    Sobel,
+    /// More accurate for diagonal slopes than Sobel
+    Scharr,
+}
+
+impl GradientKernel {
+    pub const ALL: [GradientKernel; 2] = [GradientKernel::Sobel, GradientKernel::Scharr];
+
+    /// Weights of the corner and middle taps on each side. The taps are divided by twice their
+    /// sum so that a slope of 1 per pixel comes out as 1.
+    fn weights(self) -> (f32, f32) {
+        match self {
+            GradientKernel::Sobel => (1.0, 2.0),
+            GradientKernel::Scharr => (3.0, 10.0),
+        }
+    }
+}
+
+impl std::fmt::Display for GradientKernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            GradientKernel::Sobel => "Sobel",
+            GradientKernel::Scharr => "Scharr",
+        })
+    }
+}
+
+
+/// Bakes a normal map from the slope of the height map.
+pub struct NormalFromHeight {
+    pub kernel: GradientKernel,
+    /// Height map units per pixel of slope, higher values make steeper normals
+    pub strength: f32,
+    pub edge: EdgeMode,
+    pub format: NormalFormat,
+}
+
+impl NormalFromHeight {
+    pub fn new() -> Self {
+        NormalFromHeight {
+            kernel: GradientKernel::Sobel,
+            strength: 4.0,
+            edge: EdgeMode::Clamp,
+            format: NormalFormat::YUp,
+        }
+    }
+
+    /// Normal of every pixel of `height`, in image space with y pointing down.
+    pub fn normals(&self, height: &Field) -> Vec<[f32; 3]> {
+        let (corner, middle) = self.kernel.weights();
+        let scale = self.strength / (2.0 * (2.0 * corner + middle));
+        let mut normals = Vec::with_capacity(height.values.len());
+        for y in 0..height.height as i32 {
+            for x in 0..height.width as i32 {
+                let h = |dx: i32, dy: i32| height.sample(x + dx, y + dy, self.edge);
+                let dx = corner * (h(1, -1) - h(-1, -1)) + middle * (h(1, 0) - h(-1, 0)) + corner * (h(1, 1) - h(-1, 1));
+                let dy = corner * (h(-1, 1) - h(-1, -1)) + middle * (h(0, 1) - h(0, -1)) + corner * (h(1, 1) - h(1, -1));
+                normals.push(normalize([-dx * scale, -dy * scale, 1.0]));
+            }
+        }
+        normals
+    }
+}
+
+impl MapGenerator for NormalFromHeight {
+    const NAME: &'static str = "Normal from Height";
+    const INPUTS: &'static [MapKind] = &[MapKind::Height, MapKind::Albedo];
+
+    fn draw_settings(&mut self, ui: &imgui::Ui) -> bool {
+        let mut changed = false;
+        let mut kernel_idx = GradientKernel::ALL.iter().position(|k| *k == self.kernel).unwrap();
+        if ui.combo("Kernel##normal-gen", &mut kernel_idx, &GradientKernel::ALL, |k| k.to_string().into()) {
+            self.kernel = GradientKernel::ALL[kernel_idx];
+            changed = true;
+        }
+        changed |= ui.slider_config("Strength##normal-gen", 0.1, 64.0)
+            .flags(SliderFlags::LOGARITHMIC)
+            .build(&mut self.strength);
+        let mut edge_idx = EdgeMode::ALL.iter().position(|e| *e == self.edge).unwrap();
+        if ui.combo("Edges##normal-gen", &mut edge_idx, &EdgeMode::ALL, |e| e.to_string().into()) {
+            self.edge = EdgeMode::ALL[edge_idx];
+            changed = true;
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text("How pixels past the edge are read. Wrap suits tiles, Transparent as Zero suits sprites with gaps.");
+        }
+        let mut format_idx = NormalFormat::ALL.iter().position(|f| *f == self.format).unwrap();
+        if ui.combo("Output##normal-gen", &mut format_idx, &NormalFormat::ALL, |f| f.to_string().into()) {
+            self.format = NormalFormat::ALL[format_idx];
+            changed = true;
+        }
+        changed
+    }
+
+    fn generate(&self, images: &MapImages) -> Result<Vec<GeneratedMap>, String> {
+        let mut height = Field::from_red(&images.height);
+        if self.edge == EdgeMode::TransparentZero {
+            height.mask_transparent(&images.albedo);
+        }
+        let normals = self.normals(&height);
+        let image = RgbaImage::from_fn(height.width, height.height, |x, y| {
+            self.format.encode(normals[(y * height.width + x) as usize])
+        });
+        Ok(vec![GeneratedMap::new(MapKind::Normal, image)])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8x8 height field rising by `rise` per pixel to the right, or down if `vertical`
+    fn ramp(rise: f32, vertical: bool) -> Field {
+        Field {
+            width: 8,
+            height: 8,
+            values: (0..64).map(|i| rise * if vertical { i / 8 } else { i % 8 } as f32).collect(),
+        }
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!((0..3).all(|i| (a[i] - b[i]).abs() < 1e-5), "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn ramp_has_constant_normal() {
+        for kernel in GradientKernel::ALL {
+            let generator = NormalFromHeight { kernel, ..NormalFromHeight::new() };
+            let slope = 0.1 * generator.strength;
+            for (vertical, expected) in [(false, [-slope, 0.0, 1.0]), (true, [0.0, -slope, 1.0])] {
+                let normals = generator.normals(&ramp(0.1, vertical));
+                // clamping flattens the slope across the edge pixels
+                for y in 1..7 {
+                    for x in 1..7 {
+                        assert_close(normals[y * 8 + x], normalize(expected));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn formats_flip_green() {
+        // a height map rising downwards faces up the image
+        let normal = NormalFromHeight::new().normals(&ramp(0.1, true))[3 * 8 + 3];
+        let up = NormalFormat::YUp.encode(normal);
+        let down = NormalFormat::YDown.encode(normal);
+        assert!(up.0[1] > 128 && down.0[1] < 128, "{:?} {:?}", up, down);
+        assert_eq!((up.0[0], up.0[2]), (down.0[0], down.0[2]));
+        for format in NormalFormat::ALL {
+            let decoded = format.decode(format.encode(normal));
+            assert!((0..3).all(|i| (decoded[i] - normal[i]).abs() < 0.01), "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,393 @@
+use std::path::{Path, PathBuf};
+use image::{Rgba, RgbaImage};
+use toolbelt::cgmath::{InnerSpace, Matrix3, SquareMatrix, Vector3, Zero};
+use toolbelt::color::{linear_to_srgb, srgb_to_linear};
+use crate::registry::MapImages;
+use crate::panel::ERROR_COLOR;
+use crate::sprite::MapKind;
+use super::{normalize, Field, GeneratedMap, MapGenerator, MapTarget, NormalFormat};
+
+const MIN_PROFILES: usize = 2;
+const MAX_PROFILES: usize = 5;
+/// The albedo estimate is only a guide for painting the real albedo, so it doesn't replace it
+const ALBEDO_ESTIMATE_FILE_NAME: &str = "albedo_estimate.png";
+/// Keeps the solve stable when the lights don't span all three axes, which is always the case
+/// with two of them. The normal then has no lean along the missing axis.
+const REGULARIZATION: f32 = 1e-4;
+/// Normals flatter than this are treated as this steep when integrating height, so the edges of
+/// rounded shapes don't shoot off to infinity
+const MIN_NORMAL_Z: f32 = 0.1;
+/// Over-relaxation factor for integrating height
+const SOR_WEIGHT: f32 = 1.8;
+/// Height is integrated on successively halved copies of the sprite down to about this size
+const COARSEST_LEVEL_SIZE: u32 = 8;
+
+
+/// A drawing of the sprite lit from one direction
+pub struct LightProfile {
+    /// Path of the drawing, relative to the sprite's folder
+    pub file: String,
+    /// Direction the light comes from in degrees, counterclockwise from the right
+    pub azimuth: f32,
+    /// Angle of the light above the sprite's plane in degrees
+    pub elevation: f32,
+    image: Option<Result<RgbaImage, String>>,
+}
+
+impl LightProfile {
+    pub fn new(file: &str, azimuth: f32, elevation: f32) -> Self {
+        LightProfile { file: file.to_string(), azimuth, elevation, image: None }
+    }
+
+    /// Unit vector towards the light, in image space with y pointing down.
+    pub fn direction(&self) -> Vector3<f32> {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        Vector3::new(elevation.cos() * azimuth.cos(), -elevation.cos() * azimuth.sin(), elevation.sin())
+    }
+
+    fn load(&mut self, dir: &Path) {
+        if self.file.is_empty() {
+            self.image = Some(Err("Choose a file for every profile".to_string()));
+            return;
+        }
+        let path = dir.join(&self.file);
+        self.image = Some(image::open(&path)
+            .map(|image| image.to_rgba8())
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e)));
+    }
+}
+
+
+/// Light reaching the viewer from a pixel, which the Lambertian model is linear in. Drawings are
+/// stored as sRGB, so the channels are decoded first.
+fn linear_luminance(pixel: Rgba<u8>) -> f32 {
+    let [r, g, b] = [0, 1, 2].map(|i| srgb_to_linear(pixel.0[i] as f32 / 255.0));
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+
+/// Solves for normals from several drawings of a sprite, each lit from a different direction,
+/// and integrates a height map from them.
+pub struct PhotometricStereo {
+    pub profiles: Vec<LightProfile>,
+    pub format: NormalFormat,
+    pub make_height: bool,
+    /// Passes over the height map at each resolution while integrating it
+    pub height_passes: u32,
+    /// Also save the brightness each pixel would have when lit head-on
+    pub make_albedo: bool,
+    dir: Option<PathBuf>,
+}
+
+impl PhotometricStereo {
+    pub fn new() -> Self {
+        PhotometricStereo {
+            profiles: vec![
+                LightProfile::new("light_left.png", 180.0, 30.0),
+                LightProfile::new("light_right.png", 0.0, 30.0),
+                LightProfile::new("light_top.png", 90.0, 30.0),
+                LightProfile::new("light_bottom.png", 270.0, 30.0),
+            ],
+            format: NormalFormat::YUp,
+            make_height: true,
+            height_passes: 20,
+            make_albedo: false,
+            dir: None,
+        }
+    }
+
+    /// Least squares fit of `albedo * normal` to every profile's brightness, at each pixel.
+    /// Returns the unit normals, in image space with y pointing down, and the albedos.
+    pub fn solve(&self, images: &[&RgbaImage], mask: &RgbaImage) -> (Vec<[f32; 3]>, Field) {
+        let directions: Vec<Vector3<f32>> = self.profiles.iter().map(|p| p.direction()).collect();
+        let mut lights = Matrix3::identity() * REGULARIZATION;
+        for l in directions.iter() {
+            lights += Matrix3::from_cols(l * l.x, l * l.y, l * l.z);
+        }
+        let lights_inverse = lights.invert().unwrap_or_else(Matrix3::zero);
+
+        let (width, height) = mask.dimensions();
+        let mut normals = Vec::with_capacity((width * height) as usize);
+        let mut albedos = Vec::with_capacity((width * height) as usize);
+        for (i, pixel) in mask.pixels().enumerate() {
+            if pixel.0[3] == 0 {
+                normals.push([0.0, 0.0, 1.0]);
+                albedos.push(0.0);
+                continue;
+            }
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            let lit = images.iter().zip(directions.iter())
+                .fold(Vector3::zero(), |sum, (image, l)| sum + l * linear_luminance(*image.get_pixel(x, y)));
+            let scaled_normal = lights_inverse * lit;
+            let albedo = scaled_normal.magnitude();
+            // the sprite can't face away from the viewer, drawings that say otherwise are off
+            normals.push(normalize([scaled_normal.x, scaled_normal.y, scaled_normal.z.max(0.0)]));
+            albedos.push(albedo.min(1.0));
+        }
+        (normals, Field { width, height, values: albedos })
+    }
+
+    /// Finds the height map whose slopes best match `normals` inside the mask's opaque pixels,
+    /// scaled to fill 0 to 1.
+    pub fn integrate(&self, normals: &[[f32; 3]], mask: &RgbaImage) -> Field {
+        let (width, height) = mask.dimensions();
+        let opaque: Vec<bool> = mask.pixels().map(|p| p.0[3] != 0).collect();
+        // rise of the height map per pixel to the right and down
+        let slopes: Vec<(f32, f32)> = normals.iter()
+            .map(|[x, y, z]| {
+                let z = z.max(MIN_NORMAL_Z);
+                (-x / z, -y / z)
+            })
+            .collect();
+
+        // relaxation only smooths out errors a few pixels across each pass, so the broad shape is
+        // solved on halved copies first and each one starts the next finer one off
+        let mut levels = vec![HeightLevel { width, height, opaque, slopes }];
+        while levels.last().is_some_and(|level| level.width.max(level.height) > COARSEST_LEVEL_SIZE) {
+            let coarser = levels.last().unwrap().halve();
+            levels.push(coarser);
+        }
+        let mut values = Vec::new();
+        for (i, level) in levels.iter().enumerate().rev() {
+            values = match levels.get(i + 1) {
+                Some(coarser) => coarser.expand(&values, level.width, level.height),
+                None => vec![0.0; level.opaque.len()],
+            };
+            level.relax(&mut values, self.height_passes);
+        }
+        let opaque = levels.swap_remove(0).opaque;
+
+        let (min, max) = values.iter().zip(opaque.iter())
+            .filter(|(_, opaque)| **opaque)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), (v, _)| (min.min(*v), max.max(*v)));
+        let range = (max - min).max(f32::EPSILON);
+        for (value, opaque) in values.iter_mut().zip(opaque.iter()) {
+            *value = if *opaque { (*value - min) / range } else { 0.0 };
+        }
+        Field { width, height, values }
+    }
+}
+
+/// The slopes to integrate at one resolution
+struct HeightLevel {
+    width: u32,
+    height: u32,
+    opaque: Vec<bool>,
+    slopes: Vec<(f32, f32)>,
+}
+
+impl HeightLevel {
+    /// Half the size, rounded up. A pixel is opaque if any of the four it covers is, and its
+    /// slopes are twice theirs on average, being per pixel twice the size.
+    fn halve(&self) -> HeightLevel {
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut opaque = Vec::with_capacity((width * height) as usize);
+        let mut slopes = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = (0.0, 0.0);
+                let mut count = 0;
+                for (fx, fy) in [(2 * x, 2 * y), (2 * x + 1, 2 * y), (2 * x, 2 * y + 1), (2 * x + 1, 2 * y + 1)] {
+                    let i = (fy * self.width + fx) as usize;
+                    if fx < self.width && fy < self.height && self.opaque[i] {
+                        sum = (sum.0 + self.slopes[i].0, sum.1 + self.slopes[i].1);
+                        count += 1;
+                    }
+                }
+                opaque.push(count > 0);
+                slopes.push(if count > 0 { (2.0 * sum.0 / count as f32, 2.0 * sum.1 / count as f32) } else { (0.0, 0.0) });
+            }
+        }
+        HeightLevel { width, height, opaque, slopes }
+    }
+
+    /// Heights solved at this level spread out over the level `width` by `height` that it halved.
+    fn expand(&self, values: &[f32], width: u32, height: u32) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| values[((i / width / 2) * self.width + (i % width) / 2) as usize])
+            .collect()
+    }
+
+    /// Successive over-relaxation of `values` towards the heights whose differences best match
+    /// the slopes, which converges far faster than plain Gauss-Seidel.
+    fn relax(&self, values: &mut [f32], passes: u32) {
+        let (width, height) = (self.width as i32, self.height as i32);
+        for _ in 0..passes {
+            for y in 0..height {
+                for x in 0..width {
+                    let i = (y * width + x) as usize;
+                    if !self.opaque[i] {
+                        continue;
+                    }
+                    let mut sum = 0.0;
+                    let mut count = 0;
+                    for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                        let (nx, ny) = (x + dx, y + dy);
+                        if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                            continue;
+                        }
+                        let n = (ny * width + nx) as usize;
+                        if !self.opaque[n] {
+                            continue;
+                        }
+                        // the rise to the neighbor is the mean of both pixels' slopes that way
+                        let rise = if dx != 0 {
+                            dx as f32 * (self.slopes[i].0 + self.slopes[n].0) * 0.5
+                        } else {
+                            dy as f32 * (self.slopes[i].1 + self.slopes[n].1) * 0.5
+                        };
+                        sum += values[n] - rise;
+                        count += 1;
+                    }
+                    if count > 0 {
+                        values[i] += SOR_WEIGHT * (sum / count as f32 - values[i]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl MapGenerator for PhotometricStereo {
+    const NAME: &'static str = "Normals from Lighting Profiles";
+    const INPUTS: &'static [MapKind] = &[MapKind::Albedo];
+
+    fn draw_settings(&mut self, ui: &imgui::Ui) -> bool {
+        let mut changed = false;
+        ui.text_disabled("Drawings of the sprite lit from each side, in the sprite's folder");
+        let can_remove = self.profiles.len() > MIN_PROFILES;
+        let mut remove = None;
+        for (i, profile) in self.profiles.iter_mut().enumerate() {
+            let _id = ui.push_id(i.to_string());
+            // profiles are read when the dialog is first drawn for a sprite, not when it's loaded
+            if let (None, Some(dir)) = (&profile.image, &self.dir) {
+                profile.load(dir);
+                changed = true;
+            }
+            ui.input_text("File", &mut profile.file).build();
+            // only read the file once the name is done being typed
+            if ui.is_item_deactivated_after_edit() {
+                profile.image = None;
+                changed = true;
+            }
+            if can_remove {
+                ui.same_line();
+                if ui.button("x") {
+                    remove = Some(i);
+                }
+            }
+            changed |= ui.slider("Azimuth", 0.0, 360.0, &mut profile.azimuth);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Direction the light comes from in degrees: 0 is right, 90 is up");
+            }
+            changed |= ui.slider("Elevation", 0.0, 90.0, &mut profile.elevation);
+            if let Some(Err(e)) = &profile.image {
+                let _wrap = ui.push_text_wrap_pos();
+                ui.text_colored(ERROR_COLOR, e);
+            }
+            ui.separator();
+        }
+        if let Some(i) = remove {
+            self.profiles.remove(i);
+            changed = true;
+        }
+        if self.profiles.len() < MAX_PROFILES && ui.button("Add Profile") {
+            self.profiles.push(LightProfile::new("", 90.0, 60.0));
+            changed = true;
+        }
+
+        let mut format_idx = NormalFormat::ALL.iter().position(|f| *f == self.format).unwrap();
+        if ui.combo("Output##stereo", &mut format_idx, &NormalFormat::ALL, |f| f.to_string().into()) {
+            self.format = NormalFormat::ALL[format_idx];
+            changed = true;
+        }
+        changed |= ui.checkbox("Height##stereo", &mut self.make_height);
+        {
+            let _disabled = ui.begin_disabled(!self.make_height);
+            changed |= ui.slider("Passes##stereo", 1, 100, &mut self.height_passes);
+            if ui.is_item_hovered() {
+                ui.tooltip_text("Passes over the height map at each resolution, more follow the normals more closely");
+            }
+        }
+        changed |= ui.checkbox("Albedo Estimate##stereo", &mut self.make_albedo);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(format!("Also save {}, the sprite's brightness with the lighting taken out", ALBEDO_ESTIMATE_FILE_NAME));
+        }
+        changed
+    }
+
+    fn generate(&self, images: &MapImages) -> Result<Vec<GeneratedMap>, String> {
+        if self.profiles.len() < MIN_PROFILES {
+            return Err(format!("At least {} lighting profiles are needed", MIN_PROFILES));
+        }
+        let mut profile_images = Vec::with_capacity(self.profiles.len());
+        for profile in self.profiles.iter() {
+            match &profile.image {
+                Some(Ok(image)) if image.dimensions() == images.albedo.dimensions() => profile_images.push(image),
+                Some(Ok(image)) => {
+                    return Err(format!("{} is {}x{}, but the albedo is {}x{}", profile.file,
+                        image.width(), image.height(), images.albedo.width(), images.albedo.height()));
+                }
+                Some(Err(e)) => return Err(e.clone()),
+                None => return Err(format!("{} hasn't been read yet", profile.file)),
+            }
+        }
+
+        let (normals, albedos) = self.solve(&profile_images, &images.albedo);
+        let normal = RgbaImage::from_fn(albedos.width, albedos.height, |x, y| {
+            self.format.encode(normals[(y * albedos.width + x) as usize])
+        });
+        let mut maps = vec![GeneratedMap::new(MapKind::Normal, normal)];
+        if self.make_height {
+            maps.push(GeneratedMap::new(MapKind::Height, self.integrate(&normals, &images.albedo).to_image()));
+        }
+        if self.make_albedo {
+            let albedo = RgbaImage::from_fn(albedos.width, albedos.height, |x, y| {
+                // the solve is in linear light, the image is stored as sRGB
+                let v = (linear_to_srgb(albedos.get(x, y)).clamp(0.0, 1.0) * 255.0).round() as u8;
+                Rgba([v, v, v, images.albedo.get_pixel(x, y).0[3]])
+            });
+            maps.push(GeneratedMap {
+                target: MapTarget::File { name: ALBEDO_ESTIMATE_FILE_NAME, preview_in: None },
+                image: albedo,
+            });
+        }
+        Ok(maps)
+    }
+
+    fn set_sprite_dir(&mut self, dir: &Path) {
+        for profile in self.profiles.iter_mut() {
+            profile.image = None;
+        }
+        self.dir = Some(dir.to_path_buf());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn even_lighting_faces_viewer() {
+        // the default profiles are four lights at the same elevation, a quarter turn apart
+        let stereo = PhotometricStereo::new();
+        let image = RgbaImage::from_pixel(3, 3, Rgba([180, 180, 180, 255]));
+        let (normals, albedos) = stereo.solve(&[&image; 4], &image);
+        for normal in normals {
+            assert!(normal[0].abs() < 1e-4 && normal[1].abs() < 1e-4 && (normal[2] - 1.0).abs() < 1e-4, "{:?}", normal);
+        }
+        // lit at 30 degrees, a surface facing the viewer is half as bright as its albedo
+        let expected = 2.0 * srgb_to_linear(180.0 / 255.0);
+        assert!(albedos.values.iter().all(|a| (a - expected.min(1.0)).abs() < 1e-3), "{:?}", albedos.values);
+    }
+
+    #[test]
+    fn brighter_side_tilts_normal() {
+        let stereo = PhotometricStereo::new();
+        let [dim, bright] = [100, 200].map(|v| RgbaImage::from_pixel(1, 1, Rgba([v, v, v, 255])));
+        // left, right, top and bottom, with the right side lit brighter
+        let (normals, _) = stereo.solve(&[&dim, &bright, &dim, &dim], &dim);
+        assert!(normals[0][0] > 0.1 && normals[0][1].abs() < 1e-4, "{:?}", normals[0]);
+    }
+}
@@ -1,10 +1,13 @@
 use toolbelt::cgmath::{Point2, Point3};
 use winit::event::MouseButton;
-use toolbelt::Color;
+use toolbelt::{Color, ColorSpace};
 use toolbelt::drag::DragState;
 use serde_derive::{Serialize, Deserialize};
 
 
+fn default_normalize_intensities() -> bool { true }
+fn default_emissive_intensity() -> f32 { 1.0 }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LightingInfo {
     pub lights: Vec<Light>,
@@ -12,16 +15,192 @@ pub struct LightingInfo {
     pub global_ambient: f32,
     pub global_diffuse: f32,
     pub global_specular: f32,
+    /// Whether editing one global intensity rescales the others so they keep summing to 1
+    #[serde(default = "default_normalize_intensities")]
+    pub normalize_intensities: bool,
+    /// Whether the AO map darkens diffuse light as well as ambient light
+    #[serde(default)]
+    pub ao_affects_diffuse: bool,
+    #[serde(default)]
+    pub shadows: ShadowSettings,
+    #[serde(default)]
+    pub toon: ToonSettings,
+    /// Brightness of the sprite's emissive map
+    #[serde(default = "default_emissive_intensity")]
+    pub emissive_intensity: f32,
+}
+
+impl Default for LightingInfo {
+    fn default() -> Self {
+        LightingInfo {
+            lights: vec![Light::new("Light 1", Point2::new(-16.0, -16.0))],
+            enable_light_parallax: false,
+            global_ambient: 0.05,
+            global_diffuse: 0.475,
+            global_specular: 0.475,
+            normalize_intensities: true,
+            ao_affects_diffuse: false,
+            shadows: ShadowSettings::default(),
+            emissive_intensity: default_emissive_intensity(),
+            toon: ToonSettings::default(),
+        }
+    }
+}
+
+
+/// Self-shadowing traced over the sprite's height map
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Number of height map samples taken between each pixel and the light
+    pub steps: u32,
+    /// Height in canvas pixels of a white texel in the height map
+    pub height_scale: f32,
+    /// How quickly shadows fade out with distance from whatever casts them, 0 for hard shadows
+    pub softness: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            enabled: false,
+            steps: 32,
+            height_scale: 8.0,
+            softness: 0.1,
+        }
+    }
+}
+
+/// Most bands the toon shader supports
+pub const MAX_TOON_BANDS: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DitherPattern { None, Bayer2, Bayer4, Bayer8 }
+impl DitherPattern {
+    pub const ALL: [DitherPattern; 4] = [DitherPattern::None, DitherPattern::Bayer2, DitherPattern::Bayer4, DitherPattern::Bayer8];
+
+    /// Width of the Bayer matrix, 0 for no dithering
+    pub fn size(self) -> u32 {
+        match self {
+            DitherPattern::None => 0,
+            DitherPattern::Bayer2 => 2,
+            DitherPattern::Bayer4 => 4,
+            DitherPattern::Bayer8 => 8,
+        }
+    }
+}
+impl std::fmt::Display for DitherPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            DitherPattern::None => "None",
+            DitherPattern::Bayer2 => "Bayer 2x2",
+            DitherPattern::Bayer4 => "Bayer 4x4",
+            DitherPattern::Bayer8 => "Bayer 8x8",
+        })
+    }
+}
+
+/// Quantizes each light's contribution into a few flat bands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToonSettings {
+    pub enabled: bool,
+    pub bands: u32,
+    /// Light amounts where each band after the first starts, ascending, `bands - 1` of them
+    pub thresholds: Vec<f32>,
+    pub dither: DitherPattern,
+    /// How far either side of a band edge the dither reaches, in light amount
+    pub dither_spread: f32,
+}
+
+impl ToonSettings {
+    /// Thresholds splitting 0..1 into `bands` equal parts
+    pub fn even_thresholds(bands: u32) -> Vec<f32> {
+        (1..bands).map(|i| i as f32 / bands as f32).collect()
+    }
+
+    /// Thresholds in the layout the sprite shader expects, padded with values no light reaches.
+    pub fn packed_thresholds(&self) -> [[f32; 4]; 2] {
+        let mut packed = [[2.0; 4]; 2];
+        for (i, t) in self.thresholds.iter().take(MAX_TOON_BANDS as usize - 1).enumerate() {
+            packed[i / 4][i % 4] = *t;
+        }
+        packed
+    }
+}
+
+impl Default for ToonSettings {
+    fn default() -> Self {
+        ToonSettings {
+            enabled: false,
+            bands: 3,
+            thresholds: ToonSettings::even_thresholds(3),
+            dither: DitherPattern::None,
+            dither_spread: 0.05,
+        }
+    }
+}
+
+
+/// How a light illuminates the scene. Angles are in degrees, measured clockwise from +x in canvas space.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Shines in every direction from its position
+    #[default]
+    Point,
+    /// Parallel light from infinitely far away, like the sun. `angle` is the direction the light comes
+    /// from and `elevation` is how far above the sprite plane it is (90 is straight down).
+    Directional { angle: f32, elevation: f32 },
+    /// A point light limited to a cone around `direction`. `cone_angle` is the full width of the cone
+    /// and `softness` is the fraction of it that fades out towards the edge.
+    Spot { direction: f32, cone_angle: f32, softness: f32 },
+    /// Ambient light blended from the light's color above to `ground` below, based on surface normals
+    Hemisphere { ground: Color },
+}
+
+impl LightKind {
+    pub const NAMES: [&'static str; 4] = ["Point", "Directional", "Spot", "Hemisphere"];
+
+    /// Index into `NAMES`, also used as the kind id in the sprite shader
+    pub fn index(&self) -> usize {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Directional { .. } => 1,
+            LightKind::Spot { .. } => 2,
+            LightKind::Hemisphere { .. } => 3,
+        }
+    }
+
+    /// Returns the default parameters of the kind at `index` in `NAMES`.
+    pub fn from_index(index: usize) -> Self {
+        match index {
+            1 => LightKind::Directional { angle: 225.0, elevation: 45.0 },
+            2 => LightKind::Spot { direction: 45.0, cone_angle: 60.0, softness: 0.25 },
+            3 => LightKind::Hemisphere { ground: Color::from_rgba(0.25, 0.2, 0.15, 1.0) },
+            _ => LightKind::Point,
+        }
+    }
 }
 
+
+fn default_light_range() -> f32 { 250.0 }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Light {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub kind: LightKind,
     pub position: Point2<f32>,
     pub height: f32,
     pub color: Color,
     pub gizmo_hovered: bool,
     pub falloff_exp: f32,
     pub enable_falloff: bool,
+    /// Distance at which point and spot lights fade out completely when falloff is enabled
+    #[serde(default = "default_light_range")]
+    pub range: f32,
     #[serde(skip)]
     pub drag_state: DragState<MouseButton>,
     pub diffuse: f32,
@@ -29,6 +208,23 @@ pub struct Light {
 }
 
 impl Light {
+    pub fn new(name: impl Into<String>, position: Point2<f32>) -> Self {
+        Light {
+            name: name.into(),
+            kind: LightKind::Point,
+            position,
+            height: 50.0,
+            color: Color::white(ColorSpace::RGBA).with_alpha(2.0),
+            gizmo_hovered: false,
+            falloff_exp: 2.0,
+            enable_falloff: true,
+            range: default_light_range(),
+            drag_state: DragState::new(),
+            diffuse: 1.0,
+            specular: 1.0
+        }
+    }
+
     /// Returns the light's position as Point3(x, y, height)
     #[allow(dead_code)]
     pub fn position_3d(&self) -> Point3<f32> { Point3::new(self.position.x, self.position.y, self.height) }
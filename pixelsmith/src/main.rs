@@ -1,6 +1,8 @@
 mod app;
+mod generators;
 mod geometry;
 mod lights;
+mod material;
 mod palette;
 mod pipeline;
 mod project;
@@ -32,6 +34,8 @@ pub struct GlobalStatics {
 
     pub sprite_maps_bind_layout: wgpu::BindGroupLayout,
     pub single_texture_bind_layout: wgpu::BindGroupLayout,
+    /// Per-scene lookup textures used by the sprite shader: the lighting ramp and palette swap
+    pub lookup_bind_layout: wgpu::BindGroupLayout,
 
     pub rt_sampler: wgpu::Sampler,
     pub font_atlas_sampler: wgpu::Sampler,
@@ -61,6 +65,22 @@ pub fn init_globals(device: wgpu::Device, queue: wgpu::Queue) {
             wgpu::BindGroupLayoutEntry { binding: 2, ..MAP_BIND_ENTRY },
             wgpu::BindGroupLayoutEntry { binding: 3, ..MAP_BIND_ENTRY },
             wgpu::BindGroupLayoutEntry { binding: 4, ..MAP_BIND_ENTRY },
+            wgpu::BindGroupLayoutEntry { binding: 5, ..MAP_BIND_ENTRY },
+            wgpu::BindGroupLayoutEntry { binding: 6, ..MAP_BIND_ENTRY },
+        ],
+    });
+
+    let lookup_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("sprite lookup bind group layout"),
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry { binding: 1, ..MAP_BIND_ENTRY },
+            wgpu::BindGroupLayoutEntry { binding: 2, ..MAP_BIND_ENTRY },
         ],
     });
 
@@ -96,6 +116,7 @@ pub fn init_globals(device: wgpu::Device, queue: wgpu::Queue) {
         queue,
         sprite_maps_bind_layout,
         single_texture_bind_layout,
+        lookup_bind_layout,
         rt_sampler,
         font_atlas_sampler,
     });
@@ -0,0 +1,149 @@
+use serde_derive::{Serialize, Deserialize};
+use toolbelt::Color;
+
+
+/// What the sprite's specular map means
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MaterialWorkflow {
+    /// The map scales the strength of specular highlights, their size comes from `spec_power`
+    Specular = 0,
+    /// The map is surface roughness, white being fully rough. Highlights are always full strength.
+    Roughness = 1,
+}
+
+impl MaterialWorkflow {
+    pub const ALL: [MaterialWorkflow; 2] = [MaterialWorkflow::Specular, MaterialWorkflow::Roughness];
+}
+
+impl std::fmt::Display for MaterialWorkflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            MaterialWorkflow::Specular => "Specular Intensity",
+            MaterialWorkflow::Roughness => "Roughness",
+        })
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpecularModel {
+    Phong = 0,
+    /// Cook-Torrance with a GGX distribution, as used by most PBR engines
+    Ggx = 1,
+}
+
+impl SpecularModel {
+    pub const ALL: [SpecularModel; 2] = [SpecularModel::Phong, SpecularModel::Ggx];
+}
+
+impl std::fmt::Display for SpecularModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SpecularModel::Phong => "Phong",
+            SpecularModel::Ggx => "GGX",
+        })
+    }
+}
+
+
+/// How a sprite's surface responds to light, saved in `scene.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialInfo {
+    pub workflow: MaterialWorkflow,
+    pub specular_model: SpecularModel,
+    /// Phong exponent of a perfectly smooth surface. In the specular workflow this sets the
+    /// highlight size of the whole sprite.
+    pub spec_power: f32,
+    pub ramp: RampSettings,
+}
+
+impl Default for MaterialInfo {
+    fn default() -> Self {
+        MaterialInfo {
+            // sprites saved before materials existed have no `material` key, and their
+            // `specular.png` was painted brighter where it's shinier
+            workflow: MaterialWorkflow::Specular,
+            specular_model: SpecularModel::Phong,
+            spec_power: 32.0,
+            ramp: RampSettings::default(),
+        }
+    }
+}
+
+
+/// Width in texels of the baked ramp texture
+pub const RAMP_TEXTURE_WIDTH: u32 = 256;
+
+/// One color in a lighting ramp
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RampStop {
+    /// Where on the ramp this color sits, 0 being unlit and 1 fully lit
+    pub position: f32,
+    pub color: Color,
+}
+
+/// Ramp lighting looks up the brightness of the light hitting each texel in a gradient and multiplies
+/// the albedo by the color found there, so shadows and highlights can shift hue instead of only darkening.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RampSettings {
+    pub enabled: bool,
+    /// Blend between regular lighting (0) and ramp lighting (1)
+    pub strength: f32,
+    /// Kept sorted by position
+    pub stops: Vec<RampStop>,
+}
+
+impl RampSettings {
+    /// Ramp color at `t` in 0..1, linearly interpolated between stops.
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let rgb = |stop: &RampStop| { let c = stop.color.to_rgb(); *c.components_3() };
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [t, t, t],
+        };
+        if t <= first.position {
+            return rgb(first);
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if t <= b.position {
+                let span = b.position - a.position;
+                let f = if span > 0.0 { (t - a.position) / span } else { 1.0 };
+                let (ca, cb) = (rgb(a), rgb(b));
+                return [ca[0] + (cb[0] - ca[0]) * f, ca[1] + (cb[1] - ca[1]) * f, ca[2] + (cb[2] - ca[2]) * f];
+            }
+        }
+        rgb(last)
+    }
+
+    /// RGBA8 pixels of the ramp, `RAMP_TEXTURE_WIDTH` wide and one tall.
+    pub fn bake(&self) -> Vec<u8> {
+        (0..RAMP_TEXTURE_WIDTH).flat_map(|x| {
+            let [r, g, b] = self.sample(x as f32 / (RAMP_TEXTURE_WIDTH - 1) as f32);
+            [(r.clamp(0.0, 1.0) * 255.0).round() as u8, (g.clamp(0.0, 1.0) * 255.0).round() as u8,
+             (b.clamp(0.0, 1.0) * 255.0).round() as u8, 255]
+        }).collect()
+    }
+
+    pub fn sort_stops(&mut self) {
+        self.stops.sort_by(|a, b| a.position.partial_cmp(&b.position).unwrap_or(std::cmp::Ordering::Equal));
+    }
+}
+
+impl Default for RampSettings {
+    /// Cool, dark shadows through neutral midtones to warm highlights
+    fn default() -> Self {
+        RampSettings {
+            enabled: false,
+            strength: 1.0,
+            stops: vec![
+                RampStop { position: 0.0, color: Color::from_rgb(0.10, 0.08, 0.25) },
+                RampStop { position: 0.35, color: Color::from_rgb(0.45, 0.45, 0.65) },
+                RampStop { position: 0.7, color: Color::from_rgb(1.0, 0.96, 0.9) },
+                RampStop { position: 1.0, color: Color::from_rgb(1.0, 0.92, 0.75) },
+            ],
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use image::RgbaImage;
+use toolbelt::Color;
+
+
+/// A color used by a sprite and how many pixels use it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorCount {
+    pub rgb: [u8; 3],
+    pub count: u32,
+}
+
+/// Converts 8-bit RGB to an RGB `Color`.
+pub fn rgb_color([r, g, b]: [u8; 3]) -> Color {
+    Color::from_rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSort {
+    /// Grays first from dark to light, then around the hue wheel
+    Hue,
+    /// Most used first
+    Count,
+}
+
+impl ColorSort {
+    pub const ALL: [ColorSort; 2] = [ColorSort::Hue, ColorSort::Count];
+}
+
+impl std::fmt::Display for ColorSort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ColorSort::Hue => "Hue",
+            ColorSort::Count => "Usage",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceMethod { MedianCut, KMeans }
+
+impl ReduceMethod {
+    pub const ALL: [ReduceMethod; 2] = [ReduceMethod::MedianCut, ReduceMethod::KMeans];
+
+    /// Picks at most `target` colors representing `colors`.
+    pub fn reduce(self, colors: &[ColorCount], target: usize) -> Vec<[u8; 3]> {
+        match self {
+            ReduceMethod::MedianCut => median_cut(colors, target),
+            ReduceMethod::KMeans => k_means(colors, target, K_MEANS_ITERATIONS),
+        }
+    }
+}
+
+impl std::fmt::Display for ReduceMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ReduceMethod::MedianCut => "Median Cut",
+            ReduceMethod::KMeans => "K-Means",
+        })
+    }
+}
+
+const K_MEANS_ITERATIONS: usize = 16;
+/// Below this HSV saturation a color is sorted with the grays
+const GRAY_SATURATION: f32 = 0.08;
+
+
+/// Every distinct color in the image with its pixel count. Fully transparent pixels are skipped.
+pub fn unique_colors(image: &RgbaImage) -> Vec<ColorCount> {
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for pixel in image.pixels() {
+        let [r, g, b, a] = pixel.0;
+        if a != 0 {
+            *counts.entry([r, g, b]).or_insert(0) += 1;
+        }
+    }
+    counts.into_iter().map(|(rgb, count)| ColorCount { rgb, count }).collect()
+}
+
+pub fn sort_colors(colors: &mut [ColorCount], sort: ColorSort) {
+    match sort {
+        ColorSort::Count => colors.sort_by(|a, b| b.count.cmp(&a.count).then(a.rgb.cmp(&b.rgb))),
+        ColorSort::Hue => {
+            let key = |c: &ColorCount| {
+                let [h, s, v] = *rgb_color(c.rgb).to_hsv().components_3();
+                let gray = s < GRAY_SATURATION;
+                (!gray, if gray { 0.0 } else { h }, v)
+            };
+            colors.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap_or(std::cmp::Ordering::Equal));
+        }
+    }
+}
+
+/// Average of `colors` weighted by their counts
+fn weighted_mean(colors: &[ColorCount]) -> [f32; 3] {
+    let mut sum = [0.0f64; 3];
+    let mut total = 0.0f64;
+    for c in colors {
+        for (s, channel) in sum.iter_mut().zip(c.rgb) {
+            *s += channel as f64 * c.count as f64;
+        }
+        total += c.count as f64;
+    }
+    if total == 0.0 {
+        return [0.0; 3];
+    }
+    sum.map(|s| (s / total) as f32)
+}
+
+fn round_rgb(rgb: [f32; 3]) -> [u8; 3] {
+    rgb.map(|c| c.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Repeatedly splits the box of colors with the widest channel at its pixel-weighted median,
+/// until there are `target` boxes, then averages each box.
+pub fn median_cut(colors: &[ColorCount], target: usize) -> Vec<[u8; 3]> {
+    if target == 0 {
+        return Vec::new();
+    }
+    if colors.len() <= target {
+        return colors.iter().map(|c| c.rgb).collect();
+    }
+    // widest channel and its range
+    let widest = |bucket: &[ColorCount]| {
+        (0..3).map(|i| {
+            let min = bucket.iter().map(|c| c.rgb[i]).min().unwrap_or(0);
+            let max = bucket.iter().map(|c| c.rgb[i]).max().unwrap_or(0);
+            (i, max - min)
+        }).max_by_key(|(_, range)| *range).unwrap()
+    };
+
+    let mut buckets = vec![colors.to_vec()];
+    while buckets.len() < target {
+        let split = buckets.iter().enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .max_by_key(|(_, b)| widest(b).1)
+            .map(|(idx, _)| idx);
+        let mut bucket = match split {
+            Some(idx) => buckets.swap_remove(idx),
+            None => break,
+        };
+        let (channel, _) = widest(&bucket);
+        bucket.sort_by_key(|c| c.rgb[channel]);
+        let half = bucket.iter().map(|c| c.count as u64).sum::<u64>() / 2;
+        let mut running = 0;
+        let mut at = bucket.len() - 1;
+        for (i, c) in bucket.iter().enumerate() {
+            running += c.count as u64;
+            if running >= half {
+                at = i + 1;
+                break;
+            }
+        }
+        // both halves need at least one color
+        let at = at.clamp(1, bucket.len() - 1);
+        let upper = bucket.split_off(at);
+        buckets.push(bucket);
+        buckets.push(upper);
+    }
+    buckets.iter().map(|b| round_rgb(weighted_mean(b))).collect()
+}
+
+/// Lloyd's algorithm weighted by pixel counts. Centers start at the most used color, then
+/// repeatedly at the color farthest from every center so far, so results are repeatable.
+pub fn k_means(colors: &[ColorCount], k: usize, iterations: usize) -> Vec<[u8; 3]> {
+    if k == 0 {
+        return Vec::new();
+    }
+    if colors.len() <= k {
+        return colors.iter().map(|c| c.rgb).collect();
+    }
+    let as_f32 = |c: &ColorCount| c.rgb.map(|x| x as f32);
+    let distance = |a: [f32; 3], b: [f32; 3]| (0..3).map(|i| (a[i] - b[i]) * (a[i] - b[i])).sum::<f32>();
+
+    let first = colors.iter().max_by_key(|c| c.count).unwrap();
+    let mut centers = vec![as_f32(first)];
+    let mut nearest: Vec<f32> = colors.iter().map(|c| distance(as_f32(c), centers[0])).collect();
+    while centers.len() < k {
+        let (idx, _) = nearest.iter().enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .unwrap();
+        let center = as_f32(&colors[idx]);
+        for (n, c) in nearest.iter_mut().zip(colors.iter()) {
+            *n = n.min(distance(as_f32(c), center));
+        }
+        centers.push(center);
+    }
+
+    let mut assignment = vec![0; colors.len()];
+    for _ in 0..iterations {
+        let mut changed = false;
+        for (a, c) in assignment.iter_mut().zip(colors.iter()) {
+            let rgb = as_f32(c);
+            let closest = (0..centers.len())
+                .min_by(|&i, &j| distance(rgb, centers[i]).partial_cmp(&distance(rgb, centers[j])).unwrap_or(std::cmp::Ordering::Equal))
+                .unwrap();
+            if *a != closest {
+                *a = closest;
+                changed = true;
+            }
+        }
+        for (i, center) in centers.iter_mut().enumerate() {
+            let members: Vec<ColorCount> = colors.iter().zip(assignment.iter())
+                .filter(|(_, a)| **a == i)
+                .map(|(c, _)| *c)
+                .collect();
+            // empty clusters keep their old center
+            if !members.is_empty() {
+                *center = weighted_mean(&members);
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    centers.into_iter().map(round_rgb).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use super::*;
+
+    fn counts(colors: &[([u8; 3], u32)]) -> Vec<ColorCount> {
+        colors.iter().map(|(rgb, count)| ColorCount { rgb: *rgb, count: *count }).collect()
+    }
+
+    fn sorted(mut colors: Vec<[u8; 3]>) -> Vec<[u8; 3]> {
+        colors.sort();
+        colors
+    }
+
+    /// Three tight clusters of five colors each, far apart from each other
+    fn clusters() -> Vec<ColorCount> {
+        [[200, 20, 20], [20, 200, 20], [20, 20, 200]].iter()
+            .flat_map(|center| (0..5u8).map(move |i| ColorCount { rgb: center.map(|c| c + i), count: 1 }))
+            .collect()
+    }
+
+    #[test]
+    fn unique_colors_counts_opaque_pixels() {
+        let image = RgbaImage::from_fn(4, 2, |x, y| match (x, y) {
+            (0, 0) => Rgba([9, 9, 9, 0]),
+            (_, 0) => Rgba([255, 0, 0, 255]),
+            // alpha doesn't matter, only whether it's zero
+            (0, 1) => Rgba([255, 0, 0, 10]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+        let mut colors = unique_colors(&image);
+        sort_colors(&mut colors, ColorSort::Count);
+        assert_eq!(colors, counts(&[([255, 0, 0], 4), ([0, 0, 255], 3)]));
+    }
+
+    #[test]
+    fn median_cut_returns_at_most_target() {
+        let colors = clusters();
+        for target in 0..=colors.len() + 1 {
+            assert!(median_cut(&colors, target).len() <= target, "{}", target);
+        }
+        let few = counts(&[([1, 2, 3], 4), ([40, 50, 60], 1)]);
+        assert_eq!(sorted(median_cut(&few, 2)), vec![[1, 2, 3], [40, 50, 60]]);
+        assert_eq!(sorted(median_cut(&few, 5)), vec![[1, 2, 3], [40, 50, 60]]);
+    }
+
+    #[test]
+    fn median_cut_splits_between_clusters() {
+        // an even split by pixel count falls in the gap between two clusters
+        assert_eq!(sorted(median_cut(&clusters()[..10], 2)), vec![[22, 202, 22], [202, 22, 22]]);
+    }
+
+    #[test]
+    fn k_means_converges_on_clusters() {
+        assert_eq!(sorted(k_means(&clusters(), 3, K_MEANS_ITERATIONS)), vec![[22, 22, 202], [22, 202, 22], [202, 22, 22]]);
+        let few = counts(&[([1, 2, 3], 4)]);
+        assert_eq!(k_means(&few, 3, K_MEANS_ITERATIONS), vec![[1, 2, 3]]);
+    }
+
+    #[test]
+    fn empty_input() {
+        let transparent = RgbaImage::from_pixel(3, 3, Rgba([255, 255, 255, 0]));
+        let colors = unique_colors(&transparent);
+        assert!(colors.is_empty());
+        assert!(unique_colors(&RgbaImage::new(0, 0)).is_empty());
+        for method in ReduceMethod::ALL {
+            assert!(method.reduce(&colors, 4).is_empty());
+            assert!(method.reduce(&colors, 0).is_empty());
+            assert!(method.reduce(&clusters(), 0).is_empty());
+        }
+    }
+}
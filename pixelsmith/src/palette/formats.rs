@@ -0,0 +1,278 @@
+use std::path::{Path, PathBuf};
+use toolbelt::Color;
+use super::to_rgba8;
+
+
+/// Palette file formats that can be imported and exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    /// GIMP `.gpl`, also what palettes are saved as inside a project
+    Gpl,
+    /// JASC / Paint Shop Pro `.pal`
+    Pal,
+    /// Paint.NET `.txt`
+    PaintNet,
+    /// One `RRGGBB` per line, `.hex`
+    Hex,
+}
+
+impl PaletteFormat {
+    pub const ALL: [PaletteFormat; 4] = [PaletteFormat::Gpl, PaletteFormat::Pal, PaletteFormat::PaintNet, PaletteFormat::Hex];
+
+    /// Picks the format from a file's extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        PaletteFormat::ALL.iter().copied().find(|f| f.extension() == extension)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Pal => "pal",
+            PaletteFormat::PaintNet => "txt",
+            PaletteFormat::Hex => "hex",
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum PaletteFileError {
+    Io(PathBuf, std::io::Error),
+    /// Saving to a file whose extension isn't one of `PaletteFormat`'s
+    UnknownFormat(PathBuf),
+    /// `line` is 1-based
+    Parse { path: PathBuf, line: usize, message: String },
+    /// The file parsed, but had no colors in it
+    Empty(PathBuf),
+}
+impl std::fmt::Display for PaletteFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteFileError::Io(path, e) => write!(f, "failed to access {}: {}", path.display(), e),
+            PaletteFileError::UnknownFormat(path) =>
+                write!(f, "{} isn't a palette file (expected .gpl, .pal, .txt or .hex)", path.display()),
+            PaletteFileError::Parse { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            PaletteFileError::Empty(path) => write!(f, "{} has no colors in it", path.display()),
+        }
+    }
+}
+
+
+/// A palette as read from or written to a file
+#[derive(Debug, Clone)]
+pub struct PaletteFile {
+    /// Only `.gpl` files store a name
+    pub name: Option<String>,
+    /// In RGB
+    pub colors: Vec<Color>,
+}
+
+pub(super) fn from_bytes(r: u8, g: u8, b: u8) -> Color {
+    Color::from_rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+}
+
+fn hex_string(color: &Color) -> String {
+    let [r, g, b, _] = to_rgba8(color);
+    format!("{:02X}{:02X}{:02X}", r, g, b)
+}
+
+/// Parses three whitespace-separated 0-255 components from the start of `line`.
+fn parse_rgb_triplet(line: &str) -> Result<Color, String> {
+    let mut parts = line.split_whitespace();
+    let mut component = || -> Result<u8, String> {
+        let part = parts.next().ok_or_else(|| "expected three color components".to_string())?;
+        part.parse::<u8>().map_err(|_| format!("'{}' isn't a color component from 0 to 255", part))
+    };
+    Ok(from_bytes(component()?, component()?, component()?))
+}
+
+impl PaletteFile {
+    /// Reads a palette, picking the format from the file's extension. Files with other extensions
+    /// are read as hex lists, which is what project palettes used to be.
+    pub fn load(path: &Path) -> Result<Self, PaletteFileError> {
+        let format = PaletteFormat::from_path(path).unwrap_or(PaletteFormat::Hex);
+        let text = std::fs::read_to_string(path).map_err(|e| PaletteFileError::Io(path.to_path_buf(), e))?;
+        let palette = PaletteFile::parse(&text, format).map_err(|(line, message)| PaletteFileError::Parse {
+            path: path.to_path_buf(), line, message
+        })?;
+        if palette.colors.is_empty() {
+            return Err(PaletteFileError::Empty(path.to_path_buf()));
+        }
+        Ok(palette)
+    }
+
+    /// Writes the palette, picking the format from the file's extension.
+    pub fn save(&self, path: &Path) -> Result<(), PaletteFileError> {
+        let format = PaletteFormat::from_path(path).ok_or_else(|| PaletteFileError::UnknownFormat(path.to_path_buf()))?;
+        std::fs::write(path, self.to_text(format)).map_err(|e| PaletteFileError::Io(path.to_path_buf(), e))
+    }
+
+    /// Parses the text of a palette file. Errors are returned with their 1-based line number.
+    pub fn parse(text: &str, format: PaletteFormat) -> Result<Self, (usize, String)> {
+        let mut name = None;
+        let mut colors = Vec::new();
+        let mut lines = text.lines().enumerate().map(|(idx, line)| (idx + 1, line.trim()));
+
+        match format {
+            PaletteFormat::Gpl => {
+                match lines.next() {
+                    Some((_, "GIMP Palette")) => {}
+                    _ => return Err((1, "missing 'GIMP Palette' header".to_string())),
+                }
+                for (line_num, line) in lines {
+                    if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+                        continue;
+                    }
+                    if let Some(palette_name) = line.strip_prefix("Name:") {
+                        name = Some(palette_name.trim().to_string());
+                        continue;
+                    }
+                    // anything after the components is the color's name, which isn't kept
+                    colors.push(parse_rgb_triplet(line).map_err(|e| (line_num, e))?);
+                }
+            }
+            PaletteFormat::Pal => {
+                match lines.next() {
+                    Some((_, "JASC-PAL")) => {}
+                    _ => return Err((1, "missing 'JASC-PAL' header".to_string())),
+                }
+                // version, then the color count, which is redundant with the lines that follow
+                lines.next();
+                lines.next();
+                for (line_num, line) in lines {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    colors.push(parse_rgb_triplet(line).map_err(|e| (line_num, e))?);
+                }
+            }
+            PaletteFormat::PaintNet | PaletteFormat::Hex => {
+                for (line_num, line) in lines {
+                    if line.is_empty() || line.starts_with(';') {
+                        continue;
+                    }
+                    let hex = line.trim_start_matches('#');
+                    // Paint.NET stores AARRGGBB, alpha is dropped
+                    let hex = match hex.get(2..) {
+                        Some(rgb) if format == PaletteFormat::PaintNet && hex.len() == 8 => rgb,
+                        _ => hex,
+                    };
+                    match u32::from_str_radix(hex, 16) {
+                        // from_str_radix takes a leading sign, which no palette has
+                        Ok(rgb) if hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                            let [_, r, g, b] = rgb.to_be_bytes();
+                            colors.push(from_bytes(r, g, b));
+                        }
+                        _ => return Err((line_num, format!("'{}' isn't a hex color", line))),
+                    }
+                }
+            }
+        }
+        Ok(PaletteFile { name, colors })
+    }
+
+    pub fn to_text(&self, format: PaletteFormat) -> String {
+        let mut text = String::new();
+        match format {
+            PaletteFormat::Gpl => {
+                text.push_str("GIMP Palette\n");
+                if let Some(name) = &self.name {
+                    text.push_str(&format!("Name: {}\n", name));
+                }
+                text.push_str("#\n");
+                for color in self.colors.iter() {
+                    let [r, g, b, _] = to_rgba8(color);
+                    text.push_str(&format!("{:3} {:3} {:3}\t#{}\n", r, g, b, hex_string(color)));
+                }
+            }
+            PaletteFormat::Pal => {
+                text.push_str(&format!("JASC-PAL\n0100\n{}\n", self.colors.len()));
+                for color in self.colors.iter() {
+                    let [r, g, b, _] = to_rgba8(color);
+                    text.push_str(&format!("{} {} {}\n", r, g, b));
+                }
+            }
+            PaletteFormat::PaintNet => {
+                text.push_str(&format!("; paint.net Palette File\n; Colors: {}\n", self.colors.len()));
+                for color in self.colors.iter() {
+                    text.push_str(&format!("FF{}\n", hex_string(color)));
+                }
+            }
+            PaletteFormat::Hex => {
+                for color in self.colors.iter() {
+                    text.push_str(&format!("{}\n", hex_string(color).to_lowercase()));
+                }
+            }
+        }
+        text
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(palette: &PaletteFile) -> Vec<[u8; 4]> {
+        palette.colors.iter().map(to_rgba8).collect()
+    }
+
+    fn sample() -> PaletteFile {
+        PaletteFile {
+            name: Some("Sample".to_string()),
+            colors: vec![from_bytes(0, 0, 0), from_bytes(0x1a, 0x2b, 0x3c), from_bytes(255, 128, 7)],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let palette = sample();
+        for format in PaletteFormat::ALL {
+            let parsed = PaletteFile::parse(&palette.to_text(format), format).unwrap();
+            assert_eq!(bytes(&parsed), bytes(&palette), "{:?}", format);
+        }
+        let gpl = PaletteFile::parse(&palette.to_text(PaletteFormat::Gpl), PaletteFormat::Gpl).unwrap();
+        assert_eq!(gpl.name.as_deref(), Some("Sample"));
+    }
+
+    #[test]
+    fn parse_gpl() {
+        let text = "GIMP Palette\nName: Test\nColumns: 4\n# comment\n\n 10  20  30\tSome Color\n255 0 0\n";
+        let palette = PaletteFile::parse(text, PaletteFormat::Gpl).unwrap();
+        assert_eq!(palette.name.as_deref(), Some("Test"));
+        assert_eq!(bytes(&palette), vec![[10, 20, 30, 255], [255, 0, 0, 255]]);
+
+        assert_eq!(PaletteFile::parse("10 20 30\n", PaletteFormat::Gpl).unwrap_err().0, 1);
+        assert_eq!(PaletteFile::parse("GIMP Palette\n10 20\n", PaletteFormat::Gpl).unwrap_err().0, 2);
+        assert_eq!(PaletteFile::parse("GIMP Palette\n10 20 300\n", PaletteFormat::Gpl).unwrap_err().0, 2);
+    }
+
+    #[test]
+    fn parse_pal() {
+        let palette = PaletteFile::parse("JASC-PAL\n0100\n2\n1 2 3\n4 5 6\n", PaletteFormat::Pal).unwrap();
+        assert_eq!(bytes(&palette), vec![[1, 2, 3, 255], [4, 5, 6, 255]]);
+        assert!(PaletteFile::parse("1 2 3\n", PaletteFormat::Pal).is_err());
+    }
+
+    #[test]
+    fn parse_hex() {
+        let text = "; paint.net Palette File\n80FF0000\n00FF00\n";
+        let palette = PaletteFile::parse(text, PaletteFormat::PaintNet).unwrap();
+        // alpha is dropped
+        assert_eq!(bytes(&palette), vec![[255, 0, 0, 255], [0, 255, 0, 255]]);
+
+        let palette = PaletteFile::parse("#0000ff\nABCDEF\n", PaletteFormat::Hex).unwrap();
+        assert_eq!(bytes(&palette), vec![[0, 0, 255, 255], [0xab, 0xcd, 0xef, 255]]);
+
+        assert_eq!(PaletteFile::parse("00ff00\n+12345\n", PaletteFormat::Hex).unwrap_err().0, 2);
+        assert_eq!(PaletteFile::parse("fff\n", PaletteFormat::Hex).unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn non_ascii_is_an_error() {
+        // eight bytes, but the multibyte character straddles the alpha prefix
+        assert_eq!(PaletteFile::parse("a\u{e9}12345\n", PaletteFormat::PaintNet).unwrap_err().0, 1);
+        assert_eq!(PaletteFile::parse("\u{e9}\u{e9}\u{e9}\n", PaletteFormat::Hex).unwrap_err().0, 1);
+    }
+}
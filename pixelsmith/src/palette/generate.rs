@@ -0,0 +1,191 @@
+use imgui::{ColorEditFlags, ItemHoveredFlags, TreeNodeFlags};
+use toolbelt::Color;
+use crate::pipeline::quantize::MAX_PALETTE_COLORS;
+
+
+/// Space a generated ramp steps through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RampSpace {
+    /// Even steps of perceived lightness, hue rotated in Oklab
+    Oklab,
+    /// Even steps of HSV value, hue rotated on the HSV wheel
+    Hsv,
+}
+
+impl RampSpace {
+    pub const ALL: [RampSpace; 2] = [RampSpace::Oklab, RampSpace::Hsv];
+}
+
+impl std::fmt::Display for RampSpace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            RampSpace::Oklab => "OKLab",
+            RampSpace::Hsv => "HSV",
+        })
+    }
+}
+
+/// Other hues to build ramps for alongside the base color's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Harmony {
+    None,
+    Complementary,
+    Analogous,
+    Triadic,
+}
+
+impl Harmony {
+    pub const ALL: [Harmony; 4] = [Harmony::None, Harmony::Complementary, Harmony::Analogous, Harmony::Triadic];
+
+    /// Hue offsets of each ramp from the base color, in turns
+    pub fn hue_offsets(self) -> &'static [f32] {
+        match self {
+            Harmony::None => &[0.0],
+            Harmony::Complementary => &[0.0, 0.5],
+            Harmony::Analogous => &[-1.0 / 12.0, 0.0, 1.0 / 12.0],
+            Harmony::Triadic => &[0.0, 1.0 / 3.0, 2.0 / 3.0],
+        }
+    }
+}
+
+impl std::fmt::Display for Harmony {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Harmony::None => "None",
+            Harmony::Complementary => "Complementary",
+            Harmony::Analogous => "Analogous",
+            Harmony::Triadic => "Triadic",
+        })
+    }
+}
+
+
+/// Builds shading ramps from a base color, darkest to lightest.
+pub struct RampGenerator {
+    pub steps: u32,
+    /// Degrees the hue rotates from the darkest step to the lightest
+    pub hue_shift: f32,
+    /// How much saturation peaks in the midtones and falls off toward the ends, negative to
+    /// saturate the ends instead
+    pub saturation_curve: f32,
+    /// Lightness of the darkest and lightest steps, 0-1 in OKLab L or HSV value
+    pub value_range: [f32; 2],
+    pub space: RampSpace,
+    pub harmony: Harmony,
+}
+
+impl RampGenerator {
+    pub fn new() -> Self {
+        RampGenerator {
+            steps: 5,
+            hue_shift: 30.0,
+            saturation_curve: 0.3,
+            value_range: [0.2, 0.95],
+            space: RampSpace::Oklab,
+            harmony: Harmony::None,
+        }
+    }
+
+    /// One ramp per hue of the harmony, each `steps` RGB colors long.
+    pub fn generate(&self, base: &Color) -> Vec<Vec<Color>> {
+        self.harmony.hue_offsets().iter().map(|offset| {
+            (0..self.steps).map(|i| {
+                let t = if self.steps > 1 { i as f32 / (self.steps - 1) as f32 } else { 0.5 };
+                self.step(base, *offset, t)
+            }).collect()
+        }).collect()
+    }
+
+    /// The color `t` of the way along the ramp whose hue is offset from `base` by `hue_offset` turns.
+    fn step(&self, base: &Color, hue_offset: f32, t: f32) -> Color {
+        let lightness = self.value_range[0] + (self.value_range[1] - self.value_range[0]) * t;
+        let saturation_scale = (1.0 - self.saturation_curve * (2.0 * t - 1.0).powi(2)).max(0.0);
+        let hue_turns = hue_offset + self.hue_shift / 360.0 * (t - 0.5);
+
+        match self.space {
+            RampSpace::Hsv => {
+                let [h, s, _] = *base.to_hsv().components_3();
+                Color::from_hsv((h + hue_turns).rem_euclid(1.0), (s * saturation_scale).clamp(0.0, 1.0), lightness).to_rgb()
+            }
+            RampSpace::Oklab => {
+                let [_, a, b] = *base.to_oklab().components_3();
+                let chroma = a.hypot(b) * saturation_scale;
+                let hue = b.atan2(a) + hue_turns * std::f32::consts::TAU;
+                fit_gamut(lightness, chroma, hue)
+            }
+        }
+    }
+
+    /// Draws the generator's settings and a preview. Returns the generated colors when they
+    /// should be appended to the palette, which has room for `room` more colors.
+    pub fn draw(&mut self, ui: &imgui::Ui, base: &Color, room: usize) -> Option<Vec<Color>> {
+        if !ui.collapsing_header("Generate Ramp", TreeNodeFlags::empty()) {
+            return None;
+        }
+        let mut space_idx = RampSpace::ALL.iter().position(|s| *s == self.space).unwrap();
+        if ui.combo("Space##ramp-gen", &mut space_idx, &RampSpace::ALL, |s| s.to_string().into()) {
+            self.space = RampSpace::ALL[space_idx];
+        }
+        let mut harmony_idx = Harmony::ALL.iter().position(|h| *h == self.harmony).unwrap();
+        if ui.combo("Harmony##ramp-gen", &mut harmony_idx, &Harmony::ALL, |h| h.to_string().into()) {
+            self.harmony = Harmony::ALL[harmony_idx];
+        }
+        ui.slider("Steps##ramp-gen", 2, 16, &mut self.steps);
+        ui.slider("Hue Shift##ramp-gen", -180.0, 180.0, &mut self.hue_shift);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Degrees the hue turns from the darkest step to the lightest");
+        }
+        ui.slider("Saturation Curve##ramp-gen", -1.0, 1.0, &mut self.saturation_curve);
+        if ui.is_item_hovered() {
+            ui.tooltip_text("Positive keeps saturation in the midtones and fades it toward the ends");
+        }
+        ui.slider("Darkest##ramp-gen", 0.0, 1.0, &mut self.value_range[0]);
+        ui.slider("Lightest##ramp-gen", 0.0, 1.0, &mut self.value_range[1]);
+
+        let ramps = self.generate(base);
+        for (row, ramp) in ramps.iter().enumerate() {
+            for (i, color) in ramp.iter().enumerate() {
+                if i > 0 {
+                    ui.same_line();
+                }
+                ui.color_button_config(format!("##ramp-gen-{}-{}", row, i), *color.components_4())
+                    .flags(ColorEditFlags::NO_ALPHA)
+                    .size([18.0, 18.0])
+                    .build();
+            }
+        }
+        let count: usize = ramps.iter().map(|ramp| ramp.len()).sum();
+        let too_many = count > room;
+        {
+            let _disabled = ui.begin_disabled(too_many);
+            if ui.button("Append to Palette##ramp-gen") {
+                return Some(ramps.into_iter().flatten().collect());
+            }
+        }
+        if too_many && ui.is_item_hovered_with_flags(ItemHoveredFlags::ALLOW_WHEN_DISABLED) {
+            ui.tooltip_text(format!("Palettes can have at most {} colors, this would add {} with room for {}",
+                                    MAX_PALETTE_COLORS, count, room));
+        }
+        None
+    }
+}
+
+
+/// Converts OKLab lightness, chroma and hue (in radians) to RGB, reducing the chroma until the
+/// color fits in sRGB.
+fn fit_gamut(lightness: f32, chroma: f32, hue: f32) -> Color {
+    let to_rgb = |c: f32| Color::from_oklab(lightness, c * hue.cos(), c * hue.sin()).to_rgb();
+    let in_gamut = |color: &Color| color.components_3().iter().all(|c| (-1e-4..=1.0 + 1e-4).contains(c));
+
+    let mut color = to_rgb(chroma);
+    if !in_gamut(&color) {
+        let (mut low, mut high) = (0.0, chroma);
+        for _ in 0..16 {
+            let mid = (low + high) / 2.0;
+            if in_gamut(&to_rgb(mid)) { low = mid } else { high = mid }
+        }
+        color = to_rgb(low);
+    }
+    let [r, g, b] = color.components_3().map(|c| c.clamp(0.0, 1.0));
+    Color::from_rgb(r, g, b)
+}
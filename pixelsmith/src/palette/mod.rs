@@ -0,0 +1,603 @@
+use imgui::{StyleColor, StyleVar, TableColumnFlags, TableColumnSetup, TableFlags, TableRowFlags};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use toolbelt::{Color, ColorSpace};
+use serde_derive::{Serialize, Deserialize};
+use crate::pipeline::quantize::MAX_PALETTE_COLORS;
+use crate::panel::ERROR_COLOR;
+use crate::project::ProjectData;
+
+pub mod extract;
+pub mod formats;
+pub mod generate;
+pub use formats::{PaletteFile, PaletteFileError, PaletteFormat};
+pub use generate::RampGenerator;
+
+
+/// How the distance between a rendered color and a palette color is measured when quantizing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorMetric {
+    /// Straight distance between sRGB values
+    Rgb = 0,
+    /// CIE76 distance in CIELAB
+    Lab = 1,
+    /// Distance in Oklab, which keeps hues more stable than CIELAB
+    Oklab = 2,
+}
+
+impl ColorMetric {
+    pub const ALL: [ColorMetric; 3] = [ColorMetric::Rgb, ColorMetric::Lab, ColorMetric::Oklab];
+}
+
+impl std::fmt::Display for ColorMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            ColorMetric::Rgb => "RGB",
+            ColorMetric::Lab => "Lab",
+            ColorMetric::Oklab => "OKLab",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuantizeDither { None, Bayer2, Bayer4, Bayer8, FloydSteinberg }
+
+impl QuantizeDither {
+    pub const ALL: [QuantizeDither; 5] = [QuantizeDither::None, QuantizeDither::Bayer2, QuantizeDither::Bayer4,
+                                          QuantizeDither::Bayer8, QuantizeDither::FloydSteinberg];
+
+    /// Width of the Bayer matrix, 0 if this isn't ordered dithering
+    pub fn bayer_size(self) -> u32 {
+        match self {
+            QuantizeDither::Bayer2 => 2,
+            QuantizeDither::Bayer4 => 4,
+            QuantizeDither::Bayer8 => 8,
+            QuantizeDither::None | QuantizeDither::FloydSteinberg => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for QuantizeDither {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            QuantizeDither::None => "None",
+            QuantizeDither::Bayer2 => "Bayer 2x2",
+            QuantizeDither::Bayer4 => "Bayer 4x4",
+            QuantizeDither::Bayer8 => "Bayer 8x8",
+            QuantizeDither::FloydSteinberg => "Floyd-Steinberg",
+        })
+    }
+}
+
+/// Snaps every rendered sprite pixel to the nearest color in the palette, to check that lighting
+/// keeps a sprite inside it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuantizeSettings {
+    pub enabled: bool,
+    pub metric: ColorMetric,
+    pub dither: QuantizeDither,
+    /// Strength of ordered dithering, in sRGB units
+    pub dither_spread: f32,
+}
+
+impl Default for QuantizeSettings {
+    fn default() -> Self {
+        QuantizeSettings {
+            enabled: false,
+            metric: ColorMetric::Oklab,
+            dither: QuantizeDither::None,
+            dither_spread: 0.1,
+        }
+    }
+}
+
+
+/// Most color mappings a palette swap can have
+pub const MAX_SWAP_COLORS: u32 = 256;
+/// Width and height of the swap lookup texture, which has a texel for every 16-bit color hash
+pub const SWAP_TABLE_SIZE: u32 = 256;
+/// Hash multipliers tried before giving up on fitting every mapping in its own texel. Each try
+/// succeeds more often than not even with `MAX_SWAP_COLORS` mappings.
+const SWAP_HASH_ATTEMPTS: u32 = 64;
+
+/// Hashes a 24-bit color to its texel in the swap lookup texture, and a check value stored there
+/// to tell it from the other colors with the same hash. Multiplying by an odd number modulo
+/// 2^24 can be undone, so no two colors get both the same texel and the same check value.
+fn swap_hash(rgb: u32, multiplier: u32) -> (usize, u8) {
+    let hash = rgb.wrapping_mul(multiplier) & 0xFF_FFFF;
+    ((hash >> 8) as usize, (hash & 0xFF) as u8)
+}
+
+/// The `SWAP_HASH_ATTEMPTS` multipliers `SwapSettings::bake` tries, all odd.
+fn swap_hash_multiplier(attempt: u32) -> u32 {
+    0x9E37_79B1u32.wrapping_add(attempt.wrapping_mul(0x632B_E5AA)) | 1
+}
+
+/// The number that undoes multiplying by the odd `multiplier`, found by Newton's method. Each
+/// step doubles the number of correct low bits, starting from the 3 that `multiplier` has.
+fn swap_hash_inverse(multiplier: u32) -> u32 {
+    let mut inverse = multiplier;
+    for _ in 0..4 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(multiplier.wrapping_mul(inverse)));
+    }
+    inverse
+}
+
+/// The active palette swap baked into a lookup texture
+pub struct SwapTable {
+    /// RGBA8 pixels, `SWAP_TABLE_SIZE` square. Each texel holds the color a hash's colors are
+    /// drawn with in RGB, and in alpha the check value of the one color it applies to.
+    pub pixels: Vec<u8>,
+    /// Multiplier the sprite shader hashes colors with
+    pub multiplier: u32,
+}
+
+/// 8-bit RGBA of a color, as it would be stored in a sprite's albedo
+fn to_rgba8(color: &Color) -> [u8; 4] {
+    let [r, g, b, a] = *color.to_rgb().components_4();
+    [r, g, b, a].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorMapping {
+    pub from: Color,
+    pub to: Color,
+}
+
+/// A named recolor of a sprite. Albedo texels that exactly match a mapping's `from` color are
+/// drawn with its `to` color instead, before lighting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaletteSwap {
+    pub name: String,
+    #[serde(default)]
+    pub mappings: Vec<ColorMapping>,
+}
+
+/// A sprite's palette swaps, saved in `scene.yaml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwapSettings {
+    pub sets: Vec<PaletteSwap>,
+    /// Index into `sets` of the swap applied to the sprite, `None` to show its original colors
+    pub active: Option<usize>,
+}
+
+impl SwapSettings {
+    pub fn active_set(&self) -> Option<&PaletteSwap> {
+        self.active.and_then(|idx| self.sets.get(idx))
+    }
+
+    /// Number of mappings the sprite shader should apply.
+    pub fn active_count(&self) -> u32 {
+        self.active_set().map_or(0, |set| set.mappings.len().min(MAX_SWAP_COLORS as usize) as u32)
+    }
+
+    /// Bakes the active set into a lookup texture keyed by color, so the sprite shader finds a
+    /// texel's replacement with a single read. If no multiplier gives every source color its own
+    /// texel, mappings that collide with an earlier one are left out.
+    pub fn bake(&self) -> SwapTable {
+        let mappings: Vec<(u32, [u8; 4])> = self.active_set().map_or(Vec::new(), |set| {
+            set.mappings.iter().take(MAX_SWAP_COLORS as usize).map(|mapping| {
+                let [r, g, b, _] = to_rgba8(&mapping.from);
+                ((r as u32) << 16 | (g as u32) << 8 | b as u32, to_rgba8(&mapping.to))
+            }).collect()
+        });
+        let fits = |multiplier: u32| {
+            let mut owners = HashMap::new();
+            mappings.iter().all(|(rgb, _)| *owners.entry(swap_hash(*rgb, multiplier).0).or_insert(*rgb) == *rgb)
+        };
+        let multiplier = (0..SWAP_HASH_ATTEMPTS).map(swap_hash_multiplier).find(|m| fits(*m))
+            .unwrap_or_else(|| swap_hash_multiplier(0));
+
+        // texels no mapping claims keep the one color whose check value is 0 as it is
+        let inverse = swap_hash_inverse(multiplier);
+        let texels = (SWAP_TABLE_SIZE * SWAP_TABLE_SIZE) as usize;
+        let mut pixels = Vec::with_capacity(texels * 4);
+        for slot in 0..texels as u32 {
+            let rgb = (slot << 8).wrapping_mul(inverse);
+            pixels.extend_from_slice(&[(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 0]);
+        }
+        // the first mapping of a color wins, as it does in the swap list
+        let mut claimed = vec![false; texels];
+        for (rgb, [r, g, b, _]) in mappings {
+            let (slot, check) = swap_hash(rgb, multiplier);
+            if !claimed[slot] {
+                claimed[slot] = true;
+                pixels[slot * 4..slot * 4 + 4].copy_from_slice(&[r, g, b, check]);
+            }
+        }
+        SwapTable { pixels, multiplier }
+    }
+}
+
+
+/// Drag and drop payload type of a swatch being reordered, carrying its index
+const SWATCH_PAYLOAD: &str = "PALETTE_SWATCH";
+
+pub struct PaletteEditor {
+    pub colors: Vec<Color>,
+    pub selected_idx: usize,
+    /// Name the palette is saved under in the project's palette directory
+    pub name: String,
+    /// Project directory, import and export paths are relative to it
+    project_dir: Option<PathBuf>,
+    /// The project's palette directory, nothing can be saved until a project is open
+    palettes_dir: Option<PathBuf>,
+    /// Names of the palettes in `palettes_dir`
+    saved: Vec<String>,
+    /// Path typed into the import/export field
+    file_path: String,
+    /// Result of the last save, load, import or export
+    status: Option<Result<String, String>>,
+    /// Builds ramps from the selected color to append to the palette
+    generator: RampGenerator,
+}
+
+impl PaletteEditor {
+    pub fn new() -> Self {
+        PaletteEditor {
+            colors: vec! {
+                Color::from_hsv(0.0, 0.0, 0.0),
+                Color::from_hsv(0.0, 0.0, 0.2),
+                Color::from_hsv(0.0, 0.0, 0.4),
+                Color::from_hsv(0.0, 0.0, 0.6),
+                Color::from_hsv(0.0, 0.0, 0.8),
+                Color::from_hsv(0.0, 0.0, 1.0),
+
+                Color::from_hsv(0.0/12.0, 0.8, 0.7),
+                Color::from_hsv(1.0/12.0, 0.8, 0.7),
+                Color::from_hsv(2.0/12.0, 0.8, 0.7),
+                Color::from_hsv(3.0/12.0, 0.8, 0.7),
+                Color::from_hsv(4.0/12.0, 0.8, 0.7),
+                Color::from_hsv(5.0/12.0, 0.8, 0.7),
+                Color::from_hsv(6.0/12.0, 0.8, 0.7),
+                Color::from_hsv(7.0/12.0, 0.8, 0.7),
+                Color::from_hsv(8.0/12.0, 0.8, 0.7),
+                Color::from_hsv(9.0/12.0, 0.8, 0.7),
+                Color::from_hsv(10.0/12.0, 0.8, 0.7),
+                Color::from_hsv(11.0/12.0, 0.8, 0.7),
+            },
+            selected_idx: 0,
+            name: "Default".to_string(),
+            project_dir: None,
+            palettes_dir: None,
+            saved: Vec::new(),
+            file_path: String::new(),
+            status: None,
+            generator: RampGenerator::new(),
+        }
+    }
+
+    /// Points the editor at a project's palettes and loads its starting palette: the one named in the
+    /// manifest if there is one, otherwise the first saved palette.
+    pub fn open_project(&mut self, project: &ProjectData) {
+        self.project_dir = Some(project.path.clone());
+        self.palettes_dir = Some(project.palettes_dir());
+        self.refresh_saved();
+
+        let start = project.palette_path()
+            .or_else(|| self.saved.first().map(|name| project.palettes_dir().join(format!("{}.gpl", name))));
+        if let Some(path) = start {
+            if let Err(e) = self.load_file(&path) {
+                self.status = Some(Err(e.to_string()));
+            }
+        }
+    }
+
+    /// Rescans the project's palette directory.
+    fn refresh_saved(&mut self) {
+        self.saved.clear();
+        let entries = match self.palettes_dir.as_ref().map(std::fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if PaletteFormat::from_path(&path) == Some(PaletteFormat::Gpl) {
+                if let Some(stem) = path.file_stem() {
+                    self.saved.push(stem.to_string_lossy().to_string());
+                }
+            }
+        }
+        self.saved.sort();
+    }
+
+    /// Path of the palette file for `name` in the project's palette directory
+    fn saved_path(&self, name: &str) -> Option<PathBuf> {
+        self.palettes_dir.as_ref().map(|dir| dir.join(format!("{}.{}", name, PaletteFormat::Gpl.extension())))
+    }
+
+    /// Resolves a typed path against the project directory.
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        match &self.project_dir {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// Saves the palette into the project's palette directory under its name.
+    pub fn save(&mut self) -> Result<PathBuf, PaletteFileError> {
+        let path = match self.saved_path(&self.name) {
+            Some(path) => path,
+            None => return Err(PaletteFileError::Io(PathBuf::from(&self.name),
+                                                    std::io::Error::other("no project is open"))),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| PaletteFileError::Io(dir.to_path_buf(), e))?;
+        }
+        self.save_file(&path)?;
+        self.refresh_saved();
+        Ok(path)
+    }
+
+    /// Writes the palette to `path` in the format matching its extension.
+    pub fn save_file(&self, path: &Path) -> Result<(), PaletteFileError> {
+        PaletteFile {
+            name: Some(self.name.clone()),
+            colors: self.colors.iter().map(|c| c.to_rgb()).collect(),
+        }.save(path)
+    }
+
+    /// Inserts a copy of the selected color after it and selects the copy.
+    pub fn add_color(&mut self) {
+        let color = self.colors.get(self.selected_idx).copied().unwrap_or_else(|| Color::from_hsv(0.0, 0.0, 0.5));
+        let idx = (self.selected_idx + 1).min(self.colors.len());
+        self.colors.insert(idx, color);
+        self.selected_idx = idx;
+    }
+
+    /// Removes the selected color, always leaving at least one.
+    pub fn remove_color(&mut self) {
+        if self.colors.len() > 1 && self.selected_idx < self.colors.len() {
+            self.colors.remove(self.selected_idx);
+            self.selected_idx = self.selected_idx.min(self.colors.len() - 1);
+        }
+    }
+
+    /// Moves the color at `from` to `to`, shifting the ones in between. The selection follows the moved color.
+    pub fn move_color(&mut self, from: usize, to: usize) {
+        if from >= self.colors.len() || to >= self.colors.len() || from == to {
+            return;
+        }
+        let color = self.colors.remove(from);
+        self.colors.insert(to, color);
+        self.selected_idx = to;
+    }
+
+    /// Replaces the palette's colors, keeping its name.
+    pub fn replace_colors(&mut self, colors: Vec<Color>) {
+        self.colors = colors.iter().map(|c| c.to_hsv()).collect();
+        self.selected_idx = 0;
+    }
+
+    /// Replaces the palette with colors read from `path`, in any of the `PaletteFormat`s.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), PaletteFileError> {
+        let palette = PaletteFile::load(path)?;
+        self.name = palette.name
+            .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+            .unwrap_or_else(|| "Untitled".to_string());
+        self.colors = palette.colors.iter().map(|c| c.to_hsv()).collect();
+        self.selected_idx = 0;
+        Ok(())
+    }
+
+    /// Saving and loading project palettes, and importing and exporting palette files.
+    fn draw_file_controls(&mut self, ui: &imgui::Ui) {
+        let has_project = self.palettes_dir.is_some();
+        let _disabled = ui.begin_disabled(!has_project);
+
+        let mut selected = self.saved.iter().position(|name| *name == self.name).unwrap_or(usize::MAX);
+        let preview = if selected == usize::MAX { "(unsaved)" } else { self.saved[selected].as_str() };
+        if let Some(_combo) = ui.begin_combo("Palettes", preview) {
+            for (i, name) in self.saved.iter().enumerate() {
+                if ui.selectable_config(name).selected(i == selected).build() {
+                    selected = i;
+                }
+            }
+            drop(_combo);
+            if selected != usize::MAX && self.saved.get(selected) != Some(&self.name) {
+                let path = self.saved_path(&self.saved[selected]).unwrap();
+                self.status = Some(self.load_file(&path).map(|_| format!("Loaded {}", path.display())).map_err(|e| e.to_string()));
+            }
+        }
+        ui.input_text("Name##palette", &mut self.name).build();
+        if ui.button("Save##palette") {
+            self.status = Some(self.save().map(|path| format!("Saved {}", path.display())).map_err(|e| e.to_string()));
+        }
+        drop(_disabled);
+
+        ui.input_text("File##palette", &mut self.file_path)
+            .hint("palette.gpl / .pal / .txt / .hex")
+            .build();
+        if ui.button("Import##palette") {
+            let path = self.resolve_path(&self.file_path);
+            self.status = Some(self.load_file(&path).map(|_| format!("Imported {}", path.display())).map_err(|e| e.to_string()));
+        }
+        ui.same_line();
+        if ui.button("Export##palette") {
+            let path = self.resolve_path(&self.file_path);
+            self.status = Some(self.save_file(&path).map(|_| format!("Exported {}", path.display())).map_err(|e| e.to_string()));
+        }
+
+        if let Some(status) = &self.status {
+            let _wrap = ui.push_text_wrap_pos();
+            match status {
+                Ok(message) => ui.text_disabled(message),
+                Err(message) => ui.text_colored(ERROR_COLOR, message),
+            }
+        }
+    }
+
+    pub fn draw(&mut self, ui: &imgui::Ui) {
+        ui.window("Palette").build(|| {
+            if let Some(_token) = ui.begin_table_with_flags("##palette-table-top", 3,
+                                                            TableFlags::NO_PAD_INNER_X) {
+                ui.table_setup_column_with(TableColumnSetup {
+                    name: "##one",
+                    init_width_or_weight: 1000.0,
+                    flags: TableColumnFlags::WIDTH_STRETCH,
+                    ..Default::default()
+                });
+                ui.table_setup_column_with(TableColumnSetup {
+                    name: "##two",
+                    init_width_or_weight: 20.0,
+                    flags: TableColumnFlags::WIDTH_FIXED,
+                    ..Default::default()
+                });
+                ui.table_setup_column_with(TableColumnSetup {
+                    name: "##three",
+                    init_width_or_weight: 20.0,
+                    flags: TableColumnFlags::WIDTH_FIXED,
+                    ..Default::default()
+                });
+                ui.table_next_row();
+                ui.table_next_column();
+                ui.text("Palette");
+                ui.table_next_column();
+                if ui.button("+") {
+                    self.add_color();
+                }
+                ui.table_next_column();
+                if ui.button("-") {
+                    self.remove_color();
+                }
+            }
+
+            let mut moved = None;
+            let palette_width = ui.current_column_width() - 4.0;
+            let swatch_size = 22.0;
+            let swatches_per_row = ((palette_width / (swatch_size + 3.0)).floor() as usize).max(1);
+
+            if let Some(_token) = ui.begin_table_with_sizing("##palette-table",
+                                                             50,
+                                                             TableFlags::SIZING_FIXED_SAME | TableFlags::NO_PAD_INNER_X,
+                                                             [0.0, 0.0],
+                                                             swatch_size) {
+                for i in 0..self.colors.len() {
+                    let _id = ui.push_id(i.to_string());
+                    let _s1 = ui.push_style_var(StyleVar::FrameBorderSize(0.5));
+                    let _s2 = ui.push_style_color(StyleColor::Border, [1.0, 1.0, 1.0, 0.1]);
+
+                    if i % swatches_per_row == 0 {
+                        ui.table_next_row_with_height(TableRowFlags::empty(), swatch_size + 5.0);
+                    }
+
+                    ui.table_next_column();
+
+                    let [x, y] = ui.cursor_pos();
+                    if i == self.selected_idx {
+                        ui.set_cursor_pos([x - 3.0, y - 5.0]);
+                    } else {
+                        ui.set_cursor_pos([x + 1.0, y - 2.0]);
+                    }
+
+                    if ui.color_button_config("##palette", *self.colors[i].to_hsv().components_4())
+                        .flags(imgui::ColorEditFlags::INPUT_HSV | imgui::ColorEditFlags::NO_PICKER | imgui::ColorEditFlags::NO_ALPHA | imgui::ColorEditFlags::NO_TOOLTIP)
+                        .size(if i == self.selected_idx { [swatch_size + 6.0, swatch_size + 6.0] } else { [swatch_size - 2.0, swatch_size - 2.0] })
+                        .build()
+                    {
+                        self.selected_idx = i;
+                    }
+
+                    // dragging a swatch onto another moves it there
+                    if let Some(tooltip) = ui.drag_drop_source_config(SWATCH_PAYLOAD).begin_payload(i) {
+                        ui.color_button_config("##dragged", *self.colors[i].to_hsv().components_4())
+                            .flags(imgui::ColorEditFlags::INPUT_HSV | imgui::ColorEditFlags::NO_ALPHA | imgui::ColorEditFlags::NO_TOOLTIP)
+                            .size([swatch_size, swatch_size])
+                            .build();
+                        tooltip.end();
+                    }
+
+                    if let Some(target) = ui.drag_drop_target() {
+                        if let Some(Ok(payload)) = target.accept_payload::<usize, _>(SWATCH_PAYLOAD, imgui::DragDropFlags::empty()) {
+                            moved = Some((payload.data, i));
+                        }
+                        else if let Some(payload) = unsafe { target.accept_payload_unchecked("_COL3F", imgui::DragDropFlags::SOURCE_NO_PREVIEW_TOOLTIP) } {
+                            assert_eq!(payload.size, std::mem::size_of::<f32>() * 3);
+                            let [r, g, b] = unsafe { *payload.data.cast::<[f32; 3]>() };
+                            self.colors[i] = Color::from_rgb(r, g, b).to_hsv();
+                        }
+                    }
+                }
+            }
+            if let Some((from, to)) = moved {
+                self.move_color(from, to);
+            }
+
+            ui.separator();
+            self.draw_file_controls(ui);
+
+            if let Some(base) = self.colors.get(self.selected_idx).copied() {
+                let room = MAX_PALETTE_COLORS.saturating_sub(self.colors.len());
+                if let Some(generated) = self.generator.draw(ui, &base, room) {
+                    self.colors.extend(generated.iter().map(|c| c.to_hsv()));
+                }
+            }
+        });
+
+        ui.window("Color Picker").build(|| {
+            // make sure source is in HSV...
+            self.colors[self.selected_idx].convert(ColorSpace::HSV);
+            // ...then get a mutable reference to the source color
+            let color = &mut self.colors[self.selected_idx];
+
+            ui.color_button_config("##current-color", *color.components_4())
+                .flags(imgui::ColorEditFlags::INPUT_HSV | imgui::ColorEditFlags::NO_PICKER | imgui::ColorEditFlags::NO_ALPHA | imgui::ColorEditFlags::NO_TOOLTIP)
+                .size([ui.current_column_width(), 24.0])
+                .build();
+
+            ui.set_next_item_width(ui.window_content_region_max()[0]);
+            ui.color_picker3_config("##picker", color.components_3_mut())
+                .flags(imgui::ColorEditFlags::INPUT_HSV | imgui::ColorEditFlags::NO_SMALL_PREVIEW | imgui::ColorEditFlags::NO_SIDE_PREVIEW | imgui::ColorEditFlags::FLOAT)
+                .build();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::formats::from_bytes;
+
+    /// What the sprite shader's `swapColor` reads from the table for a color
+    fn look_up(table: &SwapTable, [r, g, b]: [u8; 3]) -> [u8; 3] {
+        let (slot, check) = swap_hash((r as u32) << 16 | (g as u32) << 8 | b as u32, table.multiplier);
+        let texel = &table.pixels[slot * 4..slot * 4 + 4];
+        if texel[3] == check { [texel[0], texel[1], texel[2]] } else { [r, g, b] }
+    }
+
+    #[test]
+    fn swap_table_replaces_only_source_colors() {
+        // a full set of neighboring grays and reds, which hash close together
+        let mappings: Vec<ColorMapping> = (0..(MAX_SWAP_COLORS / 2) as u8).flat_map(|i| [
+            ColorMapping { from: from_bytes(i, i, i), to: from_bytes(0, 0, i) },
+            ColorMapping { from: from_bytes(i, 0, 0), to: from_bytes(0, i, 0) },
+        ]).collect();
+        let swaps = SwapSettings { sets: vec![PaletteSwap { name: "Swap".to_string(), mappings }], active: Some(0) };
+        let table = swaps.bake();
+        for i in 0..(MAX_SWAP_COLORS / 2) as u8 {
+            assert_eq!(look_up(&table, [i, i, i]), [0, 0, i]);
+            assert_eq!(look_up(&table, [i, 0, 0]), [0, i, 0]);
+        }
+        for rgb in [[255, 255, 255], [200, 0, 0], [1, 2, 3], [0, 0, 1], [0, 1, 0]] {
+            assert_eq!(look_up(&table, rgb), rgb);
+        }
+        // every color with the same texel as some mapping's source is left alone
+        for check in 0..=255u8 {
+            let rgb = (((swap_hash(0x202020, table.multiplier).0 as u32) << 8 | check as u32)
+                .wrapping_mul(swap_hash_inverse(table.multiplier))) & 0xFF_FFFF;
+            let rgb = [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8];
+            let expected = if rgb == [0x20, 0x20, 0x20] { [0, 0, 0x20] } else { rgb };
+            assert_eq!(look_up(&table, rgb), expected);
+        }
+    }
+
+    #[test]
+    fn no_active_swap_keeps_every_color() {
+        let table = SwapSettings::default().bake();
+        for rgb in [[0, 0, 0], [255, 255, 255], [12, 34, 56]] {
+            assert_eq!(look_up(&table, rgb), rgb);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use image::RgbaImage;
+use imgui::{ColorEditFlags, Condition, ListClipper, Ui};
+use toolbelt::Color;
+use crate::palette::extract::{self, rgb_color, ColorCount, ColorSort, ReduceMethod};
+use crate::pipeline::quantize::MAX_PALETTE_COLORS;
+
+const SWATCH_SIZE: f32 = 16.0;
+const REDUCED_PER_ROW: usize = 12;
+
+/// Lists the colors used by the sprite's albedo map and builds palettes from them, either as-is
+/// or reduced to fewer colors.
+pub struct PaletteExtractPanel {
+    window_open: bool,
+    /// Distinct colors of the current sprite, sorted by `sort`
+    colors: Vec<ColorCount>,
+    sort: ColorSort,
+    method: ReduceMethod,
+    target_count: u32,
+    reduced: Vec<[u8; 3]>,
+    /// Set when a palette should replace the palette editor's, taken by the app with `take_palette`
+    palette: Option<Vec<Color>>,
+}
+
+impl PaletteExtractPanel {
+    pub fn new() -> Self {
+        PaletteExtractPanel {
+            window_open: false,
+            colors: Vec::new(),
+            sort: ColorSort::Hue,
+            method: ReduceMethod::MedianCut,
+            target_count: 16,
+            reduced: Vec::new(),
+            palette: None,
+        }
+    }
+
+    /// Counts the colors of a newly loaded sprite's albedo, discarding any reduced palette.
+    pub fn set_image(&mut self, image: &Arc<RgbaImage>) {
+        self.colors = extract::unique_colors(image);
+        extract::sort_colors(&mut self.colors, self.sort);
+        self.reduced.clear();
+    }
+
+    /// Returns the palette the user chose to use, once.
+    pub fn take_palette(&mut self) -> Option<Vec<Color>> {
+        self.palette.take()
+    }
+}
+
+impl super::Panel for PaletteExtractPanel {
+    fn is_open(&self) -> bool {
+        self.window_open
+    }
+
+    fn open_ref(&mut self) -> &mut bool {
+        &mut self.window_open
+    }
+
+    fn draw(&mut self, ui: &mut Ui) {
+        if !self.window_open {
+            return;
+        }
+        let mut window_open = self.window_open;
+        ui.window("Palette from Sprite")
+            .size([300.0, 420.0], Condition::FirstUseEver)
+            .opened(&mut window_open)
+            .build(|| {
+                if self.colors.is_empty() {
+                    ui.text_disabled("No sprite loaded");
+                    return;
+                }
+
+                let total: u64 = self.colors.iter().map(|c| c.count as u64).sum();
+                ui.text(format!("{} colors in {} opaque pixels", self.colors.len(), total));
+                let mut sort_idx = ColorSort::ALL.iter().position(|s| *s == self.sort).unwrap();
+                if ui.combo("Sort", &mut sort_idx, &ColorSort::ALL, |s| s.to_string().into()) {
+                    self.sort = ColorSort::ALL[sort_idx];
+                    extract::sort_colors(&mut self.colors, self.sort);
+                }
+
+                ui.child_window("sprite colors").size([0.0, 160.0]).border(true).build(|| {
+                    // legacy art can have tens of thousands of colors, so only the visible rows are built
+                    for i in ListClipper::new(self.colors.len() as i32).begin(ui).iter() {
+                        let i = i as usize;
+                        let color = &self.colors[i];
+                        let [r, g, b] = color.rgb;
+                        ui.color_button_config(format!("##color{}", i), *rgb_color(color.rgb).components_4())
+                            .flags(ColorEditFlags::NO_ALPHA)
+                            .size([SWATCH_SIZE, SWATCH_SIZE])
+                            .build();
+                        ui.same_line();
+                        ui.text(format!("#{:02X}{:02X}{:02X}  {} px ({:.1}%)", r, g, b, color.count,
+                                        color.count as f64 / total as f64 * 100.0));
+                    }
+                });
+
+                let too_many = self.colors.len() > MAX_PALETTE_COLORS;
+                {
+                    let _disabled = ui.begin_disabled(too_many);
+                    if ui.button("Use These Colors") {
+                        self.palette = Some(self.colors.iter().map(|c| rgb_color(c.rgb)).collect());
+                    }
+                }
+                if too_many && ui.is_item_hovered() {
+                    ui.tooltip_text(format!("Palettes can have at most {} colors, reduce them first", MAX_PALETTE_COLORS));
+                }
+                ui.separator();
+
+                let mut method_idx = ReduceMethod::ALL.iter().position(|m| *m == self.method).unwrap();
+                if ui.combo("Method", &mut method_idx, &ReduceMethod::ALL, |m| m.to_string().into()) {
+                    self.method = ReduceMethod::ALL[method_idx];
+                }
+                ui.slider("Colors", 2, MAX_PALETTE_COLORS as u32, &mut self.target_count);
+                if ui.button("Reduce") {
+                    self.reduced = self.method.reduce(&self.colors, self.target_count as usize);
+                }
+
+                if !self.reduced.is_empty() {
+                    for (i, rgb) in self.reduced.iter().enumerate() {
+                        if i % REDUCED_PER_ROW != 0 {
+                            ui.same_line();
+                        }
+                        ui.color_button_config(format!("##reduced{}", i), *rgb_color(*rgb).components_4())
+                            .flags(ColorEditFlags::NO_ALPHA)
+                            .size([SWATCH_SIZE, SWATCH_SIZE])
+                            .build();
+                    }
+                    if ui.button("Use Reduced Palette") {
+                        self.palette = Some(self.reduced.iter().map(|rgb| rgb_color(*rgb)).collect());
+                    }
+                }
+            });
+        self.window_open = window_open;
+    }
+}
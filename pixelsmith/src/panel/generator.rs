@@ -0,0 +1,211 @@
+use std::path::Path;
+use std::sync::Arc;
+use imgui::{Condition, Ui};
+use crate::generators::{GeneratedMap, MapGenerator};
+use super::ERROR_COLOR;
+use crate::registry::MapImages;
+use crate::sprite::MapKind;
+
+
+/// What the app should do with a generator's maps, taken with `GeneratorPanel::take_action`
+pub enum GeneratorAction {
+    /// Show these maps in place of the sprite's own
+    Preview(Vec<GeneratedMap>),
+    /// Save these maps over the sprite's own
+    Accept(Vec<GeneratedMap>),
+    /// Show the sprite's own maps of these kinds again
+    Restore(Vec<MapKind>),
+}
+
+/// Dialog for one of the map generators. Previews the maps live in the viewports while the
+/// settings are edited, and saves them next to the sprite on accept.
+pub struct GeneratorPanel<G: MapGenerator> {
+    window_open: bool,
+    pub generator: G,
+    /// The current sprite's maps, set by the app when a sprite is loaded
+    images: Option<MapImages>,
+    /// Maps from the last time the generator ran
+    generated: Vec<GeneratedMap>,
+    /// Set when the settings or the sprite changed since `generated` was made
+    dirty: bool,
+    /// Kinds of maps currently shown in place of the sprite's own
+    previewing: Vec<MapKind>,
+    /// Shows the sprite's own maps instead of the generated ones, to compare them
+    show_original: bool,
+    action: Option<GeneratorAction>,
+    /// Result of the last generate or save
+    status: Option<Result<String, String>>,
+}
+
+impl<G: MapGenerator> GeneratorPanel<G> {
+    pub fn new(generator: G) -> Self {
+        GeneratorPanel {
+            window_open: false,
+            generator,
+            images: None,
+            generated: Vec::new(),
+            dirty: true,
+            previewing: Vec::new(),
+            show_original: false,
+            action: None,
+            status: None,
+        }
+    }
+
+    /// Points the generator at a newly loaded sprite, whose maps are in `dir`. The old sprite's
+    /// textures are gone along with any preview in them, so nothing needs restoring.
+    pub fn set_sprite(&mut self, dir: &Path, images: &MapImages) {
+        self.generator.set_sprite_dir(dir);
+        self.images = Some(images.clone());
+        self.generated.clear();
+        self.previewing.clear();
+        self.dirty = true;
+        self.status = None;
+    }
+
+    /// Updates the sprite's maps after a generator's were accepted. The sprite's textures are
+    /// still there, so any preview in them stays showing, and the generator only runs again if
+    /// a map it reads was replaced.
+    pub fn set_images(&mut self, images: &MapImages) {
+        if let Some(old) = &self.images {
+            if G::INPUTS.iter().any(|kind| !Arc::ptr_eq(old.get(*kind), images.get(*kind))) {
+                self.dirty = true;
+            }
+        }
+        self.images = Some(images.clone());
+    }
+
+    /// Returns what the app should do with the generated maps, once.
+    pub fn take_action(&mut self) -> Option<GeneratorAction> {
+        self.action.take()
+    }
+
+    /// Shows the result of the app saving the maps.
+    pub fn set_status(&mut self, status: Result<String, String>) {
+        self.status = Some(status);
+    }
+
+    fn regenerate(&mut self) {
+        let images = match &self.images {
+            Some(images) => images,
+            None => return,
+        };
+        self.dirty = false;
+        match self.generator.generate(images) {
+            Ok(maps) => {
+                self.generated = maps;
+                self.status = None;
+                if !self.show_original {
+                    self.preview();
+                }
+            }
+            Err(e) => {
+                self.generated.clear();
+                self.status = Some(Err(e));
+                self.restore();
+            }
+        }
+    }
+
+    /// Shows the generated maps in place of the sprite's own.
+    fn preview(&mut self) {
+        let mut kinds: Vec<MapKind> = Vec::new();
+        for kind in self.generated.iter().filter_map(|map| map.preview_kind()) {
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        let mut maps = self.generated.clone();
+        // maps the generator stopped making, after its settings changed, go back to the sprite's own
+        if let Some(images) = &self.images {
+            for kind in self.previewing.iter().filter(|kind| !kinds.contains(kind)) {
+                maps.push(GeneratedMap::new(*kind, images.get(*kind).as_ref().clone()));
+            }
+        }
+        self.previewing = kinds;
+        if !maps.is_empty() {
+            self.action = Some(GeneratorAction::Preview(maps));
+        }
+    }
+
+    /// Puts the sprite's own maps back if a preview is showing.
+    fn restore(&mut self) {
+        if !self.previewing.is_empty() {
+            self.action = Some(GeneratorAction::Restore(std::mem::take(&mut self.previewing)));
+        }
+    }
+}
+
+impl<G: MapGenerator> super::Panel for GeneratorPanel<G> {
+    fn is_open(&self) -> bool {
+        self.window_open
+    }
+
+    fn open_ref(&mut self) -> &mut bool {
+        &mut self.window_open
+    }
+
+    fn draw(&mut self, ui: &mut Ui) {
+        if !self.window_open {
+            self.restore();
+            return;
+        }
+        let mut window_open = self.window_open;
+        ui.window(G::NAME)
+            .size([320.0, 220.0], Condition::FirstUseEver)
+            .opened(&mut window_open)
+            .build(|| {
+                if self.images.is_none() {
+                    ui.text_disabled("No sprite loaded");
+                    return;
+                }
+                if self.generator.draw_settings(ui) {
+                    self.dirty = true;
+                }
+                // some generators take a while, so wait for sliders and text fields to be let go
+                // of rather than running on every frame of a drag
+                if self.dirty && !ui.is_any_item_active() {
+                    self.regenerate();
+                }
+                ui.separator();
+
+                if ui.checkbox("Show Original", &mut self.show_original) {
+                    if self.show_original {
+                        self.restore();
+                    } else {
+                        self.preview();
+                    }
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Shows the sprite's own maps in the viewports, to compare them with the generated ones");
+                }
+
+                let has_maps = !self.generated.is_empty() && !self.dirty;
+                {
+                    let _disabled = ui.begin_disabled(!has_maps);
+                    if ui.button("Accept") {
+                        self.action = Some(GeneratorAction::Accept(self.generated.clone()));
+                        self.previewing.clear();
+                    }
+                }
+                ui.same_line();
+                if ui.button("Cancel") {
+                    self.window_open = false;
+                }
+
+                if let Some(status) = &self.status {
+                    let _wrap = ui.push_text_wrap_pos();
+                    match status {
+                        Ok(message) => ui.text_disabled(message),
+                        Err(message) => ui.text_colored(ERROR_COLOR, message),
+                    }
+                }
+            });
+        self.window_open &= window_open;
+        if !self.window_open {
+            self.restore();
+            // generate again when reopened, in case the sprite's maps changed
+            self.dirty = true;
+        }
+    }
+}
@@ -1,11 +1,24 @@
+pub mod extract;
+pub mod generator;
 pub mod inspector;
+pub mod ramp;
+pub mod sprites;
+pub mod swaps;
 
 use std::borrow::BorrowMut;
 use std::cell::UnsafeCell;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize};
 use parking_lot::{Mutex, MutexGuard};
+pub use extract::PaletteExtractPanel;
+pub use generator::{GeneratorAction, GeneratorPanel};
 pub use inspector::InspectorPanel;
+pub use ramp::RampEditorPanel;
+pub use sprites::SpriteBrowserPanel;
+pub use swaps::PaletteSwapPanel;
+
+/// Text color of error messages, in every window
+pub const ERROR_COLOR: [f32; 4] = [1.0, 0.4, 0.4, 1.0];
 
 
 macro_rules! build_panel_set {
@@ -0,0 +1,139 @@
+use imgui::{Condition, Ui};
+use toolbelt::Color;
+use crate::material::{RampSettings, RampStop};
+
+const PREVIEW_HEIGHT: f32 = 24.0;
+const PREVIEW_SEGMENTS: usize = 64;
+
+/// Edits the color stops of the scene's lighting ramp.
+pub struct RampEditorPanel {
+    window_open: bool,
+    /// The ramp being edited, set by the app when a sprite is loaded
+    pub ramp: Option<RampSettings>,
+    /// Set when the ramp is edited, taken by the app with `take_changes`
+    changed: bool,
+}
+
+impl RampEditorPanel {
+    pub fn new() -> Self {
+        RampEditorPanel {
+            window_open: false,
+            ramp: None,
+            changed: false,
+        }
+    }
+
+    /// Returns the edited stops, sorted by position, once after each change.
+    pub fn take_changes(&mut self) -> Option<Vec<RampStop>> {
+        if std::mem::replace(&mut self.changed, false) {
+            self.ramp.as_ref().map(|ramp| {
+                let mut ramp = ramp.clone();
+                ramp.sort_stops();
+                ramp.stops
+            })
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl super::Panel for RampEditorPanel {
+    fn is_open(&self) -> bool {
+        self.window_open
+    }
+
+    fn open_ref(&mut self) -> &mut bool {
+        &mut self.window_open
+    }
+
+    fn draw(&mut self, ui: &mut Ui) {
+        if !self.window_open {
+            return;
+        }
+        let mut window_open = self.window_open;
+        ui.window("Lighting Ramp")
+            .size([320.0, 300.0], Condition::FirstUseEver)
+            .opened(&mut window_open)
+            .build(|| {
+                let ramp = match &mut self.ramp {
+                    Some(ramp) => ramp,
+                    None => {
+                        ui.text_disabled("No sprite loaded");
+                        return;
+                    }
+                };
+
+                // gradient preview, with a tick above each stop
+                let mut sorted = ramp.clone();
+                sorted.sort_stops();
+                let origin = ui.cursor_screen_pos();
+                let width = ui.content_region_avail()[0];
+                let draw_list = ui.get_window_draw_list();
+                let segment = width / PREVIEW_SEGMENTS as f32;
+                for i in 0..PREVIEW_SEGMENTS {
+                    let left = sorted.sample(i as f32 / PREVIEW_SEGMENTS as f32);
+                    let right = sorted.sample((i + 1) as f32 / PREVIEW_SEGMENTS as f32);
+                    let (left, right) = ([left[0], left[1], left[2], 1.0], [right[0], right[1], right[2], 1.0]);
+                    let x = origin[0] + segment * i as f32;
+                    draw_list.add_rect_filled_multicolor([x, origin[1]], [x + segment, origin[1] + PREVIEW_HEIGHT],
+                                                         left, right, right, left);
+                }
+                for stop in ramp.stops.iter() {
+                    let x = origin[0] + stop.position * width;
+                    draw_list.add_line([x, origin[1]], [x, origin[1] + 4.0], [1.0, 1.0, 1.0, 1.0]).build();
+                }
+                ui.dummy([width, PREVIEW_HEIGHT]);
+                ui.separator();
+
+                let mut changed = false;
+                let mut resort = false;
+                let mut remove = None;
+                let can_remove = ramp.stops.len() > 2;
+                for (i, stop) in ramp.stops.iter_mut().enumerate() {
+                    let _id = ui.push_id(i.to_string());
+                    changed |= ui.color_edit3_config("##color", stop.color.components_3_mut())
+                        .flags(imgui::ColorEditFlags::NO_INPUTS)
+                        .build();
+                    ui.same_line();
+                    ui.set_next_item_width(ui.content_region_avail()[0] - 30.0);
+                    changed |= ui.slider("##position", 0.0, 1.0, &mut stop.position);
+                    // rows are only re-sorted once a drag ends so the slider doesn't move under the mouse
+                    resort |= ui.is_item_deactivated_after_edit();
+                    ui.same_line();
+                    let _disabled = ui.begin_disabled(!can_remove);
+                    if ui.button("x") {
+                        remove = Some(i);
+                    }
+                }
+                if let Some(i) = remove {
+                    ramp.stops.remove(i);
+                    changed = true;
+                }
+
+                if ui.button("Add Stop") {
+                    // halfway into the widest gap between stops
+                    let (position, _) = sorted.stops.windows(2)
+                        .map(|pair| ((pair[0].position + pair[1].position) / 2.0, pair[1].position - pair[0].position))
+                        .fold((0.5, -1.0), |best, gap| if gap.1 > best.1 { gap } else { best });
+                    let [r, g, b] = sorted.sample(position);
+                    ramp.stops.push(RampStop { position, color: Color::from_rgb(r, g, b) });
+                    changed = true;
+                    resort = true;
+                }
+                ui.same_line();
+                if ui.button("Reset") {
+                    ramp.stops = RampSettings::default().stops;
+                    changed = true;
+                }
+
+                if resort {
+                    ramp.sort_stops();
+                }
+                if changed {
+                    self.changed = true;
+                }
+            });
+        self.window_open = window_open;
+    }
+}
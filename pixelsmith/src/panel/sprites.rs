@@ -0,0 +1,104 @@
+use imgui::{Condition, Ui};
+use crate::project::SpriteEntry;
+use super::ERROR_COLOR;
+
+const WARNING_COLOR: [f32; 4] = [1.0, 0.8, 0.3, 1.0];
+
+/// Lists every sprite in the project and lets the user pick which one is shown.
+pub struct SpriteBrowserPanel {
+    window_open: bool,
+    pub entries: Vec<SpriteEntry>,
+    /// Index into `entries` of the sprite currently loaded into the scene
+    pub active: Option<usize>,
+    /// Set if the project's sprites couldn't be listed at all
+    pub error: Option<String>,
+    /// Set when the user clicks a sprite, taken by the app with `take_selection`
+    selection: Option<usize>,
+    refresh_requested: bool,
+}
+
+impl SpriteBrowserPanel {
+    pub fn new() -> Self {
+        SpriteBrowserPanel {
+            window_open: true,
+            entries: Vec::new(),
+            active: None,
+            error: None,
+            selection: None,
+            refresh_requested: false,
+        }
+    }
+
+    /// Returns the index of the sprite the user picked since the last call, if any.
+    pub fn take_selection(&mut self) -> Option<usize> {
+        self.selection.take()
+    }
+
+    /// Returns true once after the user asks for the sprite list to be rescanned.
+    pub fn take_refresh_request(&mut self) -> bool {
+        std::mem::replace(&mut self.refresh_requested, false)
+    }
+}
+
+impl super::Panel for SpriteBrowserPanel {
+    fn is_open(&self) -> bool {
+        self.window_open
+    }
+
+    fn open_ref(&mut self) -> &mut bool {
+        &mut self.window_open
+    }
+
+    fn draw(&mut self, ui: &mut Ui) {
+        if !self.window_open {
+            return;
+        }
+        let mut window_open = self.window_open;
+        ui.window("Sprites")
+            .size([220.0, 300.0], Condition::FirstUseEver)
+            .opened(&mut window_open)
+            .build(|| {
+                if ui.button("Refresh") {
+                    self.refresh_requested = true;
+                }
+                ui.separator();
+
+                if let Some(error) = &self.error {
+                    let _wrap = ui.push_text_wrap_pos();
+                    ui.text_colored(ERROR_COLOR, error);
+                }
+                else if self.entries.is_empty() {
+                    ui.text_disabled("No sprites found");
+                }
+                for (i, entry) in self.entries.iter().enumerate() {
+                    let _id = ui.push_id(i.to_string());
+                    match &entry.data {
+                        Ok(_) => {
+                            if ui.selectable_config(entry.name())
+                                .selected(self.active == Some(i))
+                                .build()
+                                && self.active != Some(i)
+                            {
+                                self.selection = Some(i);
+                            }
+                            let _wrap = ui.push_text_wrap_pos();
+                            if let Some(error) = &entry.map_error {
+                                ui.text_colored(ERROR_COLOR, format!("  {}", error));
+                            }
+                            for warning in entry.map_warnings.iter() {
+                                ui.text_colored(WARNING_COLOR, format!("  {}", warning));
+                            }
+                        }
+                        Err(e) => {
+                            ui.selectable_config(entry.name())
+                                .disabled(true)
+                                .build();
+                            let _wrap = ui.push_text_wrap_pos();
+                            ui.text_colored(ERROR_COLOR, format!("  {}", e));
+                        }
+                    }
+                }
+            });
+        self.window_open = window_open;
+    }
+}
@@ -0,0 +1,148 @@
+use imgui::{ColorEditFlags, Condition, Ui};
+use toolbelt::Color;
+use crate::palette::{ColorMapping, PaletteSwap, SwapSettings, MAX_SWAP_COLORS};
+
+/// Edits the scene's named palette swaps and picks which one the sprite is shown with.
+pub struct PaletteSwapPanel {
+    window_open: bool,
+    /// The swaps being edited, set by the app when a sprite is loaded
+    pub swaps: Option<SwapSettings>,
+    /// Color selected in the palette editor, synced by the app before drawing
+    pub palette_color: Option<Color>,
+    /// Set when the swaps are edited, taken by the app with `take_changes`
+    changed: bool,
+}
+
+impl PaletteSwapPanel {
+    pub fn new() -> Self {
+        PaletteSwapPanel {
+            window_open: false,
+            swaps: None,
+            palette_color: None,
+            changed: false,
+        }
+    }
+
+    /// Returns the edited swaps once after each change.
+    pub fn take_changes(&mut self) -> Option<SwapSettings> {
+        if std::mem::replace(&mut self.changed, false) {
+            self.swaps.clone()
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl super::Panel for PaletteSwapPanel {
+    fn is_open(&self) -> bool {
+        self.window_open
+    }
+
+    fn open_ref(&mut self) -> &mut bool {
+        &mut self.window_open
+    }
+
+    fn draw(&mut self, ui: &mut Ui) {
+        if !self.window_open {
+            return;
+        }
+        let mut window_open = self.window_open;
+        ui.window("Palette Swaps")
+            .size([300.0, 320.0], Condition::FirstUseEver)
+            .opened(&mut window_open)
+            .build(|| {
+                let swaps = match &mut self.swaps {
+                    Some(swaps) => swaps,
+                    None => {
+                        ui.text_disabled("No sprite loaded");
+                        return;
+                    }
+                };
+                let mut changed = false;
+
+                // index 0 is the sprite's own colors
+                let mut names = vec!["None".to_string()];
+                names.extend(swaps.sets.iter().map(|set| set.name.clone()));
+                let mut active_idx = swaps.active.map_or(0, |idx| idx + 1);
+                if ui.combo_simple_string("Swap Set", &mut active_idx, &names) {
+                    swaps.active = active_idx.checked_sub(1);
+                    changed = true;
+                }
+
+                if ui.button("New") {
+                    swaps.sets.push(PaletteSwap { name: format!("Swap {}", swaps.sets.len() + 1), mappings: Vec::new() });
+                    swaps.active = Some(swaps.sets.len() - 1);
+                    changed = true;
+                }
+                if let Some(idx) = swaps.active {
+                    ui.same_line();
+                    if ui.button("Duplicate") {
+                        let mut copy = swaps.sets[idx].clone();
+                        copy.name = format!("{} copy", copy.name);
+                        swaps.sets.push(copy);
+                        swaps.active = Some(swaps.sets.len() - 1);
+                        changed = true;
+                    }
+                    ui.same_line();
+                    if ui.button("Delete") {
+                        swaps.sets.remove(idx);
+                        swaps.active = None;
+                        changed = true;
+                    }
+                }
+                ui.separator();
+
+                let set = match swaps.active.and_then(|idx| swaps.sets.get_mut(idx)) {
+                    Some(set) => set,
+                    None => {
+                        ui.text_disabled("Showing the sprite's own colors");
+                        self.changed |= changed;
+                        return;
+                    }
+                };
+
+                changed |= ui.input_text("Name##swap", &mut set.name).build();
+
+                let mut remove = None;
+                for (i, mapping) in set.mappings.iter_mut().enumerate() {
+                    let _id = ui.push_id(i.to_string());
+                    ui.color_button_config("##from", *mapping.from.components_4())
+                        .flags(ColorEditFlags::NO_ALPHA)
+                        .build();
+                    ui.same_line();
+                    ui.text("->");
+                    ui.same_line();
+                    changed |= ui.color_edit3_config("##to", mapping.to.components_3_mut())
+                        .flags(ColorEditFlags::NO_INPUTS)
+                        .build();
+                    ui.same_line();
+                    if ui.button("x") {
+                        remove = Some(i);
+                    }
+                }
+                if let Some(i) = remove {
+                    set.mappings.remove(i);
+                    changed = true;
+                }
+
+                let new_color = self.palette_color.map(|c| c.to_rgb());
+                let already_mapped = new_color.is_some_and(|c| set.mappings.iter().any(|m| m.from == c));
+                {
+                    let _disabled = ui.begin_disabled(new_color.is_none() || already_mapped
+                        || set.mappings.len() >= MAX_SWAP_COLORS as usize);
+                    if ui.button("Add Selected Palette Color") {
+                        let color = new_color.unwrap();
+                        set.mappings.push(ColorMapping { from: color, to: color });
+                        changed = true;
+                    }
+                }
+                if ui.is_item_hovered() {
+                    ui.tooltip_text("Maps the color selected in the palette, starting out unchanged");
+                }
+
+                self.changed |= changed;
+            });
+        self.window_open = window_open;
+    }
+}
@@ -0,0 +1,128 @@
+use wgpu::*;
+use crate::geometry::{VertexGroup, VertexPosUV, VertexPosUVPod};
+use crate::GLOBALS;
+use crate::pipeline::{COLOR_TARGET_STATE, PRIMITIVE_STATE};
+use crate::registry::{RegistryKey, TextureRegistry};
+
+
+/// Draws a texture from the registry onto the viewport as a quad, sampled with the render
+/// target sampler (nearest filtering). Used to upscale sprites lit at their native resolution.
+pub struct ViewportBlitPipeline {
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    quad_vg: VertexGroup,
+    pipeline: RenderPipeline,
+    pub rt_key: RegistryKey
+}
+
+
+impl ViewportBlitPipeline {
+    pub fn new(rt_key: RegistryKey) -> Self {
+        let device = &GLOBALS.get().device;
+        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("blit.wgsl"));
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("viewport blit uniform buffer"),
+            size: std::mem::size_of::<[[f32; 4]; 4]>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let uniform_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let uniform_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("viewport blit bind group"),
+            layout: &uniform_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("viewport blit pipeline layout"),
+            bind_group_layouts: &[&uniform_layout, &GLOBALS.get().single_texture_bind_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("viewport blit pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[VertexBufferLayout {
+                    array_stride: std::mem::size_of::<VertexPosUV>() as BufferAddress,
+                    step_mode: VertexStepMode::Vertex,
+                    attributes: &vertex_attr_array![0 => Float32x2, 1 => Float32x2],
+                }],
+            },
+            primitive: PRIMITIVE_STATE,
+            depth_stencil: None,
+            multisample: MultisampleState { count: 1, ..Default::default() },
+            fragment: Some(FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[COLOR_TARGET_STATE],
+            }),
+            multiview: None,
+        });
+
+        let quad_vg = VertexGroup::from_data_with_labels(&[
+            VertexPosUVPod(VertexPosUV { pos: [0.0, 0.0], uv: [0.0, 0.0] }),
+            VertexPosUVPod(VertexPosUV { pos: [1.0, 0.0], uv: [1.0, 0.0] }),
+            VertexPosUVPod(VertexPosUV { pos: [1.0, 1.0], uv: [1.0, 1.0] }),
+            VertexPosUVPod(VertexPosUV { pos: [0.0, 1.0], uv: [0.0, 1.0] }),
+        ], &[0u16, 2, 1, 0, 2, 3], Some("viewport blit geometry"), device);
+
+        ViewportBlitPipeline {
+            uniform_buffer,
+            uniform_bind_group,
+            quad_vg,
+            pipeline,
+            rt_key
+        }
+    }
+
+    pub fn update_uniforms(&self, matrix: &[[f32; 4]; 4]) {
+        GLOBALS.get().queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(matrix));
+    }
+
+    /// Clears the render target and draws `source` over it.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, source: RegistryKey) {
+        let view = registry.find(self.rt_key).unwrap().view();
+        let source_bind_group = registry.texture_bind_group(source).expect("Blit source texture has no bind group");
+
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("viewport blit renderpass"),
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.01, g: 0.01, b: 0.01, a: 1.0 }),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        rpass.set_bind_group(1, source_bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.quad_vg.vertex_buffer().slice(..));
+        rpass.set_index_buffer(self.quad_vg.index_buffer().slice(..), wgpu::IndexFormat::Uint16);
+        rpass.draw_indexed(0..6, 0, 0..1);
+    }
+}
@@ -0,0 +1,38 @@
+struct Uniforms {
+    matrix: mat4x4<f32>;
+};
+
+struct VertexInput {
+    [[location(0)]] pos: vec2<f32>;
+    [[location(1)]] uv: vec2<f32>;
+};
+
+struct VertexOutput {
+    [[location(0)]] uv: vec2<f32>;
+    [[builtin(position)]] pos: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+
+[[stage(vertex)]]
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.uv = in.uv;
+    out.pos = uniforms.matrix * vec4<f32>(in.pos.xy, 0.0, 1.0);
+    return out;
+}
+
+struct FragmentOutput {
+    [[location(0)]] colorOut: vec4<f32>;
+};
+
+[[group(1), binding(0)]]
+var Texture: texture_2d<f32>;
+[[group(1), binding(1)]]
+var Sampler: sampler;
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    return FragmentOutput(textureSample(Texture, Sampler, in.uv));
+}
@@ -1,21 +1,79 @@
 let MapType_Albedo = 0u;
 let MapType_Normal = 1u;
-let MapType_Roughness = 2u;
+let MapType_Specular = 2u;
 let MapType_Height = 3u;
 let MapType_Rendered = 4u;
+let MapType_AO = 5u;
+let MapType_Shadows = 6u;
+let MapType_Emissive = 7u;
+
+let MAX_LIGHTS = 16u;
+let SWAP_TABLE_SIZE = 256u;
+
+let Workflow_Specular = 0u;
+let Workflow_Roughness = 1u;
+
+let SpecularModel_Phong = 0u;
+let SpecularModel_Ggx = 1u;
+
+let PI = 3.14159265;
+// reflectance at normal incidence for GGX, typical for non-metals
+let GGX_F0 = 0.04;
+
+let LightKind_Point = 0u;
+let LightKind_Directional = 1u;
+let LightKind_Spot = 2u;
+let LightKind_Hemisphere = 3u;
+
+struct Light {
+    // rgb color, w is intensity
+    color: vec4<f32>;
+    // x, y, height, w is the falloff exponent (0 to disable falloff)
+    position: vec4<f32>;
+    // directional: unit vector towards the light, spot: unit vector the cone points along
+    direction: vec4<f32>;
+    groundColor: vec4<f32>;
+    kind: u32;
+    range: f32;
+    coneInnerCos: f32;
+    coneOuterCos: f32;
+    diffuse: f32;
+    specular: f32;
+};
 
 struct Uniforms {
     matrix: mat4x4<f32>;
-    lightColor: vec4<f32>;
-    lightPos: vec4<f32>;
     cameraPos: vec4<f32>;
     specPower: f32;
     ambientIntensity: f32;
     diffuseIntensity: f32;
     specularIntensity: f32;
     spriteSize: vec2<f32>;
-    lightFalloff: f32;
     viewMapType: u32;
+    aoDiffuse: f32;
+    lightCount: u32;
+    shadowsEnabled: u32;
+    shadowSteps: u32;
+    // canvas units per unit of the height map
+    shadowHeightScale: f32;
+    shadowSoftness: f32;
+    emissiveIntensity: f32;
+    materialWorkflow: u32;
+    specularModel: u32;
+    // 0 for smooth lighting
+    toonBands: u32;
+    // width of the Bayer matrix, 0 for no dithering
+    ditherSize: u32;
+    // how far either side of a band edge the dither reaches
+    ditherSpread: f32;
+    rampStrength: f32;
+    swapCount: u32;
+    swapHashMultiplier: u32;
+    _padding0: u32;
+    _padding1: u32;
+    // band thresholds in ascending order, unused ones are above 1
+    bandThresholds: array<vec4<f32>, 2>;
+    lights: array<Light, MAX_LIGHTS>;
 };
 
 struct VertexInput {
@@ -50,36 +108,225 @@ var AlbedoMap: texture_2d<f32>;
 [[group(1), binding(2)]]
 var NormalMap: texture_2d<f32>;
 [[group(1), binding(3)]]
-var RoughnessMap: texture_2d<f32>;
+var SpecularMap: texture_2d<f32>;
 [[group(1), binding(4)]]
 var HeightMap: texture_2d<f32>;
+[[group(1), binding(5)]]
+var AoMap: texture_2d<f32>;
+[[group(1), binding(6)]]
+var EmissiveMap: texture_2d<f32>;
+
+[[group(2), binding(0)]]
+var LookupSampler: sampler;
+[[group(2), binding(1)]]
+var RampMap: texture_2d<f32>;
+// replacement colors of the active palette swap, keyed by color hash, see `swapColor`
+[[group(2), binding(2)]]
+var SwapMap: texture_2d<f32>;
+
+// Marches from `position` (with its height already scaled) towards the light over the height map,
+// returning how much of the light reaches it. 0 is fully shadowed.
+fn shadowVisibility(position: vec3<f32>, light: Light) -> f32 {
+    var toLight = light.direction.xyz;
+    var maxDistance = length(uniforms.spriteSize);
+    if (light.kind != LightKind_Directional) {
+        toLight = light.position.xyz - position;
+        maxDistance = min(maxDistance, length(toLight.xy));
+    }
+    var planeLength = length(toLight.xy);
+    if (planeLength < 0.0001 || maxDistance < 1.0) {
+        // light is directly overhead, nothing can block it
+        return 1.0;
+    }
+    // height gained per canvas unit travelled in the sprite plane
+    var slope = toLight.z / planeLength;
+    var planeDir = toLight.xy / planeLength;
+
+    var steps = max(uniforms.shadowSteps, 1u);
+    var stepLength = max(maxDistance / f32(steps), 1.0);
+    var visibility = 1.0;
+    for (var i = 1u; i <= steps; i = i + 1u) {
+        var t = f32(i) * stepLength;
+        if (t > maxDistance) {
+            break;
+        }
+        var samplePos = position.xy + planeDir * t;
+        var sampleUv = samplePos / uniforms.spriteSize;
+        if (any(sampleUv < vec2<f32>(0.0)) || any(sampleUv > vec2<f32>(1.0))) {
+            break;
+        }
+        var rayHeight = position.z + slope * t;
+        var surfaceHeight = textureSampleLevel(HeightMap, Sampler, sampleUv, 0.0).x * uniforms.shadowHeightScale;
+        // the penumbra widens the further the occluder is from the shaded pixel
+        var occlusion = clamp((surfaceHeight - rayHeight) / (uniforms.shadowSoftness * t + 0.001), 0.0, 1.0);
+        visibility = min(visibility, 1.0 - occlusion);
+    }
+    return visibility;
+}
+
+// Cook-Torrance specular with a GGX distribution and Smith-Schlick geometry term,
+// already multiplied by N.L
+fn ggxSpecular(normal: vec3<f32>, lightDir: vec3<f32>, viewDir: vec3<f32>, alpha: f32) -> f32 {
+    var halfway = normalize(lightDir + viewDir);
+    var nDotL = max(dot(normal, lightDir), 0.0);
+    var nDotV = max(dot(normal, viewDir), 0.0001);
+    var nDotH = max(dot(normal, halfway), 0.0);
+
+    var a2 = alpha * alpha;
+    var d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+    var distribution = a2 / (PI * d * d);
+    var k = alpha / 2.0;
+    var geometry = (nDotL / (nDotL * (1.0 - k) + k)) * (nDotV / (nDotV * (1.0 - k) + k));
+    var fresnel = GGX_F0 + (1.0 - GGX_F0) * pow(1.0 - max(dot(halfway, viewDir), 0.0), 5.0);
+
+    return distribution * geometry * fresnel / max(4.0 * nDotL * nDotV, 0.0001) * nDotL;
+}
+
+// Ordered dither threshold in [0, 1) for a texel, from a `size`x`size` Bayer matrix
+fn bayer(texel: vec2<u32>, size: u32) -> f32 {
+    var value = 0u;
+    var cellCount = 1u;
+    // each bit of the coordinates picks a cell of the 2x2 base pattern, lower bits having more weight
+    for (var bit = 1u; bit < size; bit = bit * 2u) {
+        var x = select(0u, 1u, (texel.x & bit) != 0u);
+        var y = select(0u, 1u, (texel.y & bit) != 0u);
+        value = value * 4u + ((x * 2u) ^ (y * 3u));
+        cellCount = cellCount * 4u;
+    }
+    return (f32(value) + 0.5) / f32(cellCount);
+}
+
+// Quantizes an amount of light into `toonBands` levels, dithered across band edges
+fn toonShade(amount: f32, texel: vec2<u32>) -> f32 {
+    if (uniforms.toonBands == 0u) {
+        return amount;
+    }
+    var value = amount;
+    if (uniforms.ditherSize > 1u) {
+        value = value + (bayer(texel, uniforms.ditherSize) - 0.5) * 2.0 * uniforms.ditherSpread;
+    }
+    var band = 0u;
+    for (var i = 0u; i < uniforms.toonBands - 1u; i = i + 1u) {
+        if (value >= uniforms.bandThresholds[i / 4u][i % 4u]) {
+            band = band + 1u;
+        }
+    }
+    return f32(band) / f32(uniforms.toonBands - 1u);
+}
+
+// Replaces `color` if it matches one of the palette swap's source colors. The color's 24 bits
+// times an odd multiplier give its texel in the top 16 bits and a check value in the low 8, which
+// the texel's alpha only holds for the one color it replaces.
+fn swapColor(color: vec3<f32>) -> vec3<f32> {
+    if (uniforms.swapCount == 0u) {
+        return color;
+    }
+    var rgb = vec3<u32>(color * 255.0 + vec3<f32>(0.5));
+    var hash = (((rgb.x << 16u) | (rgb.y << 8u) | rgb.z) * uniforms.swapHashMultiplier) & 0xFFFFFFu;
+    var slot = hash >> 8u;
+    var entry = textureLoad(SwapMap, vec2<i32>(i32(slot % SWAP_TABLE_SIZE), i32(slot / SWAP_TABLE_SIZE)), 0);
+    if (u32(entry.w * 255.0 + 0.5) == (hash & 0xFFu)) {
+        return entry.xyz;
+    }
+    return color;
+}
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> FragmentOutput {
-    var albedo = textureSample(AlbedoMap, Sampler, in.uv).xyz;
+    var albedo = swapColor(textureSample(AlbedoMap, Sampler, in.uv).xyz);
     var normal = normalize(vec3<f32>(
         textureSample(NormalMap, Sampler, in.uv).xy * vec2<f32>(2.0, -2.0) - vec2<f32>(1.0, -1.0), 1.0
     ));
-    var roughness = textureSample(RoughnessMap, Sampler, in.uv).x;
+    var specularSample = textureSample(SpecularMap, Sampler, in.uv).x;
     var height = textureSample(HeightMap, Sampler, in.uv).x;
+    var ao = textureSample(AoMap, Sampler, in.uv).x;
+    var emissive = textureSample(EmissiveMap, Sampler, in.uv).xyz;
     var position = vec3<f32>(in.uv*uniforms.spriteSize, height);
-    var ambient = vec3<f32>(uniforms.ambientIntensity) * albedo;
+    // light reaching the texel before albedo is applied, ramp lighting indexes the ramp with it
+    var ambientLight = vec3<f32>(uniforms.ambientIntensity);
+    // dithering is anchored to sprite texels so it doesn't move with the view
+    var texel = vec2<u32>(floor(in.uv * uniforms.spriteSize));
+
+    var cam = uniforms.cameraPos.xyz;
+    var dirToCam = normalize(vec3<f32>(uniforms.spriteSize.x/2.0, uniforms.spriteSize.y/2.0, cam.z) - position);
 
-    var vecToLight = uniforms.lightPos.xyz - position;
-    var dist = length(vecToLight);
-    var lightFalloffMod = 1.0;
-    if (uniforms.lightFalloff != 0.0) {
-        lightFalloffMod = pow(1.0 - clamp(dist / 250.0, 0.0, 1.0), uniforms.lightFalloff);
+    // highlight strength, Phong exponent and GGX alpha for this texel
+    var specularMask = 1.0;
+    var phongExponent = uniforms.specPower;
+    var ggxAlpha = sqrt(2.0 / (uniforms.specPower + 2.0));
+    if (uniforms.materialWorkflow == Workflow_Specular) {
+        specularMask = specularSample;
     }
-    var lightColor = uniforms.lightColor.xyz * uniforms.lightColor.w * lightFalloffMod;
+    else {
+        var smoothness = 1.0 - specularSample;
+        phongExponent = max(uniforms.specPower * smoothness * smoothness, 1.0);
+        ggxAlpha = specularSample * specularSample;
+    }
+    ggxAlpha = max(ggxAlpha, 0.01);
 
-    var lightDir = normalize(vecToLight);
-    var diffuse = dot(normal, normalize(lightDir)) * lightColor * albedo;
+    var diffuseLight = vec3<f32>(0.0);
+    var specular = vec3<f32>(0.0);
+    // average visibility over the lights that cast shadows, for the Shadows view
+    var shadowTotal = 0.0;
+    var shadowCount = 0.0;
+    for (var i = 0u; i < min(uniforms.lightCount, MAX_LIGHTS); i = i + 1u) {
+        let light = uniforms.lights[i];
 
-    var reflect = normalize(lightDir - (dot(normal, lightDir) * 2.0 * normal));
-    var cam = uniforms.cameraPos.xyz;
-    var dirToCam = normalize(vec3<f32>(uniforms.spriteSize.x/2.0, uniforms.spriteSize.y/2.0, cam.z) - position);
-    var specular = pow(max(dot(-reflect, dirToCam), 0.0), uniforms.specPower * (1.0 + pow(roughness, 0.25))) * lightColor * albedo;
+        if (light.kind == LightKind_Hemisphere) {
+            // normals are in canvas space, so "up" is -y
+            var skyAmount = dot(normal, vec3<f32>(0.0, -1.0, 0.0)) * 0.5 + 0.5;
+            ambientLight = ambientLight + mix(light.groundColor.xyz, light.color.xyz, skyAmount) * light.color.w * light.diffuse;
+            continue;
+        }
+
+        var lightDir = light.direction.xyz;
+        var lightAttenuation = 1.0;
+        if (light.kind != LightKind_Directional) {
+            var vecToLight = light.position.xyz - position;
+            lightDir = normalize(vecToLight);
+            if (light.position.w != 0.0) {
+                lightAttenuation = pow(1.0 - clamp(length(vecToLight) / light.range, 0.0, 1.0), light.position.w);
+            }
+        }
+        if (light.kind == LightKind_Spot) {
+            // the cone is measured in the sprite plane, so it lights a wedge of the sprite
+            var planeDir = position.xy - light.position.xy;
+            var cosToPixel = 1.0;
+            if (length(planeDir) > 0.0) {
+                cosToPixel = dot(normalize(planeDir), light.direction.xy);
+            }
+            lightAttenuation = lightAttenuation * smoothStep(light.coneOuterCos, light.coneInnerCos, cosToPixel);
+        }
+        if (uniforms.shadowsEnabled != 0u) {
+            var visibility = shadowVisibility(vec3<f32>(position.xy, height * uniforms.shadowHeightScale), light);
+            lightAttenuation = lightAttenuation * visibility;
+            shadowTotal = shadowTotal + visibility;
+            shadowCount = shadowCount + 1.0;
+        }
+        var lightColor = light.color.xyz * light.color.w;
+
+        var diffuseAmount = toonShade(max(dot(normal, lightDir), 0.0) * lightAttenuation, texel);
+        diffuseLight = diffuseLight + diffuseAmount * lightColor * light.diffuse * mix(1.0, ao, uniforms.aoDiffuse);
+
+        var specularAmount = 0.0;
+        if (uniforms.specularModel == SpecularModel_Ggx) {
+            specularAmount = ggxSpecular(normal, lightDir, dirToCam, ggxAlpha) * lightAttenuation;
+        }
+        else {
+            var reflect = normalize(lightDir - (dot(normal, lightDir) * 2.0 * normal));
+            specularAmount = pow(max(dot(-reflect, dirToCam), 0.0), phongExponent) * lightAttenuation;
+        }
+        if (uniforms.toonBands != 0u) {
+            specularAmount = toonShade(min(specularAmount, 1.0), texel);
+        }
+        specularAmount = specularAmount * specularMask * light.specular;
+        if (uniforms.specularModel == SpecularModel_Ggx) {
+            specular = specular + specularAmount * lightColor;
+        }
+        else {
+            specular = specular + specularAmount * lightColor * albedo;
+        }
+    }
 
     var final_color = vec3<f32>(0.0);
     if (uniforms.viewMapType == MapType_Albedo) {
@@ -88,14 +335,35 @@ fn fs_main(in: VertexOutput) -> FragmentOutput {
     else if (uniforms.viewMapType == MapType_Normal) {
         final_color = textureSample(NormalMap, Sampler, in.uv).xyz;
     }
-    else if (uniforms.viewMapType == MapType_Roughness) {
-        final_color = vec3<f32>(roughness);
+    else if (uniforms.viewMapType == MapType_Specular) {
+        final_color = vec3<f32>(specularSample);
     }
     else if (uniforms.viewMapType == MapType_Height) {
         final_color = vec3<f32>(height);
     }
+    else if (uniforms.viewMapType == MapType_AO) {
+        final_color = vec3<f32>(ao);
+    }
+    else if (uniforms.viewMapType == MapType_Emissive) {
+        final_color = emissive;
+    }
+    else if (uniforms.viewMapType == MapType_Shadows) {
+        final_color = vec3<f32>(1.0);
+        if (shadowCount > 0.0) {
+            final_color = vec3<f32>(shadowTotal / shadowCount);
+        }
+    }
     else {
-        final_color = ambient + (diffuse * uniforms.diffuseIntensity) + (specular * uniforms.specularIntensity);
+        var lightLevel = ambientLight * ao + diffuseLight * uniforms.diffuseIntensity;
+        var lit = lightLevel * albedo;
+        if (uniforms.rampStrength > 0.0) {
+            var luminance = clamp(dot(lightLevel, vec3<f32>(0.299, 0.587, 0.114)), 0.0, 1.0);
+            var rampColor = textureSampleLevel(RampMap, LookupSampler, vec2<f32>(luminance, 0.5), 0.0).xyz;
+            lit = mix(lit, albedo * rampColor, uniforms.rampStrength);
+        }
+        final_color = lit + (specular * uniforms.specularIntensity);
+        // emission isn't affected by lighting, so it's added last
+        final_color = final_color + emissive * uniforms.emissiveIntensity;
     }
 
     return FragmentOutput(pow(vec4<f32>(final_color, 1.0), vec4<f32>(2.2)));
@@ -3,13 +3,41 @@ use wgpu::*;
 use crate::geometry::{VertexGroup, VertexPos, VertexPosPod};
 use crate::GLOBALS;
 use crate::pipeline::{COLOR_TARGET_STATE, PRIMITIVE_STATE, SimpleGeometryPipeline};
+use crate::pipeline::sprite::MAX_LIGHTS;
 use crate::registry::{RegistryKey, TextureRegistry};
 
 
+/// Maximum number of gizmo instances drawn per frame, must match `MAX_GIZMOS` in `light_gizmo.wgsl`
+pub const MAX_GIZMOS: usize = MAX_LIGHTS * 3;
+
+/// Transform and color of one light's gizmo
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LightGizmoInstance {
+    pub matrix: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+unsafe impl bytemuck::Zeroable for LightGizmoInstance {}
+unsafe impl bytemuck::Pod for LightGizmoInstance {}
+
+
+/// The meshes light gizmos are built from. Shapes are unit-sized and point along +x.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoShape {
+    Ring,
+    Arrow,
+    Line,
+    HalfDisc,
+}
+
+
+/// Draws light gizmos, each instance taken from a uniform array of up to `MAX_GIZMOS` entries
 pub struct ViewportLightGizmoPipeline {
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
     gizmo_vg: VertexGroup,
+    /// Index ranges of each `GizmoShape` in `gizmo_vg`
+    shape_ranges: [std::ops::Range<u32>; 4],
     pipeline: RenderPipeline,
     pub rt_key: RegistryKey,
 }
@@ -22,7 +50,7 @@ impl ViewportLightGizmoPipeline {
 
         let uniform_buffer = device.create_buffer(&BufferDescriptor {
             label: Some("viewport light gizmo uniform buffer"),
-            size: 80,
+            size: (std::mem::size_of::<LightGizmoInstance>() * MAX_GIZMOS) as BufferAddress,
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -79,29 +107,58 @@ impl ViewportLightGizmoPipeline {
             multiview: None,
         });
 
-        let gizmo_vg = VertexGroup::from_data_with_labels(
-            &[VertexPosPod(VertexPos { pos: [ 0.000000, -0.500000 ] }), VertexPosPod(VertexPos { pos: [ -0.097545, -0.490393 ] }), VertexPosPod(VertexPos { pos: [ -0.191342, -0.461940 ] }), VertexPosPod(VertexPos { pos: [ -0.277785, -0.415735 ] }), VertexPosPod(VertexPos { pos: [ -0.353553, -0.353553 ] }), VertexPosPod(VertexPos { pos: [ -0.415735, -0.277785 ] }), VertexPosPod(VertexPos { pos: [ -0.461940, -0.191342 ] }), VertexPosPod(VertexPos { pos: [ -0.490393, -0.097545 ] }), VertexPosPod(VertexPos { pos: [ -0.500000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ -0.490393, 0.097545 ] }), VertexPosPod(VertexPos { pos: [ -0.461940, 0.191342 ] }), VertexPosPod(VertexPos { pos: [ -0.415735, 0.277785 ] }), VertexPosPod(VertexPos { pos: [ -0.353553, 0.353553 ] }), VertexPosPod(VertexPos { pos: [ -0.277785, 0.415735 ] }), VertexPosPod(VertexPos { pos: [ -0.191342, 0.461940 ] }), VertexPosPod(VertexPos { pos: [ -0.097545, 0.490393 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, 0.500000 ] }), VertexPosPod(VertexPos { pos: [ 0.097545, 0.490393 ] }), VertexPosPod(VertexPos { pos: [ 0.191342, 0.461940 ] }), VertexPosPod(VertexPos { pos: [ 0.277785, 0.415735 ] }), VertexPosPod(VertexPos { pos: [ 0.353553, 0.353553 ] }), VertexPosPod(VertexPos { pos: [ 0.415735, 0.277785 ] }), VertexPosPod(VertexPos { pos: [ 0.461940, 0.191342 ] }), VertexPosPod(VertexPos { pos: [ 0.490393, 0.097545 ] }), VertexPosPod(VertexPos { pos: [ 0.500000, -0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.490393, -0.097545 ] }), VertexPosPod(VertexPos { pos: [ 0.461940, -0.191342 ] }), VertexPosPod(VertexPos { pos: [ 0.415735, -0.277785 ] }), VertexPosPod(VertexPos { pos: [ 0.353553, -0.353553 ] }), VertexPosPod(VertexPos { pos: [ 0.277785, -0.415735 ] }), VertexPosPod(VertexPos { pos: [ 0.191342, -0.461940 ] }), VertexPosPod(VertexPos { pos: [ 0.097545, -0.490393 ] }), VertexPosPod(VertexPos { pos: [ -0.000000, -0.450000 ] }), VertexPosPod(VertexPos { pos: [ -0.087791, -0.441353 ] }), VertexPosPod(VertexPos { pos: [ -0.172208, -0.415746 ] }), VertexPosPod(VertexPos { pos: [ -0.250007, -0.374161 ] }), VertexPosPod(VertexPos { pos: [ -0.318198, -0.318198 ] }), VertexPosPod(VertexPos { pos: [ -0.374161, -0.250007 ] }), VertexPosPod(VertexPos { pos: [ -0.415746, -0.172208 ] }), VertexPosPod(VertexPos { pos: [ -0.441353, -0.087791 ] }), VertexPosPod(VertexPos { pos: [ -0.450000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ -0.441353, 0.087791 ] }), VertexPosPod(VertexPos { pos: [ -0.415746, 0.172208 ] }), VertexPosPod(VertexPos { pos: [ -0.374161, 0.250007 ] }), VertexPosPod(VertexPos { pos: [ -0.318198, 0.318198 ] }), VertexPosPod(VertexPos { pos: [ -0.250007, 0.374161 ] }), VertexPosPod(VertexPos { pos: [ -0.172208, 0.415746 ] }), VertexPosPod(VertexPos { pos: [ -0.087791, 0.441353 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, 0.450000 ] }), VertexPosPod(VertexPos { pos: [ 0.087791, 0.441353 ] }), VertexPosPod(VertexPos { pos: [ 0.172208, 0.415746 ] }), VertexPosPod(VertexPos { pos: [ 0.250007, 0.374161 ] }), VertexPosPod(VertexPos { pos: [ 0.318198, 0.318198 ] }), VertexPosPod(VertexPos { pos: [ 0.374161, 0.250007 ] }), VertexPosPod(VertexPos { pos: [ 0.415746, 0.172208 ] }), VertexPosPod(VertexPos { pos: [ 0.441353, 0.087791 ] }), VertexPosPod(VertexPos { pos: [ 0.450000, -0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.441353, -0.087791 ] }), VertexPosPod(VertexPos { pos: [ 0.415746, -0.172208 ] }), VertexPosPod(VertexPos { pos: [ 0.374161, -0.250007 ] }), VertexPosPod(VertexPos { pos: [ 0.318198, -0.318198 ] }), VertexPosPod(VertexPos { pos: [ 0.250007, -0.374161 ] }), VertexPosPod(VertexPos { pos: [ 0.172208, -0.415746 ] }), VertexPosPod(VertexPos { pos: [ 0.087791, -0.441353 ] }),  VertexPosPod(VertexPos { pos: [ 0.000000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, -0.100000 ] }), VertexPosPod(VertexPos { pos: [ -0.038268, -0.092388 ] }), VertexPosPod(VertexPos { pos: [ -0.070711, -0.070711 ] }), VertexPosPod(VertexPos { pos: [ -0.092388, -0.038268 ] }), VertexPosPod(VertexPos { pos: [ -0.100000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ -0.092388, 0.038268 ] }), VertexPosPod(VertexPos { pos: [ -0.070711, 0.070711 ] }), VertexPosPod(VertexPos { pos: [ -0.038268, 0.092388 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, 0.100000 ] }), VertexPosPod(VertexPos { pos: [ 0.038268, 0.092388 ] }), VertexPosPod(VertexPos { pos: [ 0.070711, 0.070711 ] }), VertexPosPod(VertexPos { pos: [ 0.092388, 0.038268 ] }), VertexPosPod(VertexPos { pos: [ 0.100000, -0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.092388, -0.038268 ] }), VertexPosPod(VertexPos { pos: [ 0.070711, -0.070711 ] }), VertexPosPod(VertexPos { pos: [ 0.038268, -0.092388 ] })],
-            &[27u16, 60, 28, 14, 47, 15, 2, 33, 34, 28, 61, 29, 15, 48, 16, 2, 35, 3, 30, 61, 62, 16, 49, 17, 3, 36, 4, 30, 63, 31, 17, 50, 18, 5, 36, 37, 31, 0, 32, 19, 50, 51, 5, 38, 6, 32, 0, 1, 19, 52, 20, 6, 39, 7, 20, 53, 21, 7, 40, 8, 22, 53, 54, 9, 40, 41, 22, 55, 23, 9, 42, 10, 23, 56, 24, 10, 43, 11, 25, 56, 57, 11, 44, 12, 25, 58, 26, 13, 44, 45, 26, 59, 27, 13, 46, 14, 27, 59, 60, 14, 46, 47, 2, 1, 33, 28, 60, 61, 15, 47, 48, 2, 34, 35, 30, 29, 61, 16, 48, 49, 3, 35, 36, 30, 62, 63, 17, 49, 50, 5, 4, 36, 31, 63, 32, 19, 18, 50, 5, 37, 38, 32, 1, 33, 19, 51, 52, 6, 38, 39, 20, 52, 53, 7, 39, 40, 22, 21, 53, 9, 8, 40, 22, 54, 55, 9, 41, 42, 23, 55, 56, 10, 42, 43, 25, 24, 56, 11, 43, 44, 25, 57, 58, 13, 12, 44, 26, 58, 59, 13, 45, 46, 64, 65, 66, 64, 66, 67, 64, 67, 68, 64, 68, 69, 64, 69, 70, 64, 70, 71, 64, 71, 72, 64, 72, 73, 64, 73, 74, 64, 74, 75, 64, 75, 76, 64, 76, 77, 64, 77, 78, 64, 78, 79, 64, 79, 80, 64, 80, 65],
-            Some("canvas light gizmo geometry"), &device);
+        // ring with a dot in the middle, used for every light's position
+        let ring_vertices = [VertexPosPod(VertexPos { pos: [ 0.000000, -0.500000 ] }), VertexPosPod(VertexPos { pos: [ -0.097545, -0.490393 ] }), VertexPosPod(VertexPos { pos: [ -0.191342, -0.461940 ] }), VertexPosPod(VertexPos { pos: [ -0.277785, -0.415735 ] }), VertexPosPod(VertexPos { pos: [ -0.353553, -0.353553 ] }), VertexPosPod(VertexPos { pos: [ -0.415735, -0.277785 ] }), VertexPosPod(VertexPos { pos: [ -0.461940, -0.191342 ] }), VertexPosPod(VertexPos { pos: [ -0.490393, -0.097545 ] }), VertexPosPod(VertexPos { pos: [ -0.500000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ -0.490393, 0.097545 ] }), VertexPosPod(VertexPos { pos: [ -0.461940, 0.191342 ] }), VertexPosPod(VertexPos { pos: [ -0.415735, 0.277785 ] }), VertexPosPod(VertexPos { pos: [ -0.353553, 0.353553 ] }), VertexPosPod(VertexPos { pos: [ -0.277785, 0.415735 ] }), VertexPosPod(VertexPos { pos: [ -0.191342, 0.461940 ] }), VertexPosPod(VertexPos { pos: [ -0.097545, 0.490393 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, 0.500000 ] }), VertexPosPod(VertexPos { pos: [ 0.097545, 0.490393 ] }), VertexPosPod(VertexPos { pos: [ 0.191342, 0.461940 ] }), VertexPosPod(VertexPos { pos: [ 0.277785, 0.415735 ] }), VertexPosPod(VertexPos { pos: [ 0.353553, 0.353553 ] }), VertexPosPod(VertexPos { pos: [ 0.415735, 0.277785 ] }), VertexPosPod(VertexPos { pos: [ 0.461940, 0.191342 ] }), VertexPosPod(VertexPos { pos: [ 0.490393, 0.097545 ] }), VertexPosPod(VertexPos { pos: [ 0.500000, -0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.490393, -0.097545 ] }), VertexPosPod(VertexPos { pos: [ 0.461940, -0.191342 ] }), VertexPosPod(VertexPos { pos: [ 0.415735, -0.277785 ] }), VertexPosPod(VertexPos { pos: [ 0.353553, -0.353553 ] }), VertexPosPod(VertexPos { pos: [ 0.277785, -0.415735 ] }), VertexPosPod(VertexPos { pos: [ 0.191342, -0.461940 ] }), VertexPosPod(VertexPos { pos: [ 0.097545, -0.490393 ] }), VertexPosPod(VertexPos { pos: [ -0.000000, -0.450000 ] }), VertexPosPod(VertexPos { pos: [ -0.087791, -0.441353 ] }), VertexPosPod(VertexPos { pos: [ -0.172208, -0.415746 ] }), VertexPosPod(VertexPos { pos: [ -0.250007, -0.374161 ] }), VertexPosPod(VertexPos { pos: [ -0.318198, -0.318198 ] }), VertexPosPod(VertexPos { pos: [ -0.374161, -0.250007 ] }), VertexPosPod(VertexPos { pos: [ -0.415746, -0.172208 ] }), VertexPosPod(VertexPos { pos: [ -0.441353, -0.087791 ] }), VertexPosPod(VertexPos { pos: [ -0.450000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ -0.441353, 0.087791 ] }), VertexPosPod(VertexPos { pos: [ -0.415746, 0.172208 ] }), VertexPosPod(VertexPos { pos: [ -0.374161, 0.250007 ] }), VertexPosPod(VertexPos { pos: [ -0.318198, 0.318198 ] }), VertexPosPod(VertexPos { pos: [ -0.250007, 0.374161 ] }), VertexPosPod(VertexPos { pos: [ -0.172208, 0.415746 ] }), VertexPosPod(VertexPos { pos: [ -0.087791, 0.441353 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, 0.450000 ] }), VertexPosPod(VertexPos { pos: [ 0.087791, 0.441353 ] }), VertexPosPod(VertexPos { pos: [ 0.172208, 0.415746 ] }), VertexPosPod(VertexPos { pos: [ 0.250007, 0.374161 ] }), VertexPosPod(VertexPos { pos: [ 0.318198, 0.318198 ] }), VertexPosPod(VertexPos { pos: [ 0.374161, 0.250007 ] }), VertexPosPod(VertexPos { pos: [ 0.415746, 0.172208 ] }), VertexPosPod(VertexPos { pos: [ 0.441353, 0.087791 ] }), VertexPosPod(VertexPos { pos: [ 0.450000, -0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.441353, -0.087791 ] }), VertexPosPod(VertexPos { pos: [ 0.415746, -0.172208 ] }), VertexPosPod(VertexPos { pos: [ 0.374161, -0.250007 ] }), VertexPosPod(VertexPos { pos: [ 0.318198, -0.318198 ] }), VertexPosPod(VertexPos { pos: [ 0.250007, -0.374161 ] }), VertexPosPod(VertexPos { pos: [ 0.172208, -0.415746 ] }), VertexPosPod(VertexPos { pos: [ 0.087791, -0.441353 ] }),  VertexPosPod(VertexPos { pos: [ 0.000000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, -0.100000 ] }), VertexPosPod(VertexPos { pos: [ -0.038268, -0.092388 ] }), VertexPosPod(VertexPos { pos: [ -0.070711, -0.070711 ] }), VertexPosPod(VertexPos { pos: [ -0.092388, -0.038268 ] }), VertexPosPod(VertexPos { pos: [ -0.100000, 0.000000 ] }), VertexPosPod(VertexPos { pos: [ -0.092388, 0.038268 ] }), VertexPosPod(VertexPos { pos: [ -0.070711, 0.070711 ] }), VertexPosPod(VertexPos { pos: [ -0.038268, 0.092388 ] }), VertexPosPod(VertexPos { pos: [ 0.000000, 0.100000 ] }), VertexPosPod(VertexPos { pos: [ 0.038268, 0.092388 ] }), VertexPosPod(VertexPos { pos: [ 0.070711, 0.070711 ] }), VertexPosPod(VertexPos { pos: [ 0.092388, 0.038268 ] }), VertexPosPod(VertexPos { pos: [ 0.100000, -0.000000 ] }), VertexPosPod(VertexPos { pos: [ 0.092388, -0.038268 ] }), VertexPosPod(VertexPos { pos: [ 0.070711, -0.070711 ] }), VertexPosPod(VertexPos { pos: [ 0.038268, -0.092388 ] })];
+        let ring_indices = [27u16, 60, 28, 14, 47, 15, 2, 33, 34, 28, 61, 29, 15, 48, 16, 2, 35, 3, 30, 61, 62, 16, 49, 17, 3, 36, 4, 30, 63, 31, 17, 50, 18, 5, 36, 37, 31, 0, 32, 19, 50, 51, 5, 38, 6, 32, 0, 1, 19, 52, 20, 6, 39, 7, 20, 53, 21, 7, 40, 8, 22, 53, 54, 9, 40, 41, 22, 55, 23, 9, 42, 10, 23, 56, 24, 10, 43, 11, 25, 56, 57, 11, 44, 12, 25, 58, 26, 13, 44, 45, 26, 59, 27, 13, 46, 14, 27, 59, 60, 14, 46, 47, 2, 1, 33, 28, 60, 61, 15, 47, 48, 2, 34, 35, 30, 29, 61, 16, 48, 49, 3, 35, 36, 30, 62, 63, 17, 49, 50, 5, 4, 36, 31, 63, 32, 19, 18, 50, 5, 37, 38, 32, 1, 33, 19, 51, 52, 6, 38, 39, 20, 52, 53, 7, 39, 40, 22, 21, 53, 9, 8, 40, 22, 54, 55, 9, 41, 42, 23, 55, 56, 10, 42, 43, 25, 24, 56, 11, 43, 44, 25, 57, 58, 13, 12, 44, 26, 58, 59, 13, 45, 46, 64, 65, 66, 64, 66, 67, 64, 67, 68, 64, 68, 69, 64, 69, 70, 64, 70, 71, 64, 71, 72, 64, 72, 73, 64, 73, 74, 64, 74, 75, 64, 75, 76, 64, 76, 77, 64, 77, 78, 64, 78, 79, 64, 79, 80, 64, 80, 65];
+
+        let mut vertices = ring_vertices.to_vec();
+        let mut indices = ring_indices.to_vec();
+        let mut add_shape = |shape_vertices: &[[f32; 2]], shape_indices: &[u16]| {
+            let base = vertices.len() as u16;
+            let first = indices.len() as u32;
+            vertices.extend(shape_vertices.iter().map(|&pos| VertexPosPod(VertexPos { pos })));
+            indices.extend(shape_indices.iter().map(|i| i + base));
+            first..indices.len() as u32
+        };
+        let ring_range = 0..ring_indices.len() as u32;
+        // arrow along +x, starting just outside the center dot
+        let arrow_range = add_shape(
+            &[[0.1, -0.025], [0.75, -0.025], [0.75, 0.025], [0.1, 0.025], [0.75, -0.09], [0.95, 0.0], [0.75, 0.09]],
+            &[0, 1, 2, 0, 2, 3, 4, 5, 6]);
+        // thin line along +x
+        let line_range = add_shape(
+            &[[0.1, -0.015], [0.9, -0.015], [0.9, 0.015], [0.1, 0.015]],
+            &[0, 1, 2, 0, 2, 3]);
+        // upper half of a disc just inside the ring (-y is up on the canvas)
+        let half_disc_vertices: Vec<[f32; 2]> = std::iter::once([0.0, 0.0])
+            .chain((0..=16).map(|i| {
+                let angle = std::f32::consts::PI * (1.0 + i as f32 / 16.0);
+                [0.4 * angle.cos(), 0.4 * angle.sin()]
+            }))
+            .collect();
+        let half_disc_indices: Vec<u16> = (1..17u16).flat_map(|i| [0, i, i + 1]).collect();
+        let half_disc_range = add_shape(&half_disc_vertices, &half_disc_indices);
+
+        let gizmo_vg = VertexGroup::from_data_with_labels(&vertices, &indices, Some("canvas light gizmo geometry"), device);
 
         ViewportLightGizmoPipeline {
             uniform_buffer,
             uniform_bind_group,
             gizmo_vg,
+            shape_ranges: [ring_range, arrow_range, line_range, half_disc_range],
             pipeline,
             rt_key,
         }
     }
 
-    pub fn update_uniforms(&self, m: &[[f32; 4]; 4], color: toolbelt::Color) {
-        let data = [
-            m[0], m[1], m[2], m[3],
-            *color.components_4()
-        ];
-        GLOBALS.get().queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&data));
+    /// Uploads the gizmos to draw, anything past `MAX_GIZMOS` is ignored.
+    pub fn update_uniforms(&self, gizmos: &[LightGizmoInstance]) {
+        let count = gizmos.len().min(MAX_GIZMOS);
+        GLOBALS.get().queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&gizmos[..count]));
     }
 
-    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry) {
+    /// Draws the gizmos last passed to `update_uniforms`, `shapes[i]` being the mesh used for instance `i`.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, shapes: &[GizmoShape]) {
         let view = registry.find(self.rt_key).expect("Failed to retrieve render target texture").view();
 
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -118,6 +175,9 @@ impl ViewportLightGizmoPipeline {
         rpass.set_bind_group(0, &self.uniform_bind_group, &[]);
         rpass.set_vertex_buffer(0, self.gizmo_vg.vertex_buffer().slice(..));
         rpass.set_index_buffer(self.gizmo_vg.index_buffer().slice(..), wgpu::IndexFormat::Uint16);
-        rpass.draw_indexed(0..240, 0, 0..1);
+        for (i, shape) in shapes.iter().take(MAX_GIZMOS).enumerate() {
+            let i = i as u32;
+            rpass.draw_indexed(self.shape_ranges[*shape as usize].clone(), 0, i..i+1);
+        }
     }
 }
@@ -1,14 +1,22 @@
-struct Uniforms {
+let MAX_GIZMOS = 48u;
+
+struct Gizmo {
     u_Matrix: mat4x4<f32>;
     u_Color: vec4<f32>;
 };
 
+struct Uniforms {
+    gizmos: array<Gizmo, MAX_GIZMOS>;
+};
+
 struct VertexInput {
     [[location(0)]] a_Pos: vec2<f32>;
+    [[builtin(instance_index)]] instance: u32;
 };
 
 struct VertexOutput {
     [[builtin(position)]] v_Position: vec4<f32>;
+    [[location(0)]] v_Color: vec4<f32>;
 };
 
 [[group(0), binding(0)]]
@@ -16,8 +24,10 @@ var<uniform> uniforms: Uniforms;
 
 [[stage(vertex)]]
 fn vs_main(in: VertexInput) -> VertexOutput {
+    let gizmo = uniforms.gizmos[in.instance];
     var out: VertexOutput;
-    out.v_Position = uniforms.u_Matrix * vec4<f32>(in.a_Pos.xy, 0.0, 1.0);
+    out.v_Position = gizmo.u_Matrix * vec4<f32>(in.a_Pos.xy, 0.0, 1.0);
+    out.v_Color = gizmo.u_Color;
     return out;
 }
 
@@ -27,5 +37,5 @@ struct FragmentOutput {
 
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> FragmentOutput {
-    return FragmentOutput(uniforms.u_Color);
+    return FragmentOutput(in.v_Color);
 }
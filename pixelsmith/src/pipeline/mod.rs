@@ -6,6 +6,10 @@ pub mod sprite;
 pub use sprite::ViewportSpritePipeline;
 pub mod light;
 pub use light::ViewportLightGizmoPipeline;
+pub mod blit;
+pub use blit::ViewportBlitPipeline;
+pub mod quantize;
+pub use quantize::QuantizePipeline;
 use crate::registry::TextureRegistry;
 
 
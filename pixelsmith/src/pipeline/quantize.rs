@@ -0,0 +1,207 @@
+use wgpu::*;
+use toolbelt::Color;
+use crate::GLOBALS;
+use crate::palette::{QuantizeDither, QuantizeSettings};
+use crate::registry::{RegistryKey, TextureRegistry};
+
+
+/// Most palette colors the quantize shader considers, must match `MAX_PALETTE_COLORS` in `quantize.wgsl`
+pub const MAX_PALETTE_COLORS: usize = 256;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct QuantizePipelineUniforms {
+    pub palette_size: u32,
+    /// `ColorMetric` as u32
+    pub metric: u32,
+    /// Bayer matrix width, 0 for no ordered dithering
+    pub dither_size: u32,
+    pub dither_spread: f32,
+    /// sRGB palette colors, alpha unused
+    pub palette: [[f32; 4]; MAX_PALETTE_COLORS],
+}
+unsafe impl bytemuck::Zeroable for QuantizePipelineUniforms {}
+unsafe impl bytemuck::Pod for QuantizePipelineUniforms {}
+
+
+/// Maps every pixel of a sprite-sized render target to its nearest palette color, writing the
+/// result into a texture of its own that can be blitted to the viewport.
+pub struct QuantizePipeline {
+    uniform_buffer: wgpu::Buffer,
+    bind_layout: wgpu::BindGroupLayout,
+    ordered_pipeline: ComputePipeline,
+    diffuse_pipeline: ComputePipeline,
+    /// Error carried between rows by Floyd-Steinberg dithering, one vec4 per target pixel
+    diffusion_buffer: Option<wgpu::Buffer>,
+    /// Quantized output, created by `update_target`
+    pub target_key: Option<RegistryKey>,
+}
+
+
+impl QuantizePipeline {
+    pub fn new() -> Self {
+        let device = &GLOBALS.get().device;
+        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("quantize.wgsl"));
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("quantize uniform buffer"),
+            size: std::mem::size_of::<QuantizePipelineUniforms>() as BufferAddress,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("quantize bind group layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: TextureFormat::Rgba16Float,
+                        view_dimension: TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("quantize pipeline layout"),
+            bind_group_layouts: &[&bind_layout],
+            push_constant_ranges: &[],
+        });
+
+        let ordered_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("quantize ordered pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "cs_ordered",
+        });
+        let diffuse_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("quantize diffuse pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader_module,
+            entry_point: "cs_diffuse",
+        });
+
+        QuantizePipeline {
+            uniform_buffer,
+            bind_layout,
+            ordered_pipeline,
+            diffuse_pipeline,
+            diffusion_buffer: None,
+            target_key: None,
+        }
+    }
+
+    /// Creates or resizes the output texture to `size`, or frees it if `size` is None.
+    pub fn update_target(&mut self, size: Option<(u32, u32)>, registry: &mut TextureRegistry) {
+        let current_size = self.target_key.and_then(|key| registry.find(key)).map(|t| t.size());
+        if current_size == size {
+            return;
+        }
+        if let Some(key) = self.target_key.take() {
+            registry.remove(key);
+        }
+        self.diffusion_buffer = None;
+
+        if let Some(size) = size {
+            let key = registry.create_texture(size, "quantized render target", TextureFormat::Rgba16Float,
+                                              TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING);
+            let bind_group = registry.add_bind_group(BindGroupDescriptor {
+                label: Some("quantized render target"),
+                layout: &GLOBALS.get().single_texture_bind_layout,
+                entries: &[
+                    BindGroupEntry { binding: 0, resource: BindingResource::TextureView(&registry.find(key).unwrap().view()) },
+                    BindGroupEntry { binding: 1, resource: BindingResource::Sampler(&GLOBALS.get().rt_sampler) }
+                ]
+            });
+            registry.find_mut(key).unwrap().replace_bind_group_idx(bind_group);
+            self.target_key = Some(key);
+
+            self.diffusion_buffer = Some(GLOBALS.get().device.create_buffer(&BufferDescriptor {
+                label: Some("quantize diffusion buffer"),
+                size: (size.0 * size.1) as BufferAddress * std::mem::size_of::<[f32; 4]>() as BufferAddress,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }));
+        }
+    }
+
+    pub fn update_uniforms(&self, settings: &QuantizeSettings, palette: &[Color]) {
+        let mut uniforms = QuantizePipelineUniforms {
+            palette_size: palette.len().min(MAX_PALETTE_COLORS) as u32,
+            metric: settings.metric as u32,
+            dither_size: settings.dither.bayer_size(),
+            dither_spread: settings.dither_spread,
+            palette: [[0.0; 4]; MAX_PALETTE_COLORS],
+        };
+        for (gpu_color, color) in uniforms.palette.iter_mut().zip(palette.iter()) {
+            *gpu_color = *color.to_rgb().components_4();
+        }
+        GLOBALS.get().queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    /// Quantizes `source`, which must be the same size as the target, into `target_key`.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, source: RegistryKey, dither: QuantizeDither) {
+        let (target_key, diffusion_buffer) = match (self.target_key, self.diffusion_buffer.as_ref()) {
+            (Some(key), Some(buffer)) => (key, buffer),
+            _ => return,
+        };
+        let target = registry.find(target_key).unwrap();
+        let (width, height) = target.size();
+        let bind_group = GLOBALS.get().device.create_bind_group(&BindGroupDescriptor {
+            label: Some("quantize bind group"),
+            layout: &self.bind_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: self.uniform_buffer.as_entire_binding() },
+                BindGroupEntry { binding: 1, resource: BindingResource::TextureView(&registry.find(source).unwrap().view()) },
+                BindGroupEntry { binding: 2, resource: BindingResource::TextureView(&target.view()) },
+                BindGroupEntry { binding: 3, resource: diffusion_buffer.as_entire_binding() },
+            ],
+        });
+
+        let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor { label: Some("quantize pass") });
+        cpass.set_bind_group(0, &bind_group, &[]);
+        if dither == QuantizeDither::FloydSteinberg {
+            // a single workgroup walks the whole image, `DIFFUSION_ROWS` rows at a time
+            cpass.set_pipeline(&self.diffuse_pipeline);
+            cpass.dispatch(1, 1, 1);
+        }
+        else {
+            cpass.set_pipeline(&self.ordered_pipeline);
+            cpass.dispatch(width.div_ceil(8), height.div_ceil(8), 1);
+        }
+    }
+}
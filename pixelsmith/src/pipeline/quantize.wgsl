@@ -0,0 +1,197 @@
+// must match MAX_PALETTE_COLORS in quantize.rs
+let MAX_PALETTE_COLORS = 256u;
+// rows handled at once by error diffusion, must match the workgroup size of cs_diffuse
+let DIFFUSION_ROWS = 256u;
+
+let Metric_Rgb = 0u;
+let Metric_Lab = 1u;
+let Metric_Oklab = 2u;
+
+struct Uniforms {
+    paletteSize: u32;
+    metric: u32;
+    ditherSize: u32;
+    ditherSpread: f32;
+    // sRGB, alpha unused
+    palette: array<vec4<f32>, MAX_PALETTE_COLORS>;
+};
+
+struct DiffusionBuffer {
+    // error carried into each pixel from the row above
+    errors: array<vec4<f32>>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> uniforms: Uniforms;
+[[group(0), binding(1)]]
+var Source: texture_2d<f32>;
+[[group(0), binding(2)]]
+var Target: texture_storage_2d<rgba16float, write>;
+[[group(0), binding(3)]]
+var<storage, read_write> diffusion: DiffusionBuffer;
+
+// palette converted into the metric's space, filled at the start of each workgroup
+var<workgroup> paletteSpace: array<vec3<f32>, MAX_PALETTE_COLORS>;
+
+fn srgbToLinear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linearToSrgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn labF(t: f32) -> f32 {
+    if (t > 0.008856) {
+        return pow(t, 1.0 / 3.0);
+    }
+    return t * 7.787 + 16.0 / 116.0;
+}
+
+fn cbrt(x: f32) -> f32 {
+    return sign(x) * pow(abs(x), 1.0 / 3.0);
+}
+
+fn toMetricSpace(srgb: vec3<f32>) -> vec3<f32> {
+    let c = clamp(srgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    if (uniforms.metric == Metric_Lab) {
+        let l = srgbToLinear(c);
+        // D65 white
+        let x = dot(l, vec3<f32>(0.4124, 0.3576, 0.1805)) / 0.95047;
+        let y = dot(l, vec3<f32>(0.2126, 0.7152, 0.0722));
+        let z = dot(l, vec3<f32>(0.0193, 0.1192, 0.9505)) / 1.08883;
+        let fx = labF(x);
+        let fy = labF(y);
+        let fz = labF(z);
+        return vec3<f32>(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz));
+    }
+    if (uniforms.metric == Metric_Oklab) {
+        let l = srgbToLinear(c);
+        let lms = vec3<f32>(
+            cbrt(dot(l, vec3<f32>(0.4122214708, 0.5363325363, 0.0514459929))),
+            cbrt(dot(l, vec3<f32>(0.2119034982, 0.6806995451, 0.1073969566))),
+            cbrt(dot(l, vec3<f32>(0.0883024619, 0.2817188376, 0.6299787005))),
+        );
+        return vec3<f32>(
+            dot(lms, vec3<f32>(0.2104542553, 0.7936177850, -0.0040720468)),
+            dot(lms, vec3<f32>(1.9779984951, -2.4285922050, 0.4505937099)),
+            dot(lms, vec3<f32>(0.0259040371, 0.7827717662, -0.8086757660)),
+        );
+    }
+    return c;
+}
+
+// Converts this invocation's share of the palette into paletteSpace.
+fn loadPalette(localIndex: u32, invocations: u32) {
+    for (var i = localIndex; i < min(uniforms.paletteSize, MAX_PALETTE_COLORS); i = i + invocations) {
+        paletteSpace[i] = toMetricSpace(uniforms.palette[i].xyz);
+    }
+}
+
+// sRGB value of the palette color closest to `srgb`
+fn nearest(srgb: vec3<f32>) -> vec3<f32> {
+    let wanted = toMetricSpace(srgb);
+    var best = 0u;
+    var bestDistance = 1e20;
+    for (var i = 0u; i < min(uniforms.paletteSize, MAX_PALETTE_COLORS); i = i + 1u) {
+        let d = paletteSpace[i] - wanted;
+        let distance = dot(d, d);
+        if (distance < bestDistance) {
+            best = i;
+            bestDistance = distance;
+        }
+    }
+    return uniforms.palette[best].xyz;
+}
+
+// Threshold in 0..1 from a Bayer matrix of the given size, same as in canvas_sprite.wgsl
+fn bayer(texel: vec2<u32>, size: u32) -> f32 {
+    var value = 0u;
+    var cellCount = 1u;
+    // each bit of the coordinates picks a cell of the 2x2 base pattern, lower bits having more weight
+    for (var bit = 1u; bit < size; bit = bit * 2u) {
+        var x = select(0u, 1u, (texel.x & bit) != 0u);
+        var y = select(0u, 1u, (texel.y & bit) != 0u);
+        value = value * 4u + ((x * 2u) ^ (y * 3u));
+        cellCount = cellCount * 4u;
+    }
+    return (f32(value) + 0.5) / f32(cellCount);
+}
+
+// the render targets are sRGB, so loads come back as linear light. palette matching works on
+// the encoded values, with the exact sRGB curve so palette colors come back out unchanged.
+fn loadSource(texel: vec2<i32>) -> vec4<f32> {
+    let c = textureLoad(Source, texel, 0);
+    return vec4<f32>(linearToSrgb(c.xyz), c.w);
+}
+
+fn storeTarget(texel: vec2<i32>, srgb: vec3<f32>, alpha: f32) {
+    textureStore(Target, texel, vec4<f32>(srgbToLinear(srgb), alpha));
+}
+
+// Nearest color per pixel, optionally with an ordered dither anchored to sprite texels.
+[[stage(compute), workgroup_size(8, 8)]]
+fn cs_ordered([[builtin(global_invocation_id)]] id: vec3<u32>, [[builtin(local_invocation_index)]] localIndex: u32) {
+    loadPalette(localIndex, 64u);
+    workgroupBarrier();
+
+    let size = textureDimensions(Source);
+    let texel = vec2<i32>(id.xy);
+    if (texel.x >= size.x || texel.y >= size.y) {
+        return;
+    }
+    let source = loadSource(texel);
+    var color = source.xyz;
+    if (uniforms.ditherSize > 1u) {
+        color = color + (bayer(id.xy, uniforms.ditherSize) - 0.5) * uniforms.ditherSpread;
+    }
+    storeTarget(texel, nearest(color), source.w);
+}
+
+// Floyd-Steinberg error diffusion. Every invocation owns a row and works along it two pixels behind
+// the row above, so everything a pixel needs from the previous row has been written by the time it's reached.
+[[stage(compute), workgroup_size(256)]]
+fn cs_diffuse([[builtin(local_invocation_index)]] localIndex: u32) {
+    loadPalette(localIndex, DIFFUSION_ROWS);
+    workgroupBarrier();
+
+    let size = textureDimensions(Source);
+    let width = u32(size.x);
+    let height = u32(size.y);
+    let steps = width + 2u * (DIFFUSION_ROWS - 1u);
+    for (var firstRow = 0u; firstRow < height; firstRow = firstRow + DIFFUSION_ROWS) {
+        let y = firstRow + localIndex;
+        var carry = vec3<f32>(0.0);
+        for (var step = 0u; step < steps; step = step + 1u) {
+            let x = step - 2u * localIndex;
+            if (step >= 2u * localIndex && x < width && y < height) {
+                let texel = vec2<i32>(i32(x), i32(y));
+                let source = loadSource(texel);
+                var color = source.xyz + carry;
+                if (y > 0u) {
+                    color = color + diffusion.errors[y * width + x].xyz;
+                }
+                let quantized = nearest(color);
+                storeTarget(texel, quantized, source.w);
+
+                let error = color - quantized;
+                carry = error * (7.0 / 16.0);
+                // the first write to each pixel of the next row assigns, so the buffer never needs clearing
+                if (y + 1u < height) {
+                    let below = (y + 1u) * width + x;
+                    if (x == 0u) {
+                        diffusion.errors[below] = vec4<f32>(error * (5.0 / 16.0), 0.0);
+                    }
+                    else {
+                        diffusion.errors[below - 1u] = diffusion.errors[below - 1u] + vec4<f32>(error * (3.0 / 16.0), 0.0);
+                        diffusion.errors[below] = diffusion.errors[below] + vec4<f32>(error * (5.0 / 16.0), 0.0);
+                    }
+                    if (x + 1u < width) {
+                        diffusion.errors[below + 1u] = vec4<f32>(error * (1.0 / 16.0), 0.0);
+                    }
+                }
+            }
+            storageBarrier();
+        }
+    }
+}
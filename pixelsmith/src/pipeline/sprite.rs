@@ -5,20 +5,70 @@ use crate::pipeline::{COLOR_TARGET_STATE, PRIMITIVE_STATE};
 use crate::registry::{RegistryKey, TextureRegistry};
 
 
+/// Maximum number of lights the sprite shader handles, must match `MAX_LIGHTS` in `canvas_sprite.wgsl`
+pub const MAX_LIGHTS: usize = 16;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CanvasSpriteLight {
+    /// rgb color, alpha is intensity
+    pub color: [f32; 4],
+    /// x, y, height, falloff exponent (0 to disable falloff)
+    pub position: [f32; 4],
+    /// Directional: unit vector towards the light. Spot: unit vector the cone points along.
+    pub direction: [f32; 4],
+    /// Hemisphere ground color
+    pub ground_color: [f32; 4],
+    /// `LightKind::index`
+    pub kind: u32,
+    pub range: f32,
+    /// Cosines of the spot cone's full-brightness and cutoff half-angles
+    pub cone_inner_cos: f32,
+    pub cone_outer_cos: f32,
+    /// Per-light multipliers on top of the scene's global diffuse and specular intensities
+    pub diffuse: f32,
+    pub specular: f32,
+    pub _padding: [f32; 2],
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct CanvasSpritePipelineUniforms {
     pub matrix: [[f32; 4]; 4],
-    pub light_color: [f32; 4],
-    pub light_pos: [f32; 4],
     pub cam_pos: [f32; 4],
     pub spec_power: f32,
     pub ambient_intensity: f32,
     pub diffuse_intensity: f32,
     pub specular_intensity: f32,
     pub sprite_size: [f32; 2],
-    pub light_falloff: f32,
     pub map_view_type: u32,
+    /// 1.0 if the AO map also applies to diffuse light, 0.0 otherwise
+    pub ao_diffuse: f32,
+    pub light_count: u32,
+    pub shadows_enabled: u32,
+    pub shadow_steps: u32,
+    pub shadow_height_scale: f32,
+    pub shadow_softness: f32,
+    pub emissive_intensity: f32,
+    /// `MaterialWorkflow` as u32
+    pub material_workflow: u32,
+    /// `SpecularModel` as u32
+    pub specular_model: u32,
+    /// Number of toon shading bands, 0 when disabled
+    pub toon_bands: u32,
+    /// Bayer matrix width, 0 for no dithering
+    pub dither_size: u32,
+    pub dither_spread: f32,
+    /// Blend factor of ramp lighting, 0 when disabled
+    pub ramp_strength: f32,
+    /// Number of palette swap mappings to apply to the albedo, 0 for none
+    pub swap_count: u32,
+    /// Multiplier colors are hashed with to find their texel in the swap lookup texture
+    pub swap_hash_multiplier: u32,
+    pub _padding: [u32; 2],
+    /// Up to `MAX_TOON_BANDS - 1` thresholds, unused ones set above 1
+    pub band_thresholds: [[f32; 4]; 2],
+    pub lights: [CanvasSpriteLight; MAX_LIGHTS],
 }
 unsafe impl bytemuck::Zeroable for CanvasSpritePipelineUniforms {}
 unsafe impl bytemuck::Pod for CanvasSpritePipelineUniforms {}
@@ -70,7 +120,7 @@ impl ViewportSpritePipeline {
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("canvas sprite pipeline layout"),
-            bind_group_layouts: &[&uniform_layout, &GLOBALS.get().sprite_maps_bind_layout],
+            bind_group_layouts: &[&uniform_layout, &GLOBALS.get().sprite_maps_bind_layout, &GLOBALS.get().lookup_bind_layout],
             push_constant_ranges: &[],
         });
 
@@ -117,10 +167,17 @@ impl ViewportSpritePipeline {
         GLOBALS.get().queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniforms));
     }
 
-    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, maps_bg: usize) {
-        let rt_info = registry.find(self.rt_key).unwrap();
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, maps_bg: usize, lookup_bg: usize) {
+        self.render_to(encoder, registry, maps_bg, lookup_bg, self.rt_key);
+    }
+
+    /// Renders into `target` instead of the viewport's render target.
+    pub fn render_to(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, maps_bg: usize, lookup_bg: usize,
+                     target: RegistryKey) {
+        let rt_info = registry.find(target).unwrap();
         let view = rt_info.view();
         let bind_group = registry.find_bind_group(maps_bg).unwrap();
+        let lookup_bind_group = registry.find_bind_group(lookup_bg).unwrap();
 
         {
             let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -139,6 +196,7 @@ impl ViewportSpritePipeline {
             rpass.set_pipeline(&self.pipeline);
             rpass.set_bind_group(0, &self.uniform_bind_group, &[]);
             rpass.set_bind_group(1, bind_group, &[]);
+            rpass.set_bind_group(2, lookup_bind_group, &[]);
             rpass.set_vertex_buffer(0, self.sprite_vg.vertex_buffer().slice(..));
             rpass.set_index_buffer(self.sprite_vg.index_buffer().slice(..), wgpu::IndexFormat::Uint16);
             rpass.draw_indexed(0..6, 0, 0..1);
@@ -1,29 +1,199 @@
-use std::path::PathBuf;
-use crate::sprite::SceneData;
+use std::path::{Path, PathBuf};
+use serde_derive::{Serialize, Deserialize};
+use crate::lights::LightingInfo;
+use crate::sprite::{SceneData, SceneLoadError};
+
+
+#[derive(Debug)]
+pub enum ManifestError {
+    /// The project directory has no `pixproject.yaml`
+    Missing(PathBuf),
+    Io(PathBuf, std::io::Error),
+    /// The manifest isn't valid YAML or doesn't match the expected layout.
+    /// `line` and `column` are 1-based when present.
+    Parse { path: PathBuf, line: Option<usize>, column: Option<usize>, message: String },
+    /// One of the `sprites` entries isn't a valid glob pattern
+    InvalidPattern { pattern: String, message: String },
+}
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestError::Missing(path) => write!(f, "no project manifest found at {}", path.display()),
+            ManifestError::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            ManifestError::Parse { path, line: Some(line), column: Some(column), message } =>
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message),
+            ManifestError::Parse { path, message, .. } => write!(f, "{}: {}", path.display(), message),
+            ManifestError::InvalidPattern { pattern, message } =>
+                write!(f, "invalid sprite pattern '{}': {}", pattern, message),
+        }
+    }
+}
+
+
+/// File names of the maps in each sprite directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapFileNames {
+    pub albedo: String,
+    pub normal: String,
+    pub specular: String,
+    pub height: String,
+    pub ao: String,
+    pub emissive: String,
+}
+impl Default for MapFileNames {
+    fn default() -> Self {
+        MapFileNames {
+            albedo: "albedo.png".to_string(),
+            normal: "normal.png".to_string(),
+            specular: "specular.png".to_string(),
+            height: "height.png".to_string(),
+            ao: "ao.png".to_string(),
+            emissive: "emissive.png".to_string(),
+        }
+    }
+}
+
+
+fn default_sprite_patterns() -> Vec<String> { vec!["sprites/*".to_string()] }
+
+/// The contents of a project's `pixproject.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectManifest {
+    pub name: String,
+    /// Sprite directories or glob patterns matching them, relative to the project directory
+    #[serde(default = "default_sprite_patterns")]
+    pub sprites: Vec<String>,
+    /// Lighting used for sprites that don't have a `scene.yaml` yet
+    #[serde(default)]
+    pub default_lighting: Option<LightingInfo>,
+    #[serde(default)]
+    pub maps: MapFileNames,
+    /// Palette file loaded into the palette editor when the project is opened, relative to the
+    /// project directory. Defaults to the first palette in `palettes/`.
+    #[serde(default)]
+    pub palette: Option<PathBuf>,
+}
+
+impl ProjectManifest {
+    pub const FILE_NAME: &'static str = "pixproject.yaml";
+
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(ManifestError::Missing(path.to_path_buf())),
+            Err(e) => return Err(ManifestError::Io(path.to_path_buf(), e)),
+        };
+        serde_yaml::from_str::<ProjectManifest>(text.as_str()).map_err(|e| {
+            let location = e.location();
+            let mut message = e.to_string();
+            if let Some(l) = location.as_ref() {
+                // the location is reported separately, don't repeat it in the message
+                let suffix = format!(" at line {} column {}", l.line(), l.column());
+                if message.ends_with(&suffix) {
+                    message.truncate(message.len() - suffix.len());
+                }
+            }
+            ManifestError::Parse {
+                path: path.to_path_buf(),
+                line: location.as_ref().map(|l| l.line()),
+                column: location.as_ref().map(|l| l.column()),
+                message,
+            }
+        })
+    }
+}
+
 
 #[derive(Debug, Clone)]
 pub struct ProjectData {
     pub path: PathBuf,
+    pub manifest: ProjectManifest,
+}
+
+/// A sprite directory found in a project, along with the result of loading its `scene.yaml`
+#[derive(Debug)]
+pub struct SpriteEntry {
+    pub path: PathBuf,
+    pub data: Result<SceneData, SceneLoadError>,
+    /// Set if the sprite's maps couldn't be loaded the last time it was opened
+    pub map_error: Option<String>,
+    /// Problems with the sprite's maps that were worked around the last time it was opened
+    pub map_warnings: Vec<String>,
+}
+
+impl SpriteEntry {
+    /// The sprite's display name, which is the name of its directory
+    pub fn name(&self) -> String {
+        match self.path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => self.path.display().to_string(),
+        }
+    }
 }
 
 impl ProjectData {
+    /// Opens the project in `path`, reading its manifest.
+    pub fn open(path: PathBuf) -> Result<Self, ManifestError> {
+        let manifest = ProjectManifest::load(&path.join(ProjectManifest::FILE_NAME))?;
+        Ok(ProjectData { path, manifest })
+    }
+
     pub fn ini_path(&self) -> PathBuf {
         self.path.join("imgui.ini")
     }
 
-    pub fn find_sprites(&self) -> Vec<(PathBuf, SceneData)> {
-        let mut sprites = Vec::new();
-        for d in std::fs::read_dir(self.path.join("sprites")).unwrap() {
-            match d {
-                Ok(dir) => {
-                    match SceneData::try_load(dir.path().join("scene.yaml")) {
-                        Ok(data) => sprites.push((dir.path(), data)),
-                        Err(e) => println!("{:?}", e)
+    /// Absolute path of the project palette file, if the manifest names one
+    pub fn palette_path(&self) -> Option<PathBuf> {
+        self.manifest.palette.as_ref().map(|p| self.path.join(p))
+    }
+
+    /// Directory the project's named palettes are saved in
+    pub fn palettes_dir(&self) -> PathBuf {
+        self.path.join("palettes")
+    }
+
+    pub fn default_lighting(&self) -> LightingInfo {
+        self.manifest.default_lighting.clone().unwrap_or_default()
+    }
+
+    /// Finds every sprite directory matched by the manifest's `sprites` patterns. Directories
+    /// without a `scene.yaml` get a fresh scene using the project's default lighting.
+    pub fn find_sprites(&self) -> Result<Vec<SpriteEntry>, ManifestError> {
+        let mut sprites: Vec<SpriteEntry> = Vec::new();
+        for pattern in self.manifest.sprites.iter() {
+            let full_pattern = self.path.join(pattern);
+            let paths = glob::glob(&full_pattern.to_string_lossy()).map_err(|e| ManifestError::InvalidPattern {
+                pattern: pattern.clone(),
+                message: e.to_string(),
+            })?;
+            for path in paths {
+                match path {
+                    Ok(path) => {
+                        if !path.is_dir() || sprites.iter().any(|s| s.path == path) {
+                            continue;
+                        }
+                        let scene_path = path.join("scene.yaml");
+                        let data = if scene_path.exists() {
+                            SceneData::try_load(scene_path)
+                        } else {
+                            Ok(SceneData::new(self.default_lighting()))
+                        };
+                        sprites.push(SpriteEntry { path, data, map_error: None, map_warnings: Vec::new() });
+                    }
+                    Err(e) => {
+                        let path = e.path().to_path_buf();
+                        sprites.push(SpriteEntry {
+                            path,
+                            data: Err(SceneLoadError::Io(e.into())),
+                            map_error: None,
+                            map_warnings: Vec::new(),
+                        });
                     }
                 }
-                Err(e) => println!("{:?}", e)
             }
         }
-        sprites
+        sprites.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(sprites)
     }
-}
\ No newline at end of file
+}
@@ -1,7 +1,8 @@
 use std::path::Path;
 use imgui::{Condition, WindowFlags};
+use crate::panel::ERROR_COLOR;
 
-pub fn draw_recent_window(ui: &imgui::Ui, size: [f32; 2]) -> Option<(String, String)> {
+pub fn draw_recent_window(ui: &imgui::Ui, size: [f32; 2], error: Option<&str>) -> Option<(String, String)> {
     let recent_text = match std::env::current_dir() {
         Ok(cd) => match std::fs::read_to_string(cd.join("recent.yaml")) {
             Ok(text) => Some(text),
@@ -52,6 +53,10 @@ pub fn draw_recent_window(ui: &imgui::Ui, size: [f32; 2]) -> Option<(String, Str
         .size(size, Condition::Always)
         .build(|| {
             ui.text("Open Project");
+            if let Some(error) = error {
+                let _wrap = ui.push_text_wrap_pos();
+                ui.text_colored(ERROR_COLOR, error);
+            }
             ui.text("Recent Projects");
             if ui.list_box("##recent-proj-list", &mut selected, &items.iter().collect::<Vec<_>>()[..], items.len() as i32) {
                 if selected >= 0 && selected < recent_projects.len() as i32 {
@@ -5,6 +5,7 @@ use wgpu::{BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource};
 use imgui::TextureId;
 use toolbelt::MonoCounter;
 use crate::GLOBALS;
+use crate::sprite::MapKind;
 
 
 static KEY: MonoCounter = MonoCounter::new();
@@ -34,8 +35,74 @@ pub struct TextureMapSet {
     pub normal: RegistryKey,
     pub specular: RegistryKey,
     pub height: RegistryKey,
+    pub emissive: RegistryKey,
     pub extras: Vec<RegistryKey>,
     pub bind_group_idx: usize,
+    /// CPU copies of the textures, for tools that analyze or generate maps
+    pub images: MapImages,
+}
+
+impl TextureMapSet {
+    pub fn key(&self, kind: MapKind) -> RegistryKey {
+        match kind {
+            MapKind::Albedo => self.albedo,
+            MapKind::Normal => self.normal,
+            MapKind::Specular => self.specular,
+            MapKind::Height => self.height,
+            MapKind::Ao => self.ao,
+            MapKind::Emissive => self.emissive,
+        }
+    }
+
+    /// Writes `image` to `kind`'s texture without touching `images`, so it can be shown in place
+    /// of the map and put back afterwards.
+    pub fn upload(&self, kind: MapKind, image: &image::RgbaImage, registry: &TextureRegistry) {
+        registry.find(self.key(kind)).unwrap().write(image.as_raw(), image.width(), image.height());
+    }
+
+    /// Removes all of this set's textures and its bind group from the registry.
+    pub fn release(&self, registry: &mut TextureRegistry) {
+        for key in [self.albedo, self.ao, self.normal, self.specular, self.height, self.emissive].iter().chain(self.extras.iter()) {
+            registry.remove(*key);
+        }
+        registry.remove_bind_group(self.bind_group_idx);
+    }
+}
+
+/// CPU copies of a sprite's maps. Maps that couldn't be loaded hold their neutral stand-in.
+#[derive(Debug, Clone)]
+pub struct MapImages {
+    pub albedo: Arc<image::RgbaImage>,
+    pub normal: Arc<image::RgbaImage>,
+    pub specular: Arc<image::RgbaImage>,
+    pub height: Arc<image::RgbaImage>,
+    pub ao: Arc<image::RgbaImage>,
+    pub emissive: Arc<image::RgbaImage>,
+}
+
+impl MapImages {
+    pub fn get(&self, kind: MapKind) -> &Arc<image::RgbaImage> {
+        match kind {
+            MapKind::Albedo => &self.albedo,
+            MapKind::Normal => &self.normal,
+            MapKind::Specular => &self.specular,
+            MapKind::Height => &self.height,
+            MapKind::Ao => &self.ao,
+            MapKind::Emissive => &self.emissive,
+        }
+    }
+
+    pub fn set(&mut self, kind: MapKind, image: image::RgbaImage) {
+        let slot = match kind {
+            MapKind::Albedo => &mut self.albedo,
+            MapKind::Normal => &mut self.normal,
+            MapKind::Specular => &mut self.specular,
+            MapKind::Height => &mut self.height,
+            MapKind::Ao => &mut self.ao,
+            MapKind::Emissive => &mut self.emissive,
+        };
+        *slot = Arc::new(image);
+    }
 }
 
 #[derive(Debug)]
@@ -226,4 +293,8 @@ impl TextureRegistry {
         self.bind_groups.insert(key, GLOBALS.get().device.create_bind_group(&descriptor));
         key
     }
+
+    pub fn remove_bind_group(&mut self, key: usize) -> Option<BindGroup> {
+        self.bind_groups.remove(&key)
+    }
 }
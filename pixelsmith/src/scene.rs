@@ -1,16 +1,62 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use toolbelt::cgmath::Point2;
-use parking_lot::Mutex;
-use wgpu::{BindGroupDescriptor, BindGroupEntry, BindingResource, FilterMode, SamplerDescriptor, TextureFormat, TextureUsages};
-use toolbelt::{SimpleCell, Color, ColorSpace};
-use toolbelt::drag::DragState;
+use image::{Rgba, RgbaImage};
+use wgpu::{AddressMode, BindGroupDescriptor, BindGroupEntry, BindingResource, FilterMode, SamplerDescriptor, TextureFormat, TextureUsages};
+use toolbelt::SimpleCell;
 use crate::GLOBALS;
-use crate::lights::{Light, LightingInfo};
-use crate::registry::{TextureMapSet, TextureRegistry};
+use crate::lights::LightingInfo;
+use crate::material::{MaterialInfo, RAMP_TEXTURE_WIDTH};
+use crate::palette::{SwapSettings, SWAP_TABLE_SIZE};
+use crate::project::MapFileNames;
+use crate::registry::{MapImages, RegistryKey, TextureMapSet, TextureRegistry};
+use crate::sprite::{MapKind, SceneData, SpriteLoadError, SpriteLoadErrorCause};
 
 
-pub fn TEMP_create_texture_map_set(path: PathBuf, registry: &mut TextureRegistry) -> TextureMapSet {
+/// Reads one map image from the sprite directory, checking it against the albedo's size.
+fn load_map(dir: &Path, kind: MapKind, map_names: &MapFileNames, size: Option<(u32, u32)>) -> Result<RgbaImage, SpriteLoadError> {
+    let path = dir.join(kind.file_name(map_names));
+    let error = |cause| SpriteLoadError { map: kind, path: path.clone(), cause };
+
+    let reader = match image::io::Reader::open(&path) {
+        Ok(reader) => reader,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(error(SpriteLoadErrorCause::Missing)),
+        Err(e) => return Err(error(SpriteLoadErrorCause::Io(e))),
+    };
+    let img = reader.decode().map_err(|e| error(SpriteLoadErrorCause::Decode(e)))?.to_rgba8();
+    if let Some(expected) = size {
+        if img.dimensions() != expected {
+            return Err(error(SpriteLoadErrorCause::SizeMismatch { expected, found: img.dimensions() }));
+        }
+    }
+    Ok(img)
+}
+
+/// Loads a sprite's maps into the registry. Fails only if the albedo can't be loaded, problems
+/// with any other map are returned alongside the map set and a neutral stand-in is used instead.
+pub fn TEMP_create_texture_map_set(path: PathBuf, map_names: &MapFileNames, registry: &mut TextureRegistry)
+    -> Result<(TextureMapSet, Vec<SpriteLoadError>), SpriteLoadError>
+{
+    let albedo_img = load_map(&path, MapKind::Albedo, map_names, None)?;
+    let img_size = albedo_img.dimensions();
+
+    let mut problems = Vec::new();
+    let mut load_optional = |kind: MapKind| {
+        match load_map(&path, kind, map_names, Some(img_size)) {
+            Ok(img) => img,
+            Err(e) => {
+                if kind.warn_if_missing() || !matches!(e.cause, SpriteLoadErrorCause::Missing) {
+                    problems.push(e);
+                }
+                RgbaImage::from_pixel(img_size.0, img_size.1, Rgba(kind.neutral_pixel().unwrap()))
+            }
+        }
+    };
+    let normal_img = load_optional(MapKind::Normal);
+    let specular_img = load_optional(MapKind::Specular);
+    let height_img = load_optional(MapKind::Height);
+    let ao_img = load_optional(MapKind::Ao);
+    let emissive_img = load_optional(MapKind::Emissive);
+
     let maps_sampler = GLOBALS.get().device.create_sampler(&SamplerDescriptor {
         label: Some("sprite maps sampler"),
         mag_filter: FilterMode::Nearest,
@@ -19,43 +65,47 @@ pub fn TEMP_create_texture_map_set(path: PathBuf, registry: &mut TextureRegistry
         ..Default::default()
     });
 
-    let albedo_img = image::io::Reader::open(path.join("albedo.png")).unwrap().decode().unwrap();
-    let img_size = (albedo_img.width(), albedo_img.height());
     let albedo_key = registry.create_with_data(img_size, "sprite albedo texture",
                                                    TextureFormat::Rgba8Unorm,
                                                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                                                   &albedo_img.to_rgba8().into_vec()[..]);
+                                                   albedo_img.as_raw());
 
     let normal_key = registry.create_with_data(img_size, "sprite normal texture",
                                                    TextureFormat::Rgba8Unorm,
                                                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                                                   &image::io::Reader::open(path.join("normal.png")).unwrap()
-                                                       .decode().unwrap()
-                                                       .to_rgba8().into_vec()[..]);
+                                                   normal_img.as_raw());
 
     let specular_key = registry.create_with_data(img_size, "sprite specular texture",
                                                      TextureFormat::Rgba8Unorm,
                                                      TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                                                     &image::io::Reader::open(path.join("specular.png")).unwrap()
-                                                         .decode().unwrap()
-                                                         .to_rgba8().into_vec()[..]);
+                                                     specular_img.as_raw());
 
     let height_key = registry.create_with_data(img_size, "sprite height texture",
                                                    TextureFormat::Rgba8Unorm,
                                                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-                                                   &image::io::Reader::open(path.join("height.png")).unwrap()
-                                                       .decode().unwrap()
-                                                       .to_rgba8().into_vec()[..]);
+                                                   height_img.as_raw());
+
+    let ao_key = registry.create_with_data(img_size, "sprite ao texture",
+                                               TextureFormat::Rgba8Unorm,
+                                               TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                                               ao_img.as_raw());
+
+    let emissive_key = registry.create_with_data(img_size, "sprite emissive texture",
+                                                     TextureFormat::Rgba8Unorm,
+                                                     TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                                                     emissive_img.as_raw());
 
     let maps_bind_group = registry.add_bind_group(BindGroupDescriptor {
         label: Some("sprite maps bind group"),
         layout: &GLOBALS.get().sprite_maps_bind_layout,
         entries: &[
             BindGroupEntry { binding: 0, resource: BindingResource::Sampler(&maps_sampler) },
-            BindGroupEntry { binding: 1, resource: BindingResource::TextureView(&*registry.find(albedo_key).unwrap().view()) },
-            BindGroupEntry { binding: 2, resource: BindingResource::TextureView(&*registry.find(normal_key).unwrap().view()) },
-            BindGroupEntry { binding: 3, resource: BindingResource::TextureView(&*registry.find(specular_key).unwrap().view()) },
-            BindGroupEntry { binding: 4, resource: BindingResource::TextureView(&*registry.find(height_key).unwrap().view()) },
+            BindGroupEntry { binding: 1, resource: BindingResource::TextureView(&registry.find(albedo_key).unwrap().view()) },
+            BindGroupEntry { binding: 2, resource: BindingResource::TextureView(&registry.find(normal_key).unwrap().view()) },
+            BindGroupEntry { binding: 3, resource: BindingResource::TextureView(&registry.find(specular_key).unwrap().view()) },
+            BindGroupEntry { binding: 4, resource: BindingResource::TextureView(&registry.find(height_key).unwrap().view()) },
+            BindGroupEntry { binding: 5, resource: BindingResource::TextureView(&registry.find(ao_key).unwrap().view()) },
+            BindGroupEntry { binding: 6, resource: BindingResource::TextureView(&registry.find(emissive_key).unwrap().view()) },
         ],
     });
 
@@ -63,55 +113,140 @@ pub fn TEMP_create_texture_map_set(path: PathBuf, registry: &mut TextureRegistry
     registry.find_mut(normal_key).unwrap().replace_bind_group_idx(maps_bind_group);
     registry.find_mut(specular_key).unwrap().replace_bind_group_idx(maps_bind_group);
     registry.find_mut(height_key).unwrap().replace_bind_group_idx(maps_bind_group);
+    registry.find_mut(ao_key).unwrap().replace_bind_group_idx(maps_bind_group);
+    registry.find_mut(emissive_key).unwrap().replace_bind_group_idx(maps_bind_group);
 
-    TextureMapSet {
+    Ok((TextureMapSet {
         size: img_size,
         albedo: albedo_key,
-        ao: albedo_key,
+        ao: ao_key,
         normal: normal_key,
         specular: specular_key,
         height: height_key,
+        emissive: emissive_key,
         extras: vec![],
         bind_group_idx: maps_bind_group,
-    }
+        images: MapImages {
+            albedo: Arc::new(albedo_img),
+            normal: Arc::new(normal_img),
+            specular: Arc::new(specular_img),
+            height: Arc::new(height_img),
+            ao: Arc::new(ao_img),
+            emissive: Arc::new(emissive_img),
+        },
+    }, problems))
+}
+
+/// Creates the scene's lighting ramp and palette swap textures and the lookup bind group they're read through.
+fn create_lookups(material: &MaterialInfo, swaps: &SwapSettings, registry: &mut TextureRegistry) -> (RegistryKey, RegistryKey, u32, usize) {
+    let lookup_sampler = GLOBALS.get().device.create_sampler(&SamplerDescriptor {
+        label: Some("sprite lookup sampler"),
+        address_mode_u: AddressMode::ClampToEdge,
+        address_mode_v: AddressMode::ClampToEdge,
+        mag_filter: FilterMode::Linear,
+        min_filter: FilterMode::Linear,
+        mipmap_filter: FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    let ramp_key = registry.create_with_data((RAMP_TEXTURE_WIDTH, 1), "lighting ramp texture",
+                                                 TextureFormat::Rgba8Unorm,
+                                                 TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                                                 &material.ramp.bake());
+
+    let swap_table = swaps.bake();
+    let swap_key = registry.create_with_data((SWAP_TABLE_SIZE, SWAP_TABLE_SIZE), "palette swap texture",
+                                                 TextureFormat::Rgba8Unorm,
+                                                 TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                                                 &swap_table.pixels);
+
+    let lookup_bind_group = registry.add_bind_group(BindGroupDescriptor {
+        label: Some("sprite lookup bind group"),
+        layout: &GLOBALS.get().lookup_bind_layout,
+        entries: &[
+            BindGroupEntry { binding: 0, resource: BindingResource::Sampler(&lookup_sampler) },
+            BindGroupEntry { binding: 1, resource: BindingResource::TextureView(&registry.find(ramp_key).unwrap().view()) },
+            BindGroupEntry { binding: 2, resource: BindingResource::TextureView(&registry.find(swap_key).unwrap().view()) },
+        ],
+    });
+    registry.find_mut(ramp_key).unwrap().replace_bind_group_idx(lookup_bind_group);
+    registry.find_mut(swap_key).unwrap().replace_bind_group_idx(lookup_bind_group);
+
+    (ramp_key, swap_key, swap_table.multiplier, lookup_bind_group)
 }
 
 
 #[derive(Debug)]
 /// Contains information about the sprite and lighting being displayed
 pub struct Scene {
+    /// The sprite directory this scene was loaded from
+    pub path: PathBuf,
     pub textures: TextureMapSet,
     pub lighting: LightingInfo,
+    pub material: MaterialInfo,
+    pub swaps: SwapSettings,
+    /// 1D texture `material.ramp` is baked into
+    pub ramp_texture: RegistryKey,
+    /// Lookup texture the active set of `swaps` is baked into
+    pub swap_texture: RegistryKey,
+    /// Multiplier colors are hashed with to find their texel in `swap_texture`
+    pub swap_hash_multiplier: u32,
+    /// Bind group for the ramp and swap textures
+    pub lookup_bind_group_idx: usize,
+    /// Index into `lighting.lights` of the light being edited
+    pub selected_light: Option<usize>,
+    /// Problems with the sprite's maps that were worked around when loading
+    pub load_problems: Vec<SpriteLoadError>,
 }
 
 impl Scene {
-    fn create(textures: TextureMapSet) -> SimpleCell<Self> {
+    fn create(path: PathBuf, textures: TextureMapSet, lighting: LightingInfo, material: MaterialInfo, swaps: SwapSettings,
+              load_problems: Vec<SpriteLoadError>, registry: &mut TextureRegistry) -> SimpleCell<Self> {
+        let (ramp_texture, swap_texture, swap_hash_multiplier, lookup_bind_group_idx) = create_lookups(&material, &swaps, registry);
         SimpleCell::new(Scene {
+            path,
             textures,
-            lighting: LightingInfo {
-                enable_light_parallax: false,
-                lights: vec![
-                    Light {
-                        position: Point2::new(-16.0, -16.0),
-                        height: 50.0,
-                        color: Color::white(ColorSpace::RGBA).with_alpha(2.0),
-                        gizmo_hovered: false,
-                        falloff_exp: 2.0,
-                        enable_falloff: true,
-                        drag_state: DragState::new(),
-                        diffuse: 1.0,
-                        specular: 1.0
-                    }
-                ],
-                global_ambient: 0.05,
-                global_diffuse: 0.475,
-                global_specular: 0.475
-            }
+            ramp_texture,
+            swap_texture,
+            swap_hash_multiplier,
+            lookup_bind_group_idx,
+            selected_light: if lighting.lights.is_empty() { None } else { Some(0) },
+            lighting,
+            material,
+            swaps,
+            load_problems,
         })
     }
 
-    pub fn from_sprite_path(path: PathBuf, registry: &mut TextureRegistry) -> SimpleCell<Self> {
-        let textures = TEMP_create_texture_map_set(path, registry);
-        Self::create(textures)
+    pub fn from_sprite_path(path: PathBuf, data: &SceneData, map_names: &MapFileNames, registry: &mut TextureRegistry)
+        -> Result<SimpleCell<Self>, SpriteLoadError>
+    {
+        let (textures, problems) = TEMP_create_texture_map_set(path.clone(), map_names, registry)?;
+        Ok(Self::create(path, textures, data.lighting.clone(), data.material.clone(), data.swaps.clone(), problems, registry))
+    }
+
+    /// Re-bakes `material.ramp` into the ramp texture after it's been edited.
+    pub fn upload_ramp(&self, registry: &TextureRegistry) {
+        registry.find(self.ramp_texture).unwrap().write(&self.material.ramp.bake(), RAMP_TEXTURE_WIDTH, 1);
+    }
+
+    /// Re-bakes the active palette swap into the swap texture after `swaps` has been edited.
+    pub fn upload_swaps(&mut self, registry: &TextureRegistry) {
+        let table = self.swaps.bake();
+        registry.find(self.swap_texture).unwrap().write(&table.pixels, SWAP_TABLE_SIZE, SWAP_TABLE_SIZE);
+        self.swap_hash_multiplier = table.multiplier;
+    }
+
+    /// Removes all of this scene's textures and bind groups from the registry.
+    pub fn release(&self, registry: &mut TextureRegistry) {
+        self.textures.release(registry);
+        registry.remove(self.ramp_texture);
+        registry.remove(self.swap_texture);
+        registry.remove_bind_group(self.lookup_bind_group_idx);
+    }
+
+    /// Path of the `scene.yaml` file this scene is saved to.
+    pub fn scene_file_path(&self) -> PathBuf {
+        self.path.join("scene.yaml")
     }
 }
@@ -1,6 +1,10 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use yaml_rust::{EmitError, ScanError};
 use crate::lights::LightingInfo;
+use crate::material::MaterialInfo;
+use crate::palette::SwapSettings;
+use crate::project::MapFileNames;
+use crate::viewport::ViewportData;
 use serde_derive::{Serialize, Deserialize};
 
 #[derive(Debug)]
@@ -11,6 +15,17 @@ pub enum SceneLoadError {
     Io(std::io::Error),
     WrongNumberOfDocuments
 }
+impl std::fmt::Display for SceneLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneLoadError::YamlScanError(e) => write!(f, "invalid YAML: {}", e),
+            SceneLoadError::YamlEmitError(e) => write!(f, "failed to re-emit YAML: {:?}", e),
+            SceneLoadError::SerdeYamlError(e) => write!(f, "invalid scene data: {}", e),
+            SceneLoadError::Io(e) => write!(f, "{}", e),
+            SceneLoadError::WrongNumberOfDocuments => write!(f, "expected exactly one YAML document"),
+        }
+    }
+}
 impl From<ScanError> for SceneLoadError {
     fn from(e: ScanError) -> Self {
         SceneLoadError::YamlScanError(e)
@@ -32,13 +47,150 @@ impl From<serde_yaml::Error> for SceneLoadError {
     }
 }
 
+#[derive(Debug)]
+pub enum SceneSaveError {
+    SerdeYamlError(serde_yaml::Error),
+    Io(std::io::Error),
+}
+impl std::fmt::Display for SceneSaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SceneSaveError::SerdeYamlError(e) => write!(f, "failed to serialize scene: {}", e),
+            SceneSaveError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+impl From<std::io::Error> for SceneSaveError {
+    fn from(e: std::io::Error) -> Self {
+        SceneSaveError::Io(e)
+    }
+}
+impl From<serde_yaml::Error> for SceneSaveError {
+    fn from(e: serde_yaml::Error) -> Self {
+        SceneSaveError::SerdeYamlError(e)
+    }
+}
+
+/// One of the image maps that make up a sprite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind { Albedo, Normal, Specular, Height, Ao, Emissive }
+impl MapKind {
+    pub fn file_name(self, names: &MapFileNames) -> &str {
+        match self {
+            MapKind::Albedo => &names.albedo,
+            MapKind::Normal => &names.normal,
+            MapKind::Specular => &names.specular,
+            MapKind::Height => &names.height,
+            MapKind::Ao => &names.ao,
+            MapKind::Emissive => &names.emissive,
+        }
+    }
+
+    /// The pixel value used in place of this map when it's missing or unusable.
+    /// `None` for the albedo, which is required.
+    pub fn neutral_pixel(self) -> Option<[u8; 4]> {
+        match self {
+            MapKind::Albedo => None,
+            MapKind::Normal => Some([128, 128, 255, 255]),
+            // mid gray reads as moderate highlights in either workflow. Black would be a mirror
+            // under roughness, and the stand-in isn't rebuilt when the workflow changes.
+            MapKind::Specular => Some([128, 128, 128, 255]),
+            MapKind::Height => Some([0, 0, 0, 255]),
+            MapKind::Ao => Some([255, 255, 255, 255]),
+            MapKind::Emissive => Some([0, 0, 0, 255]),
+        }
+    }
+
+    /// Whether a sprite without this map is worth warning about. Most sprites have nothing
+    /// that glows, so a missing emissive map is expected.
+    pub fn warn_if_missing(self) -> bool {
+        self != MapKind::Emissive
+    }
+
+    /// Describes the stand-in from `neutral_pixel` for messages.
+    fn neutral_description(self) -> &'static str {
+        match self {
+            MapKind::Albedo => "nothing",
+            MapKind::Normal => "a flat normal",
+            MapKind::Specular => "a medium specular/roughness",
+            MapKind::Height => "zero height",
+            MapKind::Ao => "no occlusion",
+            MapKind::Emissive => "no emission",
+        }
+    }
+}
+impl std::fmt::Display for MapKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            MapKind::Albedo => "albedo",
+            MapKind::Normal => "normal",
+            MapKind::Specular => "specular",
+            MapKind::Height => "height",
+            MapKind::Ao => "ambient occlusion",
+            MapKind::Emissive => "emissive",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum SpriteLoadErrorCause {
+    Missing,
+    Io(std::io::Error),
+    Decode(image::ImageError),
+    /// The map's size doesn't match the albedo's
+    SizeMismatch { expected: (u32, u32), found: (u32, u32) },
+}
+
+/// A problem with one of a sprite's maps. Fatal for the albedo, any other map is replaced
+/// with a neutral stand-in.
+#[derive(Debug)]
+pub struct SpriteLoadError {
+    pub map: MapKind,
+    pub path: PathBuf,
+    pub cause: SpriteLoadErrorCause,
+}
+impl std::fmt::Display for SpriteLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.cause {
+            SpriteLoadErrorCause::Missing => write!(f, "{} map not found at {}", self.map, self.path.display())?,
+            SpriteLoadErrorCause::Io(e) => write!(f, "failed to read {} map {}: {}", self.map, self.path.display(), e)?,
+            SpriteLoadErrorCause::Decode(e) => write!(f, "failed to decode {} map {}: {}", self.map, self.path.display(), e)?,
+            SpriteLoadErrorCause::SizeMismatch { expected, found } =>
+                write!(f, "{} map {} is {}x{} but the albedo is {}x{}",
+                       self.map, self.path.display(), found.0, found.1, expected.0, expected.1)?,
+        }
+        if self.map.neutral_pixel().is_some() {
+            write!(f, ", using {} instead", self.map.neutral_description())?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SceneData {
     pub viewports_open: [bool; 4],
-    pub lighting: LightingInfo
+    /// Per-viewport view state, `None` for viewports that have never been opened
+    #[serde(default)]
+    pub viewports: [Option<ViewportData>; 4],
+    pub lighting: LightingInfo,
+    #[serde(default)]
+    pub material: MaterialInfo,
+    #[serde(default)]
+    pub swaps: SwapSettings,
 }
 
 impl SceneData {
+    /// Scene data for a sprite that hasn't been saved yet, with only the first viewport open.
+    pub fn new(lighting: LightingInfo) -> Self {
+        SceneData {
+            viewports_open: [true, false, false, false],
+            viewports: [None, None, None, None],
+            lighting,
+            material: MaterialInfo::default(),
+            swaps: SwapSettings::default(),
+        }
+    }
+
     pub fn try_load(path: PathBuf) -> Result<Self, SceneLoadError> {
         let docs = yaml_rust::YamlLoader::load_from_str(std::fs::read_to_string(path)?.as_str())?;
         if docs.len() != 1 {
@@ -50,4 +202,48 @@ impl SceneData {
 
         Ok(data)
     }
+
+    /// Writes this scene to `path` (usually `<sprite dir>/scene.yaml`), replacing any existing file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneSaveError> {
+        let yaml_str = serde_yaml::to_string(self)?;
+        std::fs::write(path, yaml_str)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::material::MaterialWorkflow;
+    use super::*;
+
+    /// A scene saved before sprites had materials
+    const OLD_SCENE: &str = "---
+viewports_open: [true, false, false, false]
+lighting:
+  enable_light_parallax: false
+  global_ambient: 0.05
+  global_diffuse: 0.475
+  global_specular: 0.475
+  lights:
+    - position:
+        x: -16.0
+        y: -16.0
+      height: 50.0
+      color:
+        components: [1.0, 1.0, 1.0, 2.0]
+        space: RGBA
+      gizmo_hovered: false
+      falloff_exp: 2.0
+      enable_falloff: true
+      diffuse: 1.0
+      specular: 1.0
+";
+
+    #[test]
+    fn old_scene_keeps_specular_meaning() {
+        let data = serde_yaml::from_str::<SceneData>(OLD_SCENE).unwrap();
+        // brighter texels in specular.png still mean shinier, rather than rougher
+        assert_eq!(data.material.workflow, MaterialWorkflow::Specular);
+    }
 }
@@ -4,15 +4,42 @@ use wgpu::*;
 use winit::event::{ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, TouchPhase, VirtualKeyCode, WindowEvent};
 use imgui::{Condition, WindowFlags};
 use toolbelt::drag::DragState;
-use toolbelt::{SimpleCell, cgmath, Rect};
+use toolbelt::{Color, SimpleCell, cgmath, Rect};
 use crate::app::MapType;
 use crate::{GLOBALS, Toggle};
-use crate::pipeline::{COLOR_TARGET_STATE, ViewportLightGizmoPipeline, ViewportSpritePipeline};
-use crate::pipeline::sprite::CanvasSpritePipelineUniforms;
+use crate::pipeline::{COLOR_TARGET_STATE, QuantizePipeline, ViewportBlitPipeline, ViewportLightGizmoPipeline, ViewportSpritePipeline};
+use crate::lights::{Light, LightKind, MAX_TOON_BANDS};
+use crate::palette::QuantizeSettings;
+use crate::pipeline::light::{GizmoShape, LightGizmoInstance};
+use crate::pipeline::sprite::{CanvasSpriteLight, CanvasSpritePipelineUniforms, MAX_LIGHTS};
 use crate::registry::{RegistryKey, TextureRegistry};
 use crate::scene::Scene;
+use serde_derive::{Serialize, Deserialize};
 
 
+/// Maps the unit sprite quad onto an entire render target
+const FULL_TARGET_MATRIX: [[f32; 4]; 4] = [
+    [2.0, 0.0, 0.0, 0.0],
+    [0.0, -2.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0, 1.0],
+];
+
+
+/// The persistent part of a viewport's state, saved in `scene.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewportData {
+    pub zoom: f32,
+    pub offset: Vector2<f32>,
+    pub shown_map_type: MapType,
+    #[serde(default)]
+    pub pixel_perfect: bool,
+    #[serde(default)]
+    pub snap_lights: bool,
+    #[serde(default)]
+    pub quantize: QuantizeSettings,
+}
+
 // TODO: make quick viewport rendering as simple as possible e.g. for previews
 //       Viewport::new(params...).render(...)
 /// One rendered view into a Scene
@@ -21,6 +48,10 @@ pub struct Viewport {
     rt_key: RegistryKey,
     sprite_pipeline: ViewportSpritePipeline,
     light_gizmo_pipeline: ViewportLightGizmoPipeline,
+    blit_pipeline: ViewportBlitPipeline,
+    quantize_pipeline: QuantizePipeline,
+    /// Sprite-sized target the scene is lit into when `pixel_perfect` or quantizing is on
+    pixel_rt_key: Option<RegistryKey>,
     /// view offset in sprite space (sprite pixels)
     pub offset: Vector2<f32>,
     /// zoom * sprite pixels = screen pixels
@@ -31,11 +62,13 @@ pub struct Viewport {
     pub light_gizmos_interactable: Property<bool>,
     pub gizmo_opacity: Property<f32>,
     pub shown_map_type: MapType,
+    /// Light the sprite at its own resolution and upscale the result, instead of lighting every screen pixel
+    pub pixel_perfect: bool,
+    /// Move lights to the center of the sprite pixel they're in when rendering
+    pub snap_lights: bool,
+    /// Palette quantization of the lit sprite, which always renders it at sprite resolution
+    pub quantize: QuantizeSettings,
     camera_height: f32,
-    pub global_ambient: f32,
-    pub global_diffuse: f32,
-    pub global_specular: f32,
-    pub normalize_intensities: bool,
     scene: SimpleCell<Scene>
 }
 
@@ -64,6 +97,7 @@ impl Viewport {
 
         let sprite_pipeline = ViewportSpritePipeline::new(rt_key);
         let light_gizmo_pipeline = ViewportLightGizmoPipeline::new(rt_key);
+        let blit_pipeline = ViewportBlitPipeline::new(rt_key);
 
         Viewport {
             scene: (*scene).clone(),
@@ -71,6 +105,9 @@ impl Viewport {
             rt_key,
             sprite_pipeline,
             light_gizmo_pipeline,
+            blit_pipeline,
+            quantize_pipeline: QuantizePipeline::new(),
+            pixel_rt_key: None,
             offset: Vector2::new(32.0, 32.0),
             zoom: 4.0,
             drag_state: DragState::new(),
@@ -79,15 +116,43 @@ impl Viewport {
             light_gizmos_interactable: Property::new(true),
             gizmo_opacity: Property::new(0.02),
             shown_map_type: MapType::Rendered,
+            pixel_perfect: false,
+            snap_lights: false,
+            quantize: QuantizeSettings::default(),
             camera_height: 25.0,
-            global_ambient: 0.05,
-            global_diffuse: 0.475,
-            global_specular: 0.475,
-            normalize_intensities: true
         }
     }
 
 
+    /// Points this viewport at a different scene, e.g. after switching sprites.
+    pub fn set_scene(&mut self, scene: &SimpleCell<Scene>) {
+        self.scene = (*scene).clone();
+    }
+
+
+    /// Returns the state of this viewport that should be saved with the scene.
+    pub fn data(&self) -> ViewportData {
+        ViewportData {
+            zoom: self.zoom,
+            offset: self.offset,
+            shown_map_type: self.shown_map_type,
+            pixel_perfect: self.pixel_perfect,
+            snap_lights: self.snap_lights,
+            quantize: self.quantize.clone(),
+        }
+    }
+
+    /// Restores state previously returned by `data`.
+    pub fn apply_data(&mut self, data: &ViewportData) {
+        self.zoom = data.zoom.clamp(1.0, 32.0);
+        self.offset = data.offset;
+        self.shown_map_type = data.shown_map_type;
+        self.pixel_perfect = data.pixel_perfect;
+        self.snap_lights = data.snap_lights;
+        self.quantize = data.quantize.clone();
+    }
+
+
     pub fn move_offset(&mut self, delta: Vector2<f32>) {
         self.offset += self.scale_screen_to_canvas(delta);
     }
@@ -136,9 +201,10 @@ impl Viewport {
                 if pressed && self.is_hovered {
                     match button {
                         MouseButton::Left => {
-                            let light = &mut self.scene.get_mut().lighting.lights[0];
-                            if light.gizmo_hovered {
-                                light.drag_state.activate(MouseButton::Left, Some([mouse_x, mouse_y]));
+                            let scene = &mut *self.scene.get_mut();
+                            if let Some(idx) = scene.lighting.lights.iter().position(|l| l.gizmo_hovered) {
+                                scene.selected_light = Some(idx);
+                                scene.lighting.lights[idx].drag_state.activate(MouseButton::Left, Some([mouse_x, mouse_y]));
                             }
                             return true;
                         },
@@ -151,7 +217,9 @@ impl Viewport {
                 }
                 else {
                     self.drag_state.deactivate();
-                    self.scene.get_mut().lighting.lights[0].drag_state.deactivate();
+                    for light in self.scene.get_mut().lighting.lights.iter_mut() {
+                        light.drag_state.deactivate();
+                    }
                 }
             }
             Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, .. } => {
@@ -159,33 +227,48 @@ impl Viewport {
                     let delta = self.drag_state.update([position.x as f32, position.y as f32]).unwrap();
                     self.move_offset(delta);
                     if self.scene.get().lighting.enable_light_parallax {
-                        let light = &mut self.scene.get_mut().lighting.lights[0];
-                        let ratio = (light.height / self.camera_height) - 1.0;
                         let dp_canvas = self.scale_screen_to_canvas(delta);
-                        light.position += dp_canvas * ratio;
+                        for light in self.scene.get_mut().lighting.lights.iter_mut() {
+                            let ratio = (light.height / self.camera_height) - 1.0;
+                            light.position += dp_canvas * ratio;
+                        }
                     }
                 }
 
-                let light = &mut self.scene.get_mut().lighting.lights[0];
-                if light.drag_state.active() {
-                    let delta = light.drag_state.update([position.x as f32, position.y as f32]).unwrap();
-                    let dp_canvas = self.scale_screen_to_canvas(delta);
-                    if io.key_shift {
-                        light.height = (light.height - dp_canvas.y).clamp(10.0, 250.0);
-                    }
-                    if io.key_ctrl {
-                        light.color = light.color.with_alpha((light.color.alpha() * (1.0 - delta.y / 100.0)).clamp(0.25, 5.0));
-                    }
-                    if !io.key_shift && !io.key_ctrl {
-                        light.position += dp_canvas;
+                let lights = &mut self.scene.get_mut().lighting.lights;
+                for light in lights.iter_mut() {
+                    if light.drag_state.active() {
+                        let delta = light.drag_state.update([position.x as f32, position.y as f32]).unwrap();
+                        let dp_canvas = self.scale_screen_to_canvas(delta);
+                        if io.key_shift {
+                            light.height = (light.height - dp_canvas.y).clamp(10.0, 250.0);
+                        }
+                        if io.key_ctrl {
+                            light.color = light.color.with_alpha((light.color.alpha() * (1.0 - delta.y / 100.0)).clamp(0.25, 5.0));
+                        }
+                        if !io.key_shift && !io.key_ctrl {
+                            light.position += dp_canvas;
+                        }
                     }
                 }
 
+                // only the closest gizmo under the cursor is hovered, so overlapping lights can still be picked
                 let canvas_pos = self.transform_screen_to_canvas(
                     (position.x as f32 - self.bounds.x, position.y as f32 - self.bounds.y).into());
-                let light_distance = canvas_pos.distance(light.position.into());
-                light.gizmo_hovered = *self.light_gizmos_interactable.bind()
-                    && light_distance < (6.25 * (light.height / 100.0)).max(2.0);
+                let interactable = *self.light_gizmos_interactable.bind();
+                let mut closest: Option<(usize, f32)> = None;
+                for (i, light) in lights.iter_mut().enumerate() {
+                    light.gizmo_hovered = false;
+                    let light_distance = canvas_pos.distance(light.position);
+                    if interactable && light_distance < (6.25 * (light.height / 100.0)).max(2.0)
+                        && closest.is_none_or(|(_, d)| light_distance < d)
+                    {
+                        closest = Some((i, light_distance));
+                    }
+                }
+                if let Some((i, _)) = closest {
+                    lights[i].gizmo_hovered = true;
+                }
             }
             Event::WindowEvent { event: WindowEvent::MouseWheel { delta, phase: TouchPhase::Moved, .. }, .. } => {
                 if self.is_hovered {
@@ -227,10 +310,36 @@ impl Viewport {
         self.rt_key = Viewport::recreate_render_target(new_size, Some(self.rt_key), registry);
         self.sprite_pipeline.rt_key = self.rt_key;
         self.light_gizmo_pipeline.rt_key = self.rt_key;
+        self.blit_pipeline.rt_key = self.rt_key;
         self.rt_size = new_size;
     }
 
 
+    /// True if the scene is lit into the sprite-sized target rather than straight into the viewport.
+    fn renders_at_sprite_size(&self) -> bool {
+        self.pixel_perfect || self.quantize.enabled
+    }
+
+    /// Creates, resizes or frees the sprite-sized render targets to match the viewport's options and the scene's sprite.
+    fn update_pixel_target(&mut self, registry: &mut TextureRegistry) {
+        let sprite_size = self.scene.get().textures.size;
+        self.quantize_pipeline.update_target(if self.quantize.enabled { Some(sprite_size) } else { None }, registry);
+        match self.pixel_rt_key {
+            Some(key) if !self.renders_at_sprite_size() => {
+                registry.remove(key);
+                self.pixel_rt_key = None;
+            }
+            Some(key) if registry.find(key).map(|t| t.size()) != Some(sprite_size) => {
+                self.pixel_rt_key = Some(Viewport::recreate_render_target(sprite_size, Some(key), registry));
+            }
+            None if self.renders_at_sprite_size() => {
+                self.pixel_rt_key = Some(Viewport::recreate_render_target(sprite_size, None, registry));
+            }
+            _ => {}
+        }
+    }
+
+
     pub fn draw(&mut self, ui: &imgui::Ui, num: usize, registry: &mut TextureRegistry) {
         let rt = registry.find(self.rt_key).unwrap();
         let [mouse_x, mouse_y] = ui.io().mouse_pos;
@@ -255,6 +364,15 @@ impl Viewport {
                         }
                         token.end();
                     }
+                    if let Some(token) = ui.begin_menu("Options") {
+                        if ui.menu_item_config("Pixel Perfect").selected(self.pixel_perfect).build() {
+                            self.pixel_perfect.toggle();
+                        }
+                        if ui.menu_item_config("Snap Lights to Pixels").selected(self.snap_lights).build() {
+                            self.snap_lights.toggle();
+                        }
+                        token.end();
+                    }
                     token.end();
                 }
 
@@ -265,6 +383,8 @@ impl Viewport {
                     self.resize((self.bounds.w.abs().floor() as u32, self.bounds.h.abs().floor() as u32), registry);
                 }
 
+                self.update_pixel_target(registry);
+
                 ui.get_window_draw_list().add_image(self.rt_key.into(), [x, y], [x+w, y+h]).build();
                 self.is_hovered = ui.is_window_hovered() && self.viewport_bounds().test(mouse_x, mouse_y);
             });
@@ -281,10 +401,10 @@ impl Viewport {
     }
 
 
-    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry) {
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, registry: &TextureRegistry, palette: &[Color]) {
         let textures = &self.scene.get().textures;
         let Vector2 { x: scale_x, y: scale_y } = self.scale_screen_to_fb((self.zoom, self.zoom).into());
-        let mut matrix = [
+        let matrix = [
             [scale_x * textures.size.0 as f32, 0.0, 0.0, 0.0],
             [0.0, -scale_y * textures.size.1 as f32, 0.0, 0.0],
             [0.0,     0.0, 1.0, 0.0],
@@ -295,35 +415,141 @@ impl Viewport {
         let offset_screen = self.scale_canvas_to_screen(self.offset);
         let center_vp = self.scale_screen_to_canvas(center_screen - offset_screen);
 
-        let l = &self.scene.get().lighting.lights[0];
+        let scene = self.scene.get();
+        let pixel_target = if self.renders_at_sprite_size() { self.pixel_rt_key } else { None };
+        let light_position = |l: &Light| {
+            if self.snap_lights {
+                Point2::new(l.position.x.floor() + 0.5, l.position.y.floor() + 0.5)
+            } else {
+                l.position
+            }
+        };
+
+        let mut lights = [CanvasSpriteLight::default(); MAX_LIGHTS];
+        for (gpu_light, l) in lights.iter_mut().zip(scene.lighting.lights.iter()) {
+            let position = light_position(l);
+            *gpu_light = CanvasSpriteLight {
+                color: *l.color.components_4(),
+                position: [position.x, position.y, l.height, if l.enable_falloff { l.falloff_exp } else { 0.0 }],
+                kind: l.kind.index() as u32,
+                range: l.range.max(1.0),
+                diffuse: l.diffuse,
+                specular: l.specular,
+                ..Default::default()
+            };
+            match l.kind {
+                LightKind::Point => {}
+                LightKind::Directional { angle, elevation } => {
+                    let (angle, elevation) = (angle.to_radians(), elevation.to_radians());
+                    gpu_light.direction = [angle.cos() * elevation.cos(), angle.sin() * elevation.cos(), elevation.sin(), 0.0];
+                }
+                LightKind::Spot { direction, cone_angle, softness } => {
+                    let direction = direction.to_radians();
+                    let half_angle = (cone_angle / 2.0).to_radians();
+                    gpu_light.direction = [direction.cos(), direction.sin(), 0.0, 0.0];
+                    gpu_light.cone_outer_cos = half_angle.cos();
+                    // keep the edges apart so the shader's smoothstep stays defined
+                    gpu_light.cone_inner_cos = (half_angle * (1.0 - softness.clamp(0.0, 1.0))).cos().max(gpu_light.cone_outer_cos + 0.0001);
+                }
+                LightKind::Hemisphere { ground } => {
+                    gpu_light.ground_color = *ground.components_4();
+                }
+            }
+        }
         self.sprite_pipeline.update_uniforms(CanvasSpritePipelineUniforms {
-            matrix,
-            light_color: *l.color.components_4(),
-            light_pos: [l.position.x, l.position.y, l.height, 0.0],
+            // when lighting at sprite resolution the sprite fills the whole target
+            matrix: if pixel_target.is_some() { FULL_TARGET_MATRIX } else { matrix },
             cam_pos: [center_vp.x, center_vp.y, self.camera_height, 0.0],
-            spec_power: 32.0,
-            ambient_intensity: self.global_ambient,
-            diffuse_intensity: self.global_diffuse,
-            specular_intensity: self.global_specular,
+            spec_power: scene.material.spec_power.max(1.0),
+            ambient_intensity: scene.lighting.global_ambient,
+            diffuse_intensity: scene.lighting.global_diffuse,
+            specular_intensity: scene.lighting.global_specular,
             sprite_size: [textures.size.0 as f32, textures.size.1 as f32],
-            light_falloff: if l.enable_falloff { l.falloff_exp } else { 0.0 },
             map_view_type: self.shown_map_type as u32,
+            ao_diffuse: if scene.lighting.ao_affects_diffuse { 1.0 } else { 0.0 },
+            light_count: scene.lighting.lights.len().min(MAX_LIGHTS) as u32,
+            shadows_enabled: scene.lighting.shadows.enabled as u32,
+            shadow_steps: scene.lighting.shadows.steps,
+            shadow_height_scale: scene.lighting.shadows.height_scale,
+            shadow_softness: scene.lighting.shadows.softness,
+            emissive_intensity: scene.lighting.emissive_intensity,
+            material_workflow: scene.material.workflow as u32,
+            specular_model: scene.material.specular_model as u32,
+            toon_bands: if scene.lighting.toon.enabled { scene.lighting.toon.bands.clamp(2, MAX_TOON_BANDS) } else { 0 },
+            dither_size: scene.lighting.toon.dither.size(),
+            dither_spread: scene.lighting.toon.dither_spread,
+            ramp_strength: if scene.material.ramp.enabled { scene.material.ramp.strength } else { 0.0 },
+            swap_count: scene.swaps.active_count(),
+            swap_hash_multiplier: scene.swap_hash_multiplier,
+            _padding: [0; 2],
+            band_thresholds: scene.lighting.toon.packed_thresholds(),
+            lights,
         });
-        self.sprite_pipeline.render(encoder, registry, self.scene.get().textures.bind_group_idx);
-
-        let l = &self.scene.get().lighting.lights[0];
-
-        matrix[3][0] += l.position.x*scale_x;
-        matrix[3][1] -= l.position.y*scale_y;
-        let light_scale = l.height / 100.0;
-        matrix[0][0] *= light_scale;
-        matrix[1][1] *= light_scale;
+        match pixel_target {
+            Some(pixel_rt_key) => {
+                self.sprite_pipeline.render_to(encoder, registry, textures.bind_group_idx, scene.lookup_bind_group_idx, pixel_rt_key);
+                let mut shown_key = pixel_rt_key;
+                if let (true, false, Some(quantized_key)) = (self.quantize.enabled, palette.is_empty(), self.quantize_pipeline.target_key) {
+                    self.quantize_pipeline.update_uniforms(&self.quantize, palette);
+                    self.quantize_pipeline.render(encoder, registry, pixel_rt_key, self.quantize.dither);
+                    shown_key = quantized_key;
+                }
+                self.blit_pipeline.update_uniforms(&matrix);
+                self.blit_pipeline.render(encoder, registry, shown_key);
+            }
+            None => self.sprite_pipeline.render(encoder, registry, textures.bind_group_idx, scene.lookup_bind_group_idx),
+        }
 
-        let mut gizmo_color = l.color;
-        gizmo_color.components_4_mut()[3] =
-            if l.gizmo_hovered { *self.gizmo_opacity.bind() * 2.0 } else { *self.gizmo_opacity.bind() };
-        self.light_gizmo_pipeline.update_uniforms(&matrix, gizmo_color);
-        self.light_gizmo_pipeline.render(encoder, registry);
+        let mut shapes = Vec::new();
+        let mut gizmos = Vec::new();
+        for (i, l) in scene.lighting.lights.iter().take(MAX_LIGHTS).enumerate() {
+            let position = light_position(l);
+            // rotates the unit gizmo shape by `angle` degrees and places it on the light
+            let gizmo_matrix = |angle: f32| {
+                let light_scale = l.height / 100.0;
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let (sx, sy) = (matrix[0][0] * light_scale, matrix[1][1] * light_scale);
+                [
+                    [sx * cos, sy * sin, 0.0, 0.0],
+                    [-sx * sin, sy * cos, 0.0, 0.0],
+                    matrix[2],
+                    [matrix[3][0] + position.x*scale_x, matrix[3][1] - position.y*scale_y, 0.0, 1.0],
+                ]
+            };
+
+            let mut opacity = *self.gizmo_opacity.bind();
+            if scene.selected_light == Some(i) { opacity *= 3.0; }
+            if l.gizmo_hovered { opacity *= 2.0; }
+            let mut color = *l.color.components_4();
+            color[3] = opacity.min(1.0);
+
+            shapes.push(GizmoShape::Ring);
+            gizmos.push(LightGizmoInstance { matrix: gizmo_matrix(0.0), color });
+            match l.kind {
+                LightKind::Point => {}
+                LightKind::Directional { angle, .. } => {
+                    // points the way the light travels
+                    shapes.push(GizmoShape::Arrow);
+                    gizmos.push(LightGizmoInstance { matrix: gizmo_matrix(angle + 180.0), color });
+                }
+                LightKind::Spot { direction, cone_angle, .. } => {
+                    for edge in [-0.5, 0.5] {
+                        shapes.push(GizmoShape::Line);
+                        gizmos.push(LightGizmoInstance { matrix: gizmo_matrix(direction + cone_angle * edge), color });
+                    }
+                }
+                LightKind::Hemisphere { ground } => {
+                    shapes.push(GizmoShape::HalfDisc);
+                    gizmos.push(LightGizmoInstance { matrix: gizmo_matrix(0.0), color });
+                    let mut ground_color = *ground.components_4();
+                    ground_color[3] = color[3];
+                    shapes.push(GizmoShape::HalfDisc);
+                    gizmos.push(LightGizmoInstance { matrix: gizmo_matrix(180.0), color: ground_color });
+                }
+            }
+        }
+        self.light_gizmo_pipeline.update_uniforms(&gizmos);
+        self.light_gizmo_pipeline.render(encoder, registry, &shapes);
     }
 
     pub fn close(&mut self) {
@@ -2,9 +2,43 @@ use std::ops::{Index, IndexMut, Range};
 use serde_derive::{Serialize, Deserialize};
 use crate::{slice_max, slice_min};
 
+/// The space a `Color`'s components are in.
+///
+/// - RGB components are sRGB encoded, 0-1.
+/// - HSL and HSV components are all 0-1, including hue.
+/// - XYZ is CIE 1931 XYZ relative to the D65 white point, with Y from 0 to 1.
+/// - Lab is CIELAB (D65), with L from 0 to 100 and a and b roughly -128 to 128.
+/// - Oklab has L from 0 to 1 and a and b roughly -0.4 to 0.4.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ColorSpace {
-    RGB, RGBA, HSL, HSLA, HSV, HSVA, Lab, LabA,
+    RGB, RGBA, HSL, HSLA, HSV, HSVA, Lab, LabA, XYZ, XYZA, Oklab, OklabA,
+}
+
+impl ColorSpace {
+    pub fn has_alpha(self) -> bool {
+        match self {
+            ColorSpace::RGB | ColorSpace::HSL | ColorSpace::HSV | ColorSpace::Lab | ColorSpace::XYZ | ColorSpace::Oklab => false,
+            ColorSpace::RGBA | ColorSpace::HSLA | ColorSpace::HSVA | ColorSpace::LabA | ColorSpace::XYZA | ColorSpace::OklabA => true,
+        }
+    }
+
+    /// The same space with or without alpha.
+    pub fn with_alpha(self, alpha: bool) -> ColorSpace {
+        match (self, alpha) {
+            (ColorSpace::RGB | ColorSpace::RGBA, false) => ColorSpace::RGB,
+            (ColorSpace::RGB | ColorSpace::RGBA, true) => ColorSpace::RGBA,
+            (ColorSpace::HSL | ColorSpace::HSLA, false) => ColorSpace::HSL,
+            (ColorSpace::HSL | ColorSpace::HSLA, true) => ColorSpace::HSLA,
+            (ColorSpace::HSV | ColorSpace::HSVA, false) => ColorSpace::HSV,
+            (ColorSpace::HSV | ColorSpace::HSVA, true) => ColorSpace::HSVA,
+            (ColorSpace::Lab | ColorSpace::LabA, false) => ColorSpace::Lab,
+            (ColorSpace::Lab | ColorSpace::LabA, true) => ColorSpace::LabA,
+            (ColorSpace::XYZ | ColorSpace::XYZA, false) => ColorSpace::XYZ,
+            (ColorSpace::XYZ | ColorSpace::XYZA, true) => ColorSpace::XYZA,
+            (ColorSpace::Oklab | ColorSpace::OklabA, false) => ColorSpace::Oklab,
+            (ColorSpace::Oklab | ColorSpace::OklabA, true) => ColorSpace::OklabA,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -13,18 +47,104 @@ pub struct Color {
     space: ColorSpace
 }
 
+/// Returned by `Color::from_hex` for strings that aren't hex colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HexColorError {
+    /// Not 3, 4, 6 or 8 digits long, not counting a leading `#`
+    Length(usize),
+    InvalidDigit(char),
+}
+impl std::fmt::Display for HexColorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HexColorError::Length(len) => write!(f, "expected 3, 4, 6 or 8 hex digits, got {}", len),
+            HexColorError::InvalidDigit(c) => write!(f, "'{}' isn't a hex digit", c),
+        }
+    }
+}
+impl std::error::Error for HexColorError {}
+
+/// CIE XYZ of the D65 white point
+pub const D65_WHITE: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+const LINEAR_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+const XYZ_TO_LINEAR: [[f64; 3]; 3] = [
+    [ 3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660,  1.8760108,  0.0415560],
+    [ 0.0556434, -0.2040259,  1.0572252],
+];
+// CIELAB's epsilon and kappa, as exact fractions
+const LAB_E: f32 = 216.0 / 24389.0;
+const LAB_K: f32 = 24389.0 / 27.0;
+
+fn mul_mat3(m: &[[f64; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = v.map(|c| c as f64);
+    [
+        m[0][0] * x + m[0][1] * y + m[0][2] * z,
+        m[1][0] * x + m[1][1] * y + m[1][2] * z,
+        m[2][0] * x + m[2][1] * y + m[2][2] * z,
+    ].map(|c| c as f32)
+}
+
+/// Decodes one sRGB component. Values outside 0-1 are extended symmetrically instead of clamped.
+pub fn srgb_to_linear(c: f32) -> f32 {
+    let abs = c.abs();
+    let linear = if abs <= 0.04045 { abs / 12.92 } else { ((abs + 0.055) / 1.055).powf(2.4) };
+    linear.copysign(c)
+}
+
+/// Encodes one linear component as sRGB. Values outside 0-1 are extended symmetrically instead of clamped.
+pub fn linear_to_srgb(c: f32) -> f32 {
+    let abs = c.abs();
+    let srgb = if abs <= 0.0031308 { abs * 12.92 } else { 1.055 * abs.powf(1.0 / 2.4) - 0.055 };
+    srgb.copysign(c)
+}
+
+/// Hue of an RGB color in 0-1, 0 for grays.
+fn rgb_hue(r: f32, g: f32, b: f32, max: f32, chroma: f32) -> f32 {
+    let hue = if chroma < f32::EPSILON { 0.0 }
+        else if max == r { (g - b) / chroma }
+        else if max == g { (b - r) / chroma + 2.0 }
+        else             { (r - g) / chroma + 4.0 };
+    (hue / 6.0).rem_euclid(1.0)
+}
+
+/// RGB from a hue in 0-1, a chroma and the amount to add to every channel.
+fn hue_to_rgb(hue: f32, chroma: f32, m: f32) -> [f32; 3] {
+    let h_prime = hue.rem_euclid(1.0) * 6.0;
+    let x = chroma * (1.0 - (h_prime % 2.0 - 1.0).abs());
+
+    let (r1, g1, b1) = {
+        if      h_prime < 1.0 { (chroma, x, 0.0) }
+        else if h_prime < 2.0 { (x, chroma, 0.0) }
+        else if h_prime < 3.0 { (0.0, chroma, x) }
+        else if h_prime < 4.0 { (0.0, x, chroma) }
+        else if h_prime < 5.0 { (x, 0.0, chroma) }
+        else                  { (chroma, 0.0, x) }
+    };
+    [r1 + m, g1 + m, b1 + m]
+}
+
 macro_rules! const_color_fn {
-    ($name:ident => RGB($r:literal, $g:literal, $b:literal) HSL($h:literal, $s:literal, $l:literal) HSV($h2:literal, $s2:literal, $v:literal) Lab($lab_l:literal, $lab_a:literal, $lab_b:literal)) => {
+    ($name:ident => RGB($r:literal, $g:literal, $b:literal) HSL($h:literal, $s:literal, $l:literal) HSV($h2:literal, $s2:literal, $v:literal) Lab($lab_l:literal, $lab_a:literal, $lab_b:literal) XYZ($x:literal, $y:literal, $z:literal) Oklab($ok_l:literal, $ok_a:literal, $ok_b:literal)) => {
         pub const fn $name(space: ColorSpace) -> Color {
             match space {
-                ColorSpace::RGB  => Color::from_rgb ($r, $g, $b),
-                ColorSpace::RGBA => Color::from_rgba($r, $g, $b, 1.0),
-                ColorSpace::HSL  => Color::from_hsl ($h, $s, $l),
-                ColorSpace::HSLA => Color::from_hsla($h, $s, $l, 1.0),
-                ColorSpace::HSV  => Color::from_hsv ($h2, $s2, $v),
-                ColorSpace::HSVA => Color::from_hsva($h2, $s2, $v, 1.0),
-                ColorSpace::Lab  => Color::from_lab ($lab_l, $lab_a, $lab_b),
-                ColorSpace::LabA => Color::from_laba($lab_l, $lab_a, $lab_b, 1.0),
+                ColorSpace::RGB    => Color::from_rgb   ($r, $g, $b),
+                ColorSpace::RGBA   => Color::from_rgba  ($r, $g, $b, 1.0),
+                ColorSpace::HSL    => Color::from_hsl   ($h, $s, $l),
+                ColorSpace::HSLA   => Color::from_hsla  ($h, $s, $l, 1.0),
+                ColorSpace::HSV    => Color::from_hsv   ($h2, $s2, $v),
+                ColorSpace::HSVA   => Color::from_hsva  ($h2, $s2, $v, 1.0),
+                ColorSpace::Lab    => Color::from_lab   ($lab_l, $lab_a, $lab_b),
+                ColorSpace::LabA   => Color::from_laba  ($lab_l, $lab_a, $lab_b, 1.0),
+                ColorSpace::XYZ    => Color::from_xyz   ($x, $y, $z),
+                ColorSpace::XYZA   => Color::from_xyza  ($x, $y, $z, 1.0),
+                ColorSpace::Oklab  => Color::from_oklab ($ok_l, $ok_a, $ok_b),
+                ColorSpace::OklabA => Color::from_oklaba($ok_l, $ok_a, $ok_b, 1.0),
             }
         }
     }
@@ -33,17 +153,56 @@ macro_rules! const_color_fn {
 
 #[rustversion::attr(nightly, feature(split_array))]
 impl Color {
-    const_color_fn! { black => RGB(0.0, 0.0, 0.0) HSL(0.0, 0.0, 0.0) HSV(0.0, 0.0, 0.0) Lab(0.0, 0.0, 0.0) }
-    const_color_fn! { white => RGB(1.0, 1.0, 1.0) HSL(0.0, 0.0, 1.0) HSV(0.0, 0.0, 1.0) Lab(1.0, 0.0, 0.0) }
-
-    pub const fn from_rgb (r: f32, g: f32, b: f32) -> Color { Color { components: [r, g, b, 1.0], space: ColorSpace::RGB } }
-    pub const fn from_rgba(r: f32, g: f32, b: f32, a: f32) -> Color { Color { components: [r, g, b, a], space: ColorSpace::RGBA } }
-    pub const fn from_hsl (h: f32, s: f32, l: f32) -> Color { Color { components: [h, s, l, 1.0], space: ColorSpace::HSL } }
-    pub const fn from_hsla(h: f32, s: f32, l: f32, a: f32) -> Color { Color { components: [h, s, l, a], space: ColorSpace::HSLA } }
-    pub const fn from_hsv (h: f32, s: f32, v: f32) -> Color { Color { components: [h, s, v, 1.0], space: ColorSpace::HSV } }
-    pub const fn from_hsva(h: f32, s: f32, v: f32, a: f32) -> Color { Color { components: [h, s, v, a], space: ColorSpace::HSVA } }
-    pub const fn from_lab (l: f32, a: f32, b: f32) -> Color { Color { components: [l, a, b, 1.0], space: ColorSpace::Lab } }
-    pub const fn from_laba(l: f32, a: f32, b: f32, alpha: f32) -> Color { Color { components: [l, a, b, alpha], space: ColorSpace::LabA } }
+    const_color_fn! { black => RGB(0.0, 0.0, 0.0) HSL(0.0, 0.0, 0.0) HSV(0.0, 0.0, 0.0) Lab(0.0, 0.0, 0.0) XYZ(0.0, 0.0, 0.0) Oklab(0.0, 0.0, 0.0) }
+    const_color_fn! { white => RGB(1.0, 1.0, 1.0) HSL(0.0, 0.0, 1.0) HSV(0.0, 0.0, 1.0) Lab(100.0, 0.0, 0.0) XYZ(0.95047, 1.0, 1.08883) Oklab(1.0, 0.0, 0.0) }
+
+    pub const fn from_rgb   (r: f32, g: f32, b: f32) -> Color { Color { components: [r, g, b, 1.0], space: ColorSpace::RGB } }
+    pub const fn from_rgba  (r: f32, g: f32, b: f32, a: f32) -> Color { Color { components: [r, g, b, a], space: ColorSpace::RGBA } }
+    pub const fn from_hsl   (h: f32, s: f32, l: f32) -> Color { Color { components: [h, s, l, 1.0], space: ColorSpace::HSL } }
+    pub const fn from_hsla  (h: f32, s: f32, l: f32, a: f32) -> Color { Color { components: [h, s, l, a], space: ColorSpace::HSLA } }
+    pub const fn from_hsv   (h: f32, s: f32, v: f32) -> Color { Color { components: [h, s, v, 1.0], space: ColorSpace::HSV } }
+    pub const fn from_hsva  (h: f32, s: f32, v: f32, a: f32) -> Color { Color { components: [h, s, v, a], space: ColorSpace::HSVA } }
+    pub const fn from_lab   (l: f32, a: f32, b: f32) -> Color { Color { components: [l, a, b, 1.0], space: ColorSpace::Lab } }
+    pub const fn from_laba  (l: f32, a: f32, b: f32, alpha: f32) -> Color { Color { components: [l, a, b, alpha], space: ColorSpace::LabA } }
+    pub const fn from_xyz   (x: f32, y: f32, z: f32) -> Color { Color { components: [x, y, z, 1.0], space: ColorSpace::XYZ } }
+    pub const fn from_xyza  (x: f32, y: f32, z: f32, a: f32) -> Color { Color { components: [x, y, z, a], space: ColorSpace::XYZA } }
+    pub const fn from_oklab (l: f32, a: f32, b: f32) -> Color { Color { components: [l, a, b, 1.0], space: ColorSpace::Oklab } }
+    pub const fn from_oklaba(l: f32, a: f32, b: f32, alpha: f32) -> Color { Color { components: [l, a, b, alpha], space: ColorSpace::OklabA } }
+
+    /// An RGB color from linear (not sRGB encoded) components.
+    pub fn from_linear_rgb(r: f32, g: f32, b: f32) -> Color {
+        Color::from_rgb(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b))
+    }
+
+    /// Parses `RGB`, `RGBA`, `RRGGBB` or `RRGGBBAA`, with or without a leading `#`.
+    /// Colors with alpha digits are `RGBA`, the rest are `RGB`.
+    pub fn from_hex(hex: &str) -> Result<Color, HexColorError> {
+        let hex = hex.trim().trim_start_matches('#');
+        let mut digits = Vec::with_capacity(8);
+        for c in hex.chars() {
+            digits.push(c.to_digit(16).ok_or(HexColorError::InvalidDigit(c))? as u8);
+        }
+        let bytes: Vec<u8> = match digits.len() {
+            3 | 4 => digits.iter().map(|d| d * 17).collect(),
+            6 | 8 => digits.chunks(2).map(|pair| pair[0] * 16 + pair[1]).collect(),
+            len => return Err(HexColorError::Length(len)),
+        };
+        let [r, g, b] = [bytes[0], bytes[1], bytes[2]].map(|c| c as f32 / 255.0);
+        Ok(match bytes.get(3) {
+            Some(a) => Color::from_rgba(r, g, b, *a as f32 / 255.0),
+            None => Color::from_rgb(r, g, b),
+        })
+    }
+
+    /// Formats the color as `#RRGGBB`, or `#RRGGBBAA` if it has alpha. Components are clamped to 0-1.
+    pub fn to_hex(&self) -> String {
+        let rgb = self.to_rgb();
+        let [r, g, b, a] = rgb.components.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        if rgb.space.has_alpha() { format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a) }
+        else { format!("#{:02X}{:02X}{:02X}", r, g, b) }
+    }
+
+    pub fn space(&self) -> ColorSpace { self.space }
 
     pub fn with_alpha(self, alpha: f32) -> Color {
         let [a, b, c, _] = self.components;
@@ -54,10 +213,7 @@ impl Color {
     pub fn transparent(self) -> Color { self.with_alpha(0.0) }
 
     pub fn check_alpha(self) -> Option<f32> {
-        match self.space {
-            ColorSpace::RGB | ColorSpace::HSL | ColorSpace::HSV | ColorSpace::Lab => None,
-            ColorSpace::RGBA | ColorSpace::HSLA | ColorSpace::HSVA | ColorSpace::LabA => Some(self.components[3])
-        }
+        if self.space.has_alpha() { Some(self.components[3]) } else { None }
     }
 
     pub fn alpha(&self) -> f32 { self.components[3] }
@@ -82,6 +238,11 @@ impl Color {
     pub fn components_4(&self) -> &[f32; 4] { &self.components }
     pub fn components_4_mut(&mut self) -> &mut [f32; 4] { &mut self.components }
 
+    /// A color in `space` with this color's alpha, with or without an alpha channel like this color.
+    fn converted(&self, [a, b, c]: [f32; 3], space: ColorSpace) -> Color {
+        Color { components: [a, b, c, self.components[3]], space: space.with_alpha(self.space.has_alpha()) }
+    }
+
     /// Converts this Color into a different ColorSpace *in-place*.
     pub fn convert(&mut self, space: ColorSpace) {
         match space {
@@ -89,81 +250,74 @@ impl Color {
             ColorSpace::HSL | ColorSpace::HSLA => *self = self.to_hsl(),
             ColorSpace::HSV | ColorSpace::HSVA => *self = self.to_hsv(),
             ColorSpace::Lab | ColorSpace::LabA => *self = self.to_lab(),
+            ColorSpace::XYZ | ColorSpace::XYZA => *self = self.to_xyz(),
+            ColorSpace::Oklab | ColorSpace::OklabA => *self = self.to_oklab(),
         }
     }
 
+    /// Linear (not sRGB encoded) RGB components of this color.
+    pub fn linear_rgb(&self) -> [f32; 3] {
+        self.to_rgb().components_3().map(srgb_to_linear)
+    }
+
+    /// Converts to RGB. Lab, XYZ and Oklab colors outside the sRGB gamut aren't clamped.
     pub fn to_rgb(&self) -> Color {
         match self.space {
             ColorSpace::RGB | ColorSpace::RGBA => { *self }
             ColorSpace::HSL | ColorSpace::HSLA => {
-                let [hue, saturation, lightness, alpha] = self.components;
-
+                let [hue, saturation, lightness, _] = self.components;
                 let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
-                let h_prime = hue * 6.0;
-                let x = chroma * (1.0 - (h_prime % 2.0 - 1.0).abs());
-
-                let (r1, b1, g1) = {
-                    if      h_prime <  1.0 { (chroma, x, 0.0) }
-                    else if h_prime <  2.0 { (x, chroma, 0.0) }
-                    else if h_prime <  3.0 { (0.0, chroma, x) }
-                    else if h_prime <  4.0 { (0.0, x, chroma) }
-                    else if h_prime <  5.0 { (x, 0.0, chroma) }
-                    else if h_prime <= 6.0 { (chroma, 0.0, x) }
-                    else { unreachable!() }
-                };
-                let m = lightness - (chroma / 2.0);
-                Color::from_rgba(r1 + m, g1 + m, b1 + m, alpha)
+                self.converted(hue_to_rgb(hue, chroma, lightness - (chroma / 2.0)), ColorSpace::RGB)
             }
             ColorSpace::HSV | ColorSpace::HSVA => {
-                let [hue, saturation, value, alpha] = self.components;
-
+                let [hue, saturation, value, _] = self.components;
                 let chroma = value * saturation;
-                let h_prime = hue * 6.0;
-                let x = chroma * (1.0 - (h_prime % 2.0 - 1.0).abs());
-
-                let (r1, b1, g1) = {
-                    if      h_prime <  1.0 { (chroma, x, 0.0) }
-                    else if h_prime <  2.0 { (x, chroma, 0.0) }
-                    else if h_prime <  3.0 { (0.0, chroma, x) }
-                    else if h_prime <  4.0 { (0.0, x, chroma) }
-                    else if h_prime <  5.0 { (x, 0.0, chroma) }
-                    else if h_prime <= 6.0 { (chroma, 0.0, x) }
-                    else { unreachable!() }
-                };
-                let m = value - chroma;
-                Color::from_rgba(r1 + m, g1 + m, b1 + m, alpha)
+                self.converted(hue_to_rgb(hue, chroma, value - chroma), ColorSpace::RGB)
             }
-            ColorSpace::Lab | ColorSpace::LabA => {
-                todo!()
+            ColorSpace::XYZ | ColorSpace::XYZA => {
+                let linear = mul_mat3(&XYZ_TO_LINEAR, *self.components_3());
+                self.converted(linear.map(linear_to_srgb), ColorSpace::RGB)
+            }
+            ColorSpace::Lab | ColorSpace::LabA => self.to_xyz().to_rgb(),
+            ColorSpace::Oklab | ColorSpace::OklabA => {
+                let [l, a, b] = self.components_3().map(|c| c as f64);
+                let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+                let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+                let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+                let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+                let linear = [
+                     4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+                    -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+                    -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+                ];
+                self.converted(linear.map(|c| linear_to_srgb(c as f32)), ColorSpace::RGB)
             }
         }
     }
 
     pub fn to_hsv(&self) -> Color {
         match self.space {
-            ColorSpace::HSL | ColorSpace::HSLA => { todo!() }
             ColorSpace::HSV | ColorSpace::HSVA => { *self }
+            ColorSpace::HSL | ColorSpace::HSLA => {
+                let [hue, s_hsl, lightness, _] = self.components;
+                let value = lightness + s_hsl * lightness.min(1.0 - lightness);
+                let s_hsv = if value < f32::EPSILON { 0.0 } else { 2.0 * (1.0 - lightness / value) };
+                self.converted([hue, s_hsv, value], ColorSpace::HSV)
+            }
             ColorSpace::RGB | ColorSpace::RGBA => {
-                let [r, g, b, alpha] = self.components;
+                let [r, g, b, _] = self.components;
 
                 let max = slice_max(&[r, g, b]);
                 let min = slice_min(&[r, g, b]);
                 let chroma = max - min;
 
                 let value = max;
-
-                let hue = if chroma < f32::EPSILON { 0.0 } else {
-                    if      max == r { (g - b) / chroma       }
-                    else if max == g { (b - r) / chroma + 2.0 }
-                    else if max == b { (r - g) / chroma + 4.0 }
-                    else { unreachable!() }
-                };
-
+                let hue = rgb_hue(r, g, b, max, chroma);
                 let saturation = if value < f32::EPSILON { 0.0 } else { chroma / value };
 
-                Color::from_hsva(hue / 6.0, saturation, value, alpha)
+                self.converted([hue, saturation, value], ColorSpace::HSV)
             }
-            ColorSpace::Lab | ColorSpace::LabA => { todo!() }
+            _ => self.to_rgb().to_hsv(),
         }
     }
 
@@ -171,45 +325,124 @@ impl Color {
         match self.space {
             ColorSpace::HSL | ColorSpace::HSLA => { *self }
             ColorSpace::HSV | ColorSpace::HSVA => {
-                let [hue, s_hsv, value, alpha] = self.components;
+                let [hue, s_hsv, value, _] = self.components;
                 let lightness = value * (1.0 - (s_hsv / 2.0));
                 let s_hsl = if lightness.min(1.0 - lightness) < f32::EPSILON { 0.0 }
                 else { (value - lightness) / (lightness.min(1.0 - lightness)) };
-                Color::from_hsla(hue, s_hsl, lightness, alpha)
+                self.converted([hue, s_hsl, lightness], ColorSpace::HSL)
             }
             ColorSpace::RGB | ColorSpace::RGBA => {
-                let [r, g, b, alpha] = self.components;
+                let [r, g, b, _] = self.components;
 
                 let max = slice_max(&[r, g, b]);
                 let min = slice_min(&[r, g, b]);
                 let chroma = max - min;
 
-                let lightness = (max - min) / 2.0;
-
-                let hue = if chroma < f32::EPSILON { 0.0 } else {
-                    if      max == r { (g - b) / chroma       }
-                    else if max == g { (b - r) / chroma + 2.0 }
-                    else if max == b { (r - g) / chroma + 4.0 }
-                    else { unreachable!() }
-                };
-
+                let lightness = (max + min) / 2.0;
+                let hue = rgb_hue(r, g, b, max, chroma);
                 let saturation = if lightness.min(1.0 - lightness) < f32::EPSILON { 0.0 }
                                  else { (max - lightness) / lightness.min(1.0 - lightness) };
 
-                Color::from_hsla(hue / 6.0, saturation, lightness, alpha)
+                self.converted([hue, saturation, lightness], ColorSpace::HSL)
             }
+            _ => self.to_rgb().to_hsl(),
+        }
+    }
+
+    pub fn to_xyz(&self) -> Color {
+        match self.space {
+            ColorSpace::XYZ | ColorSpace::XYZA => { *self }
             ColorSpace::Lab | ColorSpace::LabA => {
-                todo!()
+                let [l, a, b, _] = self.components;
+                let fy = (l + 16.0) / 116.0;
+                let fx = fy + a / 500.0;
+                let fz = fy - b / 200.0;
+                let finv = |f: f32| if f * f * f > LAB_E { f * f * f } else { (116.0 * f - 16.0) / LAB_K };
+                let yr = if l > LAB_K * LAB_E { fy * fy * fy } else { l / LAB_K };
+                self.converted([finv(fx) * D65_WHITE[0], yr * D65_WHITE[1], finv(fz) * D65_WHITE[2]], ColorSpace::XYZ)
             }
+            _ => self.converted(mul_mat3(&LINEAR_TO_XYZ, self.linear_rgb()), ColorSpace::XYZ),
         }
     }
 
     pub fn to_lab(&self) -> Color {
-        todo!()
+        match self.space {
+            ColorSpace::Lab | ColorSpace::LabA => { *self }
+            _ => {
+                let [x, y, z] = *self.to_xyz().components_3();
+                let f = |t: f32| if t > LAB_E { t.cbrt() } else { (LAB_K * t + 16.0) / 116.0 };
+                let (fx, fy, fz) = (f(x / D65_WHITE[0]), f(y / D65_WHITE[1]), f(z / D65_WHITE[2]));
+                self.converted([116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)], ColorSpace::Lab)
+            }
+        }
+    }
+
+    pub fn to_oklab(&self) -> Color {
+        match self.space {
+            ColorSpace::Oklab | ColorSpace::OklabA => { *self }
+            _ => {
+                let [r, g, b] = self.linear_rgb().map(|c| c as f64);
+                let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+                let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+                let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+                self.converted([
+                    0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+                    1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+                    0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+                ].map(|c| c as f32), ColorSpace::Oklab)
+            }
+        }
+    }
+
+    /// CIE76 color difference, the distance between the two colors in Lab.
+    pub fn delta_e76(&self, other: &Color) -> f32 {
+        let [l1, a1, b1] = *self.to_lab().components_3();
+        let [l2, a2, b2] = *other.to_lab().components_3();
+        ((l2 - l1).powi(2) + (a2 - a1).powi(2) + (b2 - b1).powi(2)).sqrt()
     }
 
-    // TODO: space conversions
-    // TODO: linear <-> srgb conversions
+    /// CIEDE2000 color difference, which corrects CIE76 to better match perceived differences.
+    pub fn delta_e2000(&self, other: &Color) -> f32 {
+        let [l1, a1, b1] = self.to_lab().components_3().map(|c| c as f64);
+        let [l2, a2, b2] = other.to_lab().components_3().map(|c| c as f64);
+        let pow25_7 = 25f64.powi(7);
+
+        let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+        let g = 0.5 * (1.0 - (c_bar.powi(7) / (c_bar.powi(7) + pow25_7)).sqrt());
+        let (a1p, a2p) = ((1.0 + g) * a1, (1.0 + g) * a2);
+        let (c1p, c2p) = (a1p.hypot(b1), a2p.hypot(b2));
+        let hue = |b: f64, a: f64| if b == 0.0 && a == 0.0 { 0.0 } else { b.atan2(a).to_degrees().rem_euclid(360.0) };
+        let (h1p, h2p) = (hue(b1, a1p), hue(b2, a2p));
+
+        let delta_l = l2 - l1;
+        let delta_c = c2p - c1p;
+        let delta_h = if c1p * c2p == 0.0 { 0.0 } else {
+            let dh = h2p - h1p;
+            if dh > 180.0 { dh - 360.0 } else if dh < -180.0 { dh + 360.0 } else { dh }
+        };
+        let delta_h = 2.0 * (c1p * c2p).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+        let l_bar = (l1 + l2) / 2.0;
+        let c_bar_p = (c1p + c2p) / 2.0;
+        let h_bar = if c1p * c2p == 0.0 { h1p + h2p }
+            else if (h1p - h2p).abs() <= 180.0 { (h1p + h2p) / 2.0 }
+            else if h1p + h2p < 360.0 { (h1p + h2p + 360.0) / 2.0 }
+            else { (h1p + h2p - 360.0) / 2.0 };
+
+        let t = 1.0 - 0.17 * (h_bar - 30.0).to_radians().cos()
+                    + 0.24 * (2.0 * h_bar).to_radians().cos()
+                    + 0.32 * (3.0 * h_bar + 6.0).to_radians().cos()
+                    - 0.20 * (4.0 * h_bar - 63.0).to_radians().cos();
+        let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+        let r_c = 2.0 * (c_bar_p.powi(7) / (c_bar_p.powi(7) + pow25_7)).sqrt();
+        let s_l = 1.0 + (0.015 * (l_bar - 50.0).powi(2)) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+        let s_c = 1.0 + 0.045 * c_bar_p;
+        let s_h = 1.0 + 0.015 * c_bar_p * t;
+        let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+        let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+        (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+    }
 }
 
 impl Index<usize> for Color {
@@ -218,7 +451,7 @@ impl Index<usize> for Color {
     fn index(&self, index: usize) -> &Self::Output {
         match index {
             0 | 1 | 2 | 3 => {
-                &self[index]
+                &self.components[index]
             }
             _ => panic!("Index {} out of bounds for {:?}", index, self)
         }
@@ -229,7 +462,7 @@ impl IndexMut<usize> for Color {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         match index {
             0 | 1 | 2 | 3 => {
-                &mut self[index]
+                &mut self.components[index]
             }
             _ => panic!("Index {} out of bounds for {:?}", index, self)
         }
@@ -237,11 +470,11 @@ impl IndexMut<usize> for Color {
 }
 
 impl Index<Range<usize>> for Color {
-    type Output = f32;
+    type Output = [f32];
 
     fn index(&self, index: Range<usize>) -> &Self::Output {
-        if index.start.max(index.end) <= 3 {
-            &self[index]
+        if index.start <= index.end && index.end <= 4 {
+            &self.components[index]
         }
         else {
             panic!("Index {}..{} out of bounds for {:?}", index.start, index.end, self);
@@ -251,11 +484,121 @@ impl Index<Range<usize>> for Color {
 
 impl IndexMut<Range<usize>> for Color {
     fn index_mut(&mut self, index: Range<usize>) -> &mut Self::Output {
-        if index.start.max(index.end) <= 3 {
-            &mut self[index]
+        if index.start <= index.end && index.end <= 4 {
+            &mut self.components[index]
         }
         else {
             panic!("Index {}..{} out of bounds for {:?}", index.start, index.end, self);
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::{Color, ColorSpace, HexColorError, srgb_to_linear, linear_to_srgb};
+
+    macro_rules! assert_close {
+        ($a:expr, $b:expr, $tolerance:expr) => {{
+            let (a, b) = ($a, $b);
+            assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() <= $tolerance), "{:?} != {:?}", a, b);
+        }}
+    }
+
+    #[test]
+    fn test_rgb_hsv() {
+        assert_close!(*Color::from_rgb(1.0, 0.0, 0.0).to_hsv().components_3(), [0.0, 1.0, 1.0], 1e-6);
+        assert_close!(*Color::from_rgb(0.0, 0.5, 0.0).to_hsv().components_3(), [1.0 / 3.0, 1.0, 0.5], 1e-6);
+        // hues between magenta and red used to come out negative
+        assert_close!(*Color::from_rgb(1.0, 0.0, 0.5).to_hsv().components_3(), [11.0 / 12.0, 1.0, 1.0], 1e-6);
+        assert_close!(*Color::from_hsv(2.0 / 3.0, 0.5, 0.8).to_rgb().components_3(), [0.4, 0.4, 0.8], 1e-6);
+    }
+
+    #[test]
+    fn test_rgb_hsl() {
+        assert_close!(*Color::from_rgb(0.2, 0.4, 0.6).to_hsl().components_3(), [7.0 / 12.0, 0.5, 0.4], 1e-6);
+        assert_close!(*Color::from_rgb(1.0, 1.0, 1.0).to_hsl().components_3(), [0.0, 0.0, 1.0], 1e-6);
+        // green and blue used to be swapped
+        assert_close!(*Color::from_hsl(1.0 / 3.0, 1.0, 0.25).to_rgb().components_3(), [0.0, 0.5, 0.0], 1e-6);
+        assert_close!(*Color::from_hsl(7.0 / 12.0, 0.5, 0.4).to_rgb().components_3(), [0.2, 0.4, 0.6], 1e-6);
+    }
+
+    #[test]
+    fn test_hsl_hsv() {
+        let hsl = Color::from_hsl(0.1, 0.6, 0.3);
+        assert_close!(*hsl.to_hsv().components_3(), *hsl.to_rgb().to_hsv().components_3(), 1e-6);
+        assert_close!(*hsl.to_hsv().to_hsl().components_3(), *hsl.components_3(), 1e-6);
+    }
+
+    #[test]
+    fn test_alpha_preserved() {
+        let rgba = Color::from_rgba(0.1, 0.2, 0.3, 0.4);
+        for space in [ColorSpace::HSL, ColorSpace::HSV, ColorSpace::Lab, ColorSpace::XYZ, ColorSpace::Oklab] {
+            let mut converted = rgba;
+            converted.convert(space);
+            assert_eq!(converted.check_alpha(), Some(0.4));
+            assert_close!(*converted.to_rgb().components_4(), *rgba.components_4(), 1e-4);
+        }
+        assert_eq!(Color::from_rgb(0.1, 0.2, 0.3).to_lab().space(), ColorSpace::Lab);
+    }
+
+    #[test]
+    fn test_srgb_linear() {
+        assert_close!([srgb_to_linear(0.5)], [0.214041], 1e-5);
+        assert_close!([linear_to_srgb(0.214041)], [0.5], 1e-5);
+        assert_close!([srgb_to_linear(0.02)], [0.02 / 12.92], 1e-7);
+        assert_close!(Color::from_linear_rgb(0.0, 0.214041, 1.0).linear_rgb(), [0.0, 0.214041, 1.0], 1e-5);
+    }
+
+    #[test]
+    fn test_xyz_lab() {
+        assert_close!(*Color::white(ColorSpace::RGB).to_xyz().components_3(), *Color::white(ColorSpace::XYZ).components_3(), 1e-4);
+        assert_close!(*Color::white(ColorSpace::RGB).to_lab().components_3(), [100.0, 0.0, 0.0], 1e-2);
+        assert_close!(*Color::from_rgb(1.0, 0.0, 0.0).to_lab().components_3(), [53.24, 80.09, 67.20], 1e-2);
+        assert_close!(*Color::from_lab(32.3, 79.19, -107.86).to_rgb().components_3(), [0.0, 0.0, 1.0], 1e-3);
+    }
+
+    #[test]
+    fn test_oklab() {
+        assert_close!(*Color::white(ColorSpace::RGB).to_oklab().components_3(), [1.0, 0.0, 0.0], 1e-4);
+        assert_close!(*Color::from_rgb(1.0, 0.0, 0.0).to_oklab().components_3(), [0.62796, 0.22486, 0.12585], 1e-4);
+        let color = Color::from_rgb(0.3, 0.7, 0.1);
+        assert_close!(*color.to_oklab().to_rgb().components_3(), *color.components_3(), 1e-4);
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(Color::from_hex("#FF8000"), Ok(Color::from_rgb(1.0, 128.0 / 255.0, 0.0)));
+        assert_eq!(Color::from_hex("0f0"), Ok(Color::from_rgb(0.0, 1.0, 0.0)));
+        assert_eq!(Color::from_hex("#00000080"), Ok(Color::from_rgba(0.0, 0.0, 0.0, 128.0 / 255.0)));
+        assert_eq!(Color::from_hex("#12345"), Err(HexColorError::Length(5)));
+        assert_eq!(Color::from_hex("#12345G"), Err(HexColorError::InvalidDigit('G')));
+        assert_eq!(Color::from_rgb(1.0, 128.0 / 255.0, 0.0).to_hex(), "#FF8000");
+        assert_eq!(Color::from_rgba(1.0, 1.0, 1.0, 0.0).to_hex(), "#FFFFFF00");
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0).to_hex(), "#FF0000");
+    }
+
+    #[test]
+    fn test_delta_e() {
+        assert_close!([Color::from_lab(50.0, 0.0, 0.0).delta_e76(&Color::from_lab(53.0, 4.0, 0.0))], [5.0], 1e-5);
+        // reference pairs from Sharma, Wu and Dalal's CIEDE2000 test data
+        let pairs = [
+            ((50.0, 2.6772, -79.7751), (50.0, 0.0, -82.7485), 2.0425),
+            ((50.0, 0.0, 0.0), (50.0, -1.0, 2.0), 2.3669),
+            ((50.0, 2.5, 0.0), (73.0, 25.0, -18.0), 27.1492),
+            ((2.0776, 0.0795, -1.1350), (0.9033, -0.0636, -0.5514), 0.9082),
+        ];
+        for ((l1, a1, b1), (l2, a2, b2), expected) in pairs {
+            let difference = Color::from_lab(l1, a1, b1).delta_e2000(&Color::from_lab(l2, a2, b2));
+            assert_close!([difference], [expected], 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_index() {
+        let mut color = Color::from_rgba(0.1, 0.2, 0.3, 0.4);
+        assert_eq!(color[2], 0.3);
+        color[3] = 1.0;
+        assert_eq!(&color[1..4], &[0.2, 0.3, 1.0]);
+    }
+}